@@ -0,0 +1,53 @@
+/*
+ * The C API of rustlox, see `src/ffi.rs` for the ownership rules and
+ * `src/plugin.rs` for how values and native functions cross the boundary.
+ * Link against the `cdylib` that `cargo build --release` makes, e.g.
+ * `cc app.c -Iinclude -Ltarget/release -lrustlox`.
+ */
+
+#ifndef RUSTLOX_H
+#define RUSTLOX_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LOX_NIL 0
+#define LOX_BOOL 1
+#define LOX_NUMBER 2
+#define LOX_STRING 3
+
+typedef struct LoxValue {
+    /* One of the tags above */
+    uint8_t tag;
+    /* LOX_NUMBER's value, and LOX_BOOL's as 0 or 1 */
+    double number;
+    /* LOX_STRING's bytes, UTF-8 and not NUL-terminated */
+    const uint8_t *string;
+    size_t len;
+} LoxValue;
+
+/* Returning false fails the call with the string in `*result` as the
+ * message */
+typedef bool (*LoxNativeFn)(const LoxValue *args, size_t argc, LoxValue *result);
+
+typedef struct Lox Lox;
+
+Lox *lox_new(void);
+void lox_free(Lox *lox);
+
+/* On success `*result` is the value of the top-level `return`, nil without
+ * one. On failure it's the diagnostics, as a string. */
+bool lox_run(Lox *lox, const char *source, LoxValue *result);
+bool lox_get_global(Lox *lox, const char *name, LoxValue *result);
+void lox_register_native(Lox *lox, const char *name, size_t arity, LoxNativeFn function);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
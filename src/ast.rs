@@ -1,42 +1,295 @@
-use crate::{expr::Expr, token::Literal};
+use std::borrow::Cow;
 
-pub fn print(expr: Expr) -> String {
-    visit_expr(&expr)
+use crate::{
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    token::{Literal, Token},
+    util::escape_bytes,
+    visitor::{ExprVisitor, StmtVisitor},
+};
+
+// Bumped whenever the JSON below changes in a way older readers can't handle
+pub const JSON_VERSION: u32 = 2;
+
+// The JSON document of a program, e.g.
+// `{ "version": 2, "statements": [{ "type": "Print", ... }] }`. Enum variants
+// are named by a `type` field, tokens and nodes (`span`) keep their position
+// in the source.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Document<'a> {
+    version: u32,
+    statements: Cow<'a, [Option<Stmt>]>,
+}
+
+pub fn to_json(statements: &[Option<Stmt>]) -> String {
+    let document = Document {
+        version: JSON_VERSION,
+        statements: Cow::Borrowed(statements),
+    };
+
+    // Only fails for non-string map keys, which the AST doesn't have
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+// The reverse of `to_json`. The statements still have to be resolved.
+pub fn from_json(json: &str) -> Result<Vec<Option<Stmt>>, String> {
+    let document: Document = serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+    match document.version {
+        JSON_VERSION => Ok(document.statements.into_owned()),
+        version => Err(format!("Unsupported AST version {}.", version)),
+    }
+}
+
+// Prints an expression in prefix notation, e.g. `(* (- 1) (group (+ 2 3)))`
+pub fn print(expr: &Expr) -> String {
+    expr.accept(&mut AstPrinter::default())
+}
+
+// Prints a program the same way, for `--ast`. Each statement starts a line,
+// the statements nested in it (e.g. a function's body) follow on lines of
+// their own, indented.
+pub fn print_program(statements: &[Option<Stmt>]) -> String {
+    statements
+        .iter()
+        .flatten()
+        .map(|stmt| stmt.accept(&mut AstPrinter::default()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[derive(Default)]
+pub struct AstPrinter {
+    // How deep the statement being printed is nested
+    depth: usize,
+}
+
+impl AstPrinter {
+    // `lines` one level deeper than the current statement, each on a line of
+    // its own
+    fn nested<'a>(&mut self, lines: impl IntoIterator<Item = Line<'a>>) -> String {
+        self.depth += 1;
+
+        let mut res = String::new();
+        for line in lines {
+            res.push('\n');
+            res.push_str(&"  ".repeat(self.depth));
+            match line {
+                Line::Stmt(stmt) => res.push_str(&stmt.accept(self)),
+                Line::Expr(name, expr) => res.push_str(&self.parenthesize(name, &[expr])),
+            }
+        }
+
+        self.depth -= 1;
+        res
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        let mut res = String::new();
+
+        res.push('(');
+        res.push_str(name);
+
+        for expr in exprs {
+            res.push(' ');
+            res.push_str(&expr.accept(self));
+        }
+
+        res.push(')');
+
+        res
+    }
 }
 
-fn visit_expr(expr: &Expr) -> String {
-    match expr {
-        // Base case
-        Expr::Literal { value } => match value {
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_assign(&mut self, _id: ExprId, name: &Token, value: &Expr) -> String {
+        format!("(= {} {})", name.lexeme, value.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Box<Expr>]) -> String {
+        let exprs: Vec<&Expr> = std::iter::once(callee)
+            .chain(arguments.iter().map(|arg| &**arg))
+            .collect();
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_get(&mut self, _id: ExprId, object: &Expr, name: &Token) -> String {
+        format!("(. {} {})", object.accept(self), name.lexeme)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> String {
+        self.parenthesize("group", &[expression])
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+        self.parenthesize("[]", &[object, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> String {
+        self.parenthesize("[]=", &[object, index, value])
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Box<Expr>]) -> String {
+        let exprs: Vec<&Expr> = elements.iter().map(|element| &**element).collect();
+        self.parenthesize("list", &exprs)
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        match value {
             Literal::None => "nil".to_string(),
-            Literal::String(val) => val.to_string(),
+            // Quoted, to tell them apart from variables
+            Literal::String(val) => format!("\"{}\"", val),
+            Literal::Bytes(val) => escape_bytes(val),
             Literal::Boolean(val) => val.to_string(),
             Literal::Number(val) => val.to_string(),
-        },
-        // Recursion
-        Expr::Binary {
-            left,
-            operator,
-            right,
-        } => parenthesize(&operator.lexeme, &[left, right]),
-        Expr::Grouping { expression } => parenthesize("group", &[expression]),
-        Expr::Unary { operator, right } => parenthesize(&operator.lexeme, &[right]),
-        _ => "".to_string(),
+        }
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Box<Expr>, Box<Expr>)]) -> String {
+        let exprs: Vec<&Expr> = entries
+            .iter()
+            .flat_map(|(key, value)| [&**key, &**value])
+            .collect();
+        self.parenthesize("map", &exprs)
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> String {
+        format!(
+            "(.= {} {} {})",
+            object.accept(self),
+            name.lexeme,
+            value.accept(self)
+        )
     }
+
+    fn visit_super(&mut self, _id: ExprId, _keyword: &Token, method: &Token) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_this(&mut self, _id: ExprId, _keyword: &Token) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.lexeme, &[right])
+    }
+
+    fn visit_variable(&mut self, _id: ExprId, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+}
+
+// What `AstPrinter::nested` prints on a line: a statement, or an expression
+// that belongs to the statement above (e.g. a `for` loop's increment)
+enum Line<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'static str, &'a Expr),
+}
+
+fn lines_of(statements: &[Option<Box<Stmt>>]) -> impl Iterator<Item = Line<'_>> {
+    statements.iter().flatten().map(|stmt| Line::Stmt(stmt))
 }
 
-fn parenthesize(name: &str, exprs: &[&Expr]) -> String {
-    let mut res = String::new();
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) -> String {
+        format!("(block{})", self.nested(lines_of(statements)))
+    }
+
+    fn visit_break(&mut self, _keyword: &Token) -> String {
+        "(break)".to_string()
+    }
 
-    res.push('(');
-    res.push_str(name);
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Box<Stmt>],
+    ) -> String {
+        let superclass: String = match superclass {
+            Some(superclass) => format!(" < {}", superclass.accept(self)),
+            None => "".to_string(),
+        };
+        let methods = self.nested(methods.iter().map(|method| Line::Stmt(method)));
+        format!("(class {}{}{})", name.lexeme, superclass, methods)
+    }
 
-    for expr in exprs {
-        res.push(' ');
-        res.push_str(&visit_expr(expr));
+    fn visit_continue(&mut self, _keyword: &Token) -> String {
+        "(continue)".to_string()
     }
 
-    res.push(')');
+    fn visit_expression(&mut self, expression: &Expr) -> String {
+        self.parenthesize(";", &[expression])
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
+    ) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        format!(
+            "(fn {} ({}){})",
+            name.lexeme,
+            params.join(" "),
+            self.nested(lines_of(body))
+        )
+    }
 
-    res
+    fn visit_if(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> String {
+        let condition: String = condition.accept(self);
+        let branches = self.nested(
+            std::iter::once(then_branch)
+                .chain(else_branch)
+                .map(Line::Stmt),
+        );
+        format!("(if {}{})", condition, branches)
+    }
+
+    fn visit_print(&mut self, keyword: &Token, expression: &Expr, _stderr: bool) -> String {
+        self.parenthesize(&keyword.lexeme, &[expression])
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> String {
+        let value: Vec<&Expr> = value.into_iter().collect();
+        self.parenthesize("return", &value)
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> String {
+        let initializer: Vec<&Expr> = initializer.into_iter().collect();
+        self.parenthesize(&format!("var {}", name.lexeme), &initializer)
+    }
+
+    fn visit_while(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> String {
+        let condition: String = condition.accept(self);
+        let lines = std::iter::once(Line::Stmt(body))
+            .chain(increment.map(|increment| Line::Expr("increment", increment)));
+        format!("(while {}{})", condition, self.nested(lines))
+    }
 }
@@ -0,0 +1,42 @@
+// Natives backed by futures, with the `async` feature, for scripts that
+// orchestrate a host's async services:
+//
+//     lox.define_async_native("fetch", 1, |arguments| async move {
+//         let url: String = String::from_lox(&arguments[0])?;
+//         Ok(client.get(&url).await.to_lox())
+//     });
+//     lox.run_async(source).await?;
+//
+// The interpreter can't suspend in the middle of a script, so `run_async`
+// runs it in `tokio::task::block_in_place` and an async native blocks on
+// its future there. That thread is blocked until the script ends, awaiting
+// included, and the runtime has to hand its other tasks to another worker
+// thread, which is why it must be the multi-threaded runtime (`run_async`
+// fails on a current-thread one).
+
+use std::future::Future;
+
+use crate::{
+    error::LoxError,
+    native::{self, NativeFn},
+    object::Object,
+    sync::MaybeSend,
+};
+
+// The native running `body`'s futures, see `Interpreter::define_async_native`
+pub fn native<F, Fut>(name: &str, body: F) -> impl NativeFn
+where
+    F: Fn(Vec<Object>) -> Fut + MaybeSend + 'static,
+    Fut: Future<Output = Result<Object, LoxError>> + 'static,
+{
+    let name: String = name.to_owned();
+
+    move |interpreter, arguments| match interpreter.runtime.clone() {
+        Some(runtime) => runtime.block_on(body(arguments.to_vec())),
+        // Blocking on it in a runtime's task would panic
+        None => Err(native::error(format!(
+            "{}() is async, it can only be called by a script run with run_async().",
+            name
+        ))),
+    }
+}
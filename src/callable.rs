@@ -1,77 +1,192 @@
 use crate::{
+    compiler::Compiled,
     environment::{self, Environment},
     error::LoxError,
     interpreter::Interpreter,
+    memory,
+    native::{self, NativeFn, NativeMethod},
     object::Object,
+    plugin::{self, PluginFn},
     stmt::Stmt,
+    symbol::Symbol,
+    sync::{Rc, RefCell},
     token::Token,
 };
 use core::fmt;
-use std::{cell::RefCell, rc::Rc};
 
 #[derive(Debug, Clone)]
 pub enum LoxCallable {
     Native {
+        // The minimum number of arguments for variadic natives
         arity: usize,
-        body: Box<fn(&Vec<Object>) -> Object>,
+        variadic: bool,
+        body: Rc<dyn NativeFn>,
+    },
+    // Built-in method of a primitive value (string, number, list),
+    // already bound to its receiver.
+    Method {
+        name: String,
+        arity: usize,
+        receiver: Box<Object>,
+        body: NativeMethod,
     },
     User {
-        name: Token,
-        params: Vec<Token>,
-        body: Vec<Option<Box<Stmt>>>,
+        declaration: Rc<Declaration>,
         closure: Rc<RefCell<Environment>>,
         is_initializer: bool,
     },
+    // A function from a shared library, see `plugin`
+    Plugin {
+        name: String,
+        arity: usize,
+        function: PluginFn,
+    },
+}
+
+// A user function as declared, shared by every copy of the function (e.g.
+// each method bound to an instance) so copying one is cheap
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Option<Box<Stmt>>>,
+    // The body compiled to closures, run instead of `body` by the closure
+    // backend, see `compiler`
+    pub compiled: Option<Compiled>,
+}
+
+impl Declaration {
+    pub fn new(
+        name: &Token,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
+        compiled: Option<Compiled>,
+    ) -> Rc<Self> {
+        Rc::new(Declaration {
+            name: name.clone(),
+            params: params.to_vec(),
+            body: body.to_vec(),
+            compiled,
+        })
+    }
+}
+
+// Lox calls recurse on the Rust stack, a few (large, in debug builds) frames
+// each, so `max_call_depth` calls don't fit the stack of an ordinary thread.
+// A call finding less than `RED_ZONE` left runs on a new `STACK_SEGMENT`
+// allocated on the heap instead, whatever thread the interpreter runs on.
+#[cfg(not(target_arch = "wasm32"))]
+const RED_ZONE: usize = 256 * 1024;
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn grow_stack<R>(body: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, body)
+}
+
+// WebAssembly's stack can't be switched, overflowing it traps
+#[cfg(target_arch = "wasm32")]
+fn grow_stack<R>(body: impl FnOnce() -> R) -> R {
+    body()
 }
 
 impl LoxCallable {
     pub fn arity(&self) -> usize {
         match self {
             LoxCallable::Native { arity, .. } => *arity,
-            LoxCallable::User { params, .. } => params.len(),
+            LoxCallable::Method { arity, .. } => *arity,
+            LoxCallable::User { declaration, .. } => declaration.params.len(),
+            LoxCallable::Plugin { arity, .. } => *arity,
         }
     }
 
-    pub fn call(&self, interpreter: &mut Interpreter, arguments: &Vec<Object>) -> Object {
+    pub fn is_variadic(&self) -> bool {
+        matches!(self, LoxCallable::Native { variadic: true, .. })
+    }
+
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: &[Object],
+    ) -> Result<Object, LoxError> {
         match self {
-            LoxCallable::Native { body, .. } => body(arguments),
+            LoxCallable::Native { body, .. } => {
+                let value: Object = body(interpreter, arguments)?;
+                interpreter.charge(&value);
+                Ok(value)
+            }
+            LoxCallable::Method { receiver, body, .. } => {
+                // Methods like `push` grow their receiver rather than make a value
+                let before: Option<usize> = interpreter
+                    .budget
+                    .max_memory
+                    .map(|_| memory::size_of_value(receiver));
+                let value: Object = body(interpreter, receiver, arguments)?;
+                if let Some(before) = before {
+                    let grown: usize = memory::size_of_value(receiver).saturating_sub(before);
+                    interpreter.budget.allocate(grown);
+                }
+                interpreter.charge(&value);
+                Ok(value)
+            }
+            LoxCallable::Plugin { name, function, .. } => {
+                let value: Object = plugin::call(name, *function, arguments)?;
+                interpreter.charge(&value);
+                Ok(value)
+            }
             LoxCallable::User {
-                name: _,
-                params,
-                body,
+                declaration,
                 closure,
                 is_initializer,
             } => {
-                let env: Rc<RefCell<Environment>> =
-                    Rc::new(RefCell::new(Environment::new(Some(closure.clone()))));
-
-                for i in 0..params.len() {
-                    env.borrow_mut().define(
-                        params.get(i).unwrap().lexeme.clone(),
-                        arguments.get(i).unwrap().clone(),
-                    );
+                if interpreter.call_depth >= interpreter.max_call_depth {
+                    return Err(native::error(format!(
+                        "Stack overflow: max call depth {} exceeded.",
+                        interpreter.max_call_depth
+                    )));
+                }
+
+                let env: Rc<RefCell<Environment>> = interpreter.env_pool.acquire(closure.clone());
+
+                for (param, argument) in declaration.params.iter().zip(arguments) {
+                    env.borrow_mut().define(param.symbol, argument.clone());
                 }
 
-                let ret = interpreter.execute_block(body, env.clone());
+                interpreter.call_depth += 1;
+                if let Some(profiler) = interpreter.profiler.as_mut() {
+                    profiler.enter(&declaration.name);
+                }
+                let ret = grow_stack(|| match &declaration.compiled {
+                    Some(compiled) => compiled.run(interpreter, env.clone()),
+                    None => interpreter.execute_block(&declaration.body, env.clone()),
+                });
+                if let Some(profiler) = interpreter.profiler.as_mut() {
+                    profiler.exit();
+                }
+                interpreter.call_depth -= 1;
+                interpreter.env_pool.release(env);
 
+                // An initializer returns `this`, slot 0 of its bound closure
                 let ret_val: Object = match ret {
                     Err(LoxError::Return { value }) => {
                         if *is_initializer {
-                            environment::get_at(closure.clone(), 0, "this".to_owned()).unwrap()
+                            environment::get_at(closure, 0, 0)
                         } else {
                             value
                         }
                     }
-                    _ => {
+                    Err(error) => return Err(error),
+                    Ok(()) => {
                         if *is_initializer {
-                            environment::get_at(closure.clone(), 0, "this".to_owned()).unwrap()
+                            environment::get_at(closure, 0, 0)
                         } else {
                             Object::None
                         }
                     }
                 };
 
-                ret_val
+                Ok(ret_val)
             }
         }
     }
@@ -79,23 +194,23 @@ impl LoxCallable {
     pub fn bind(&self, instance: Object) -> LoxCallable {
         match self {
             LoxCallable::User {
-                name,
-                params,
-                body,
+                declaration,
                 closure,
                 is_initializer,
             } => {
                 let environment = Rc::new(RefCell::new(Environment::new(Some(closure.clone()))));
-                environment.borrow_mut().define("this".to_owned(), instance);
+                environment.borrow_mut().define(Symbol::THIS, instance);
                 LoxCallable::User {
-                    name: name.clone(),
-                    params: params.clone(),
-                    body: body.clone(),
+                    declaration: declaration.clone(),
                     closure: environment,
                     is_initializer: *is_initializer,
                 }
             }
-            LoxCallable::Native { .. } => unreachable!(),
+            LoxCallable::Native { .. }
+            | LoxCallable::Method { .. }
+            | LoxCallable::Plugin { .. } => {
+                unreachable!()
+            }
         }
     }
 }
@@ -104,7 +219,12 @@ impl fmt::Display for LoxCallable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             LoxCallable::Native { .. } => write!(f, "<native fn>"),
-            LoxCallable::User { name, .. } => write!(f, "<fn {}>", name.lexeme),
+            LoxCallable::Method { name, .. } | LoxCallable::Plugin { name, .. } => {
+                write!(f, "<native fn {}>", name)
+            }
+            LoxCallable::User { declaration, .. } => {
+                write!(f, "<fn {}>", declaration.name.lexeme)
+            }
         }
     }
 }
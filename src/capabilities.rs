@@ -0,0 +1,100 @@
+// What natives may do outside the script, for running code that isn't
+// trusted. Everything is allowed by default, `--sandbox` (`none()`) turns
+// it all off, and a host program can pick:
+//
+//     lox.set_capabilities(Capabilities {
+//         fs_read: true,
+//         ..Capabilities::none()
+//     });
+//
+// A native checks the capability it needs before doing anything, a host's
+// natives can too (`interpreter.capabilities.check(...)`).
+
+use std::fmt;
+
+use crate::{error::LoxError, native};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    // `readFileBytes`, `loadState`
+    pub fs_read: bool,
+    // `writeFileBytes`, `saveState`
+    pub fs_write: bool,
+    // None of the built-in natives, but a host's may
+    pub network: bool,
+    // `exec`, `execStream`, `loadPlugin`
+    pub process: bool,
+    // `getenv`, `setenv`, `cwd`, `chdir`
+    pub env: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Network,
+    Process,
+    Env,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Capabilities {
+            fs_read: true,
+            fs_write: true,
+            network: true,
+            process: true,
+            env: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Capabilities {
+            fs_read: false,
+            fs_write: false,
+            network: false,
+            process: false,
+            env: false,
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::FsRead => self.fs_read,
+            Capability::FsWrite => self.fs_write,
+            Capability::Network => self.network,
+            Capability::Process => self.process,
+            Capability::Env => self.env,
+        }
+    }
+
+    // Fails the call to `function` if it needs a capability that's off
+    pub fn check(&self, capability: Capability, function: &str) -> Result<(), LoxError> {
+        match self.allows(capability) {
+            true => Ok(()),
+            false => Err(native::error(format!(
+                "Operation not permitted: {}() needs {} access.",
+                function, capability
+            ))),
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::all()
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name: &str = match self {
+            Capability::FsRead => "file read",
+            Capability::FsWrite => "file write",
+            Capability::Network => "network",
+            Capability::Process => "process",
+            Capability::Env => "environment",
+        };
+        write!(f, "{}", name)
+    }
+}
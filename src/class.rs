@@ -1,20 +1,27 @@
 use core::fmt;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::collections::HashMap;
 
-use crate::{callable::LoxCallable, error::LoxError, object::Object, token::Token};
+use crate::{
+    callable::LoxCallable,
+    error::LoxError,
+    object::Object,
+    symbol::Symbol,
+    sync::{Rc, RefCell},
+    token::Token,
+};
 
 #[derive(Clone, Debug)]
 pub struct LoxClass {
     pub name: String,
     pub superclass: Object,
-    pub methods: HashMap<String, LoxCallable>,
+    pub methods: HashMap<Symbol, LoxCallable>,
 }
 
 impl LoxClass {
     pub fn new(
         name: String,
         superclass: Object,
-        methods: HashMap<String, LoxCallable>,
+        methods: HashMap<Symbol, LoxCallable>,
     ) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(LoxClass {
             name,
@@ -23,18 +30,25 @@ impl LoxClass {
         }))
     }
 
-    pub fn find_method(&self, name: &str) -> Option<LoxCallable> {
-        if self.methods.contains_key(name) {
-            return self.methods.get(name).map(|x| x.clone());
+    pub fn find_method(&self, name: Symbol) -> Option<LoxCallable> {
+        if let Some(method) = self.methods.get(&name) {
+            return Some(method.clone());
         }
 
         match self.superclass {
-            Object::Class(ref _superclass) => {
-                _superclass.borrow().find_method(name).map(|x| x.clone())
-            }
+            Object::Class(ref _superclass) => _superclass.borrow().find_method(name),
             _ => None,
         }
     }
+
+    // The names of its methods and its superclasses' ones
+    pub fn method_names(&self) -> Vec<Symbol> {
+        let mut names: Vec<Symbol> = self.methods.keys().copied().collect();
+        if let Object::Class(superclass) = &self.superclass {
+            names.extend(superclass.borrow().method_names());
+        }
+        names
+    }
 }
 
 impl fmt::Display for LoxClass {
@@ -46,7 +60,8 @@ impl fmt::Display for LoxClass {
 #[derive(Clone, Debug)]
 pub struct LoxInstance {
     class: Rc<RefCell<LoxClass>>,
-    fields: HashMap<String, Object>,
+    fields: HashMap<Symbol, Object>,
+    frozen: bool,
 }
 
 impl LoxInstance {
@@ -54,28 +69,38 @@ impl LoxInstance {
         Rc::new(RefCell::new(LoxInstance {
             class,
             fields: HashMap::new(),
+            frozen: false,
         }))
     }
 
-    // Kinda ugly to require `instance_ref`, which is the same as `&self`.
-    // But I see no other way.
-    pub fn get(&self, name: Token, instance_ref: Rc<RefCell<Self>>) -> Result<Object, LoxError> {
-        if let Some(field) = self.fields.get(&name.lexeme) {
-            return Ok(field.clone());
-        } else if let Some(method) = self.class.borrow().find_method(&name.lexeme) {
-            return Ok(Object::Callable(
-                method.bind(Object::Instance(instance_ref.clone())),
-            ));
+    pub fn set(&mut self, name: Token, value: Object) -> Result<(), LoxError> {
+        if self.frozen {
+            return Err(LoxError::RuntimeError {
+                message: format!("Can't set '{}' on a frozen instance.", name.lexeme),
+                token: Some(name),
+            });
         }
 
-        Err(LoxError::RuntimeError {
-            message: format!("Undefined property '{}'.", name.lexeme.to_owned()),
-            token: Some(name),
-        })
+        self.fields.insert(name.symbol, value);
+        Ok(())
+    }
+
+    // Fields can no longer be set, methods may still be called
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn class(&self) -> Rc<RefCell<LoxClass>> {
+        self.class.clone()
+    }
+
+    pub fn fields(&self) -> &HashMap<Symbol, Object> {
+        &self.fields
     }
 
-    pub fn set(&mut self, name: Token, value: Object) {
-        self.fields.insert(name.lexeme, value);
+    // Like `set`, for fields created by the interpreter itself
+    pub fn define(&mut self, name: Symbol, value: Object) {
+        self.fields.insert(name, value);
     }
 }
 
@@ -0,0 +1,316 @@
+use std::{path::Path, time::Duration};
+
+use rustlox::{
+    lint::{self, Level},
+    lox,
+};
+
+pub const USAGE: &str = "\
+Usage: rustlox [options] [script|- [args...]]
+       rustlox [options] -e code [args...]
+       rustlox [options] debug script [args...]
+       rustlox [options] watch script [args...]
+       rustlox [options] lint [--deny|--warn|--allow rule]... [--config file] script
+       rustlox lsp
+       rustlox [options] emit-js script
+       rustlox minify [--rename] script
+       rustlox doc script
+       rustlox highlight script
+       rustlox [options] --check|--ast|--emit-ast-json|--emit-source script
+Options: --no-prelude --rc --opt --closures --no-warnings --strict --profile --time
+         --coverage --trace --max-depth N --max-statements N --timeout MS
+         --max-memory MB --error-format human|json --plugin lib
+         --load-state FILE --save-state FILE --sandbox
+Options that take a value also accept it as '--option=value'.";
+
+// What to do once the options are applied
+pub enum Command {
+    // The REPL
+    Prompt,
+    Script { path: String, args: Vec<String> },
+    // `-e code`, run like a script
+    Eval { code: String, args: Vec<String> },
+    // `debug script`, see `Debugger`
+    Debug { path: String, args: Vec<String> },
+    // `watch script`, run again whenever it changes, see `Lox::watch`
+    Watch { path: String, args: Vec<String> },
+    // Only compile the script, see `Lox::check`
+    Check { path: String },
+    // Only parse the script and print it
+    Ast { path: String, format: AstFormat },
+    // `lint script`, see `lint::lint`
+    Lint { path: String, config: lint::Config },
+    // `emit-js script`, the script as JavaScript, see `js::to_js`
+    EmitJs { path: String },
+    // `minify script`, see `minify::minify`
+    Minify { path: String, rename: bool },
+    // `doc script`, see `doc::to_markdown`
+    Doc { path: String },
+    // `highlight script`, see `highlight::to_html`
+    Highlight { path: String },
+    // `lsp`, a language server on stdin and stdout, see `lsp::serve`
+    Lsp,
+}
+
+// How `--ast`, `--emit-ast-json` and `--emit-source` print the syntax tree
+pub enum AstFormat {
+    // See `ast::print_program`
+    Tree,
+    // See `ast::to_json`
+    Json,
+    // Back as Lox, see `pretty::print_program`
+    Source,
+}
+
+// The command line. Options come first, everything after the script (or
+// `-e code`) belongs to the script.
+pub struct Options {
+    pub command: Command,
+    pub prelude: bool,
+    // `--rc`, scripts run `~/.loxrc` too, as the REPL does. See `Lox::run_rc`
+    pub rc: bool,
+    // `--plugin lib`, any number of times, see `plugin::load`
+    pub plugins: Vec<String>,
+    // `--load-state FILE` and `--save-state FILE`, see `state`
+    pub load_state: Option<String>,
+    pub save_state: Option<String>,
+    pub optimize: bool,
+    pub closures: bool,
+    pub hide_warnings: bool,
+    // `--strict`, see `Lox::strict`
+    pub strict: bool,
+    // `--sandbox`, natives can't touch files, processes or the environment,
+    // see `Capabilities::none`
+    pub sandbox: bool,
+    // `--error-format=json`, see `Diagnostic::to_json`
+    pub json_diagnostics: bool,
+    pub profile: bool,
+    // `--time`, see `Timings`
+    pub time: bool,
+    pub coverage: bool,
+    pub trace: bool,
+    pub max_call_depth: Option<usize>,
+    pub max_statements: Option<u64>,
+    pub time_limit: Option<Duration>,
+    // In bytes
+    pub max_memory: Option<usize>,
+}
+
+impl Options {
+    // `args` without the executable's path. Fails with the message to show
+    // above the usage.
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            command: Command::Prompt,
+            prelude: true,
+            rc: false,
+            plugins: vec![],
+            load_state: None,
+            save_state: None,
+            optimize: false,
+            closures: false,
+            hide_warnings: false,
+            strict: false,
+            sandbox: false,
+            json_diagnostics: false,
+            profile: false,
+            time: false,
+            coverage: false,
+            trace: false,
+            max_call_depth: None,
+            max_statements: None,
+            time_limit: None,
+            max_memory: None,
+        };
+        // `--check`, `--ast`, `--emit-ast-json` or `--emit-source`
+        let mut only: Option<&str> = None;
+        let mut code: Option<String> = None;
+
+        let mut args = args.iter().peekable();
+        // A lone `-` is the script, read from stdin
+        while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && *arg != lox::STDIN) {
+            let (name, mut inline): (&str, Option<String>) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+                _ => (arg, None),
+            };
+            // The value of an option that takes one, `--name=value` or `--name value`
+            let mut value = || {
+                inline
+                    .take()
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| format!("Option '{}' expects a value.", name))
+            };
+
+            match name {
+                "--no-prelude" => options.prelude = false,
+                "--rc" => options.rc = true,
+                "--opt" => options.optimize = true,
+                "--closures" => options.closures = true,
+                "--no-warnings" => options.hide_warnings = true,
+                "--strict" => options.strict = true,
+                "--sandbox" => options.sandbox = true,
+                "--profile" => options.profile = true,
+                "--time" => options.time = true,
+                "--coverage" => options.coverage = true,
+                "--trace" => options.trace = true,
+                "--check" | "--ast" | "--emit-ast-json" | "--emit-source" => only = Some(name),
+                "--plugin" => options.plugins.push(value()?),
+                "--load-state" => options.load_state = Some(value()?),
+                "--save-state" => options.save_state = Some(value()?),
+                "--error-format" => {
+                    options.json_diagnostics = match value()?.as_str() {
+                        "human" => false,
+                        "json" => true,
+                        format => return Err(format!("Unknown error format '{}'.", format)),
+                    };
+                }
+                "--max-depth" => {
+                    options.max_call_depth = Some(positive(name, &value()?)? as usize);
+                }
+                "--max-statements" => options.max_statements = Some(positive(name, &value()?)?),
+                "--timeout" => {
+                    options.time_limit = Some(Duration::from_millis(positive(name, &value()?)?));
+                }
+                "--max-memory" => {
+                    options.max_memory = Some(positive(name, &value()?)? as usize * 1024 * 1024);
+                }
+                "-e" => {
+                    code = Some(value()?);
+                    break;
+                }
+                _ => return Err(format!("Unknown option '{}'.", arg)),
+            }
+
+            if inline.is_some() {
+                return Err(format!("Option '{}' takes no value.", name));
+            }
+        }
+
+        let mut rest: Vec<String> = args.cloned().collect();
+        let script = |rest: &mut Vec<String>, after: &str| match rest.is_empty() {
+            true => Err(format!("Expect a script after '{}'.", after)),
+            false => Ok(rest.remove(0)),
+        };
+
+        options.command = match (code, only) {
+            (Some(_), Some(only)) => return Err(format!("Can't use '-e' with '{}'.", only)),
+            (Some(code), None) => Command::Eval { code, args: rest },
+            (None, Some(only)) => {
+                let path: String = script(&mut rest, only)?;
+                match only {
+                    "--check" => Command::Check { path },
+                    "--emit-ast-json" => Command::Ast {
+                        path,
+                        format: AstFormat::Json,
+                    },
+                    "--emit-source" => Command::Ast {
+                        path,
+                        format: AstFormat::Source,
+                    },
+                    _ => Command::Ast {
+                        path,
+                        format: AstFormat::Tree,
+                    },
+                }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "debug") => {
+                rest.remove(0);
+                let path: String = script(&mut rest, "debug")?;
+                // The debugger reads its commands from stdin
+                if path == lox::STDIN {
+                    return Err("Can't debug a script read from stdin.".to_owned());
+                }
+                Command::Debug { path, args: rest }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "watch") => {
+                rest.remove(0);
+                let path: String = script(&mut rest, "watch")?;
+                if path == lox::STDIN {
+                    return Err("Can't watch a script read from stdin.".to_owned());
+                }
+                Command::Watch { path, args: rest }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "lint") => {
+                rest.remove(0);
+                let config: lint::Config = lint_config(&mut rest)?;
+                let path: String = script(&mut rest, "lint")?;
+                Command::Lint { path, config }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "emit-js") => {
+                rest.remove(0);
+                let path: String = script(&mut rest, "emit-js")?;
+                Command::EmitJs { path }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "minify") => {
+                rest.remove(0);
+                let rename: bool = rest.first().is_some_and(|arg| arg == "--rename");
+                if rename {
+                    rest.remove(0);
+                }
+                let path: String = script(&mut rest, "minify")?;
+                Command::Minify { path, rename }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "doc") => {
+                rest.remove(0);
+                let path: String = script(&mut rest, "doc")?;
+                Command::Doc { path }
+            }
+            (None, None) if rest.first().is_some_and(|arg| arg == "highlight") => {
+                rest.remove(0);
+                let path: String = script(&mut rest, "highlight")?;
+                Command::Highlight { path }
+            }
+            (None, None) if rest.len() == 1 && rest[0] == "lsp" => Command::Lsp,
+            (None, None) if rest.is_empty() => Command::Prompt,
+            (None, None) => {
+                let path: String = rest.remove(0);
+                Command::Script { path, args: rest }
+            }
+        };
+
+        Ok(options)
+    }
+}
+
+// The options of `lint`, taken off the front of `rest`. `--deny` and
+// `--allow` override the config file, `.loxlint` unless `--config` is given.
+fn lint_config(rest: &mut Vec<String>) -> Result<lint::Config, String> {
+    let mut path: Option<String> = None;
+    let mut levels: Vec<(String, Level)> = vec![];
+
+    while rest.first().is_some_and(|arg| arg.starts_with("--")) {
+        let option: String = rest.remove(0);
+        if rest.is_empty() {
+            return Err(format!("Option '{}' expects a value.", option));
+        }
+        let value: String = rest.remove(0);
+
+        match option.as_str() {
+            "--config" => path = Some(value),
+            "--deny" => levels.push((value, Level::Deny)),
+            "--allow" => levels.push((value, Level::Allow)),
+            "--warn" => levels.push((value, Level::Warn)),
+            _ => return Err(format!("Unknown lint option '{}'.", option)),
+        }
+    }
+
+    let mut config = lint::Config::default();
+    match path {
+        Some(path) => config.load(&path)?,
+        None if Path::new(lint::CONFIG_PATH).exists() => config.load(lint::CONFIG_PATH)?,
+        None => (),
+    }
+    for (rule, level) in levels {
+        config.set(&rule, level)?;
+    }
+
+    Ok(config)
+}
+
+// The value of an option like `--max-depth N`
+fn positive(option: &str, value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(value) if value >= 1 => Ok(value),
+        _ => Err(format!("Option '{}' expects a positive integer.", option)),
+    }
+}
@@ -0,0 +1,481 @@
+use core::fmt;
+use std::{collections::HashMap, io::Write};
+
+use indexmap::IndexMap;
+
+use crate::{
+    callable::Declaration,
+    environment::Environment,
+    error::LoxError,
+    expr::{Expr, ExprId},
+    interpreter::{self, is_truthy, stringify, Interpreter},
+    native,
+    object::Object,
+    stmt::Stmt,
+    sync::{Rc, RefCell},
+    token::{Literal, Token, TokenType},
+};
+
+// The closure backend, for `--closures`. Once a program is resolved, every
+// expression and statement is turned into a Rust closure that captures its
+// children, already compiled. Running the program then calls closures rather
+// than matching on the tree (and cloning it) over and over.
+//
+// The operations themselves are the interpreter's, so both backends behave
+// the same. Only the statement budget is checked, `--trace`, `--coverage`
+// and the debugger need the tree-walker.
+
+#[cfg(not(feature = "sync"))]
+pub type ExprFn = Box<dyn Fn(&mut Interpreter) -> Result<Object, LoxError>>;
+#[cfg(feature = "sync")]
+pub type ExprFn = Box<dyn Fn(&mut Interpreter) -> Result<Object, LoxError> + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type StmtFn = Box<dyn Fn(&mut Interpreter) -> Result<(), LoxError>>;
+#[cfg(feature = "sync")]
+pub type StmtFn = Box<dyn Fn(&mut Interpreter) -> Result<(), LoxError> + Send + Sync>;
+
+// A function's body, shared by every copy of the function
+#[derive(Clone)]
+pub struct Compiled(Rc<[StmtFn]>);
+
+impl Compiled {
+    // Runs the body in `environment`, like `Interpreter::execute_block`
+    pub fn run(
+        &self,
+        interpreter: &mut Interpreter,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), LoxError> {
+        run_block(interpreter, &self.0, environment)
+    }
+}
+
+impl fmt::Debug for Compiled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<compiled body>")
+    }
+}
+
+// Compiles a resolved program. `locals` is where the resolver found every
+// local variable, see `Interpreter::locals`.
+pub fn compile(
+    statements: &[Option<Stmt>],
+    locals: &HashMap<ExprId, (usize, usize)>,
+) -> Vec<StmtFn> {
+    let compiler = Compiler { locals };
+    statements
+        .iter()
+        .flatten()
+        .map(|stmt| compiler.stmt(stmt))
+        .collect()
+}
+
+fn run_block(
+    interpreter: &mut Interpreter,
+    statements: &[StmtFn],
+    environment: Rc<RefCell<Environment>>,
+) -> Result<(), LoxError> {
+    interpreter.charge_environment(&environment);
+    let previous = std::mem::replace(&mut interpreter.environment, environment);
+    interpreter.frames.push(previous);
+
+    for stmt in statements {
+        if let Err(err) = stmt(interpreter) {
+            // Restore the original environment even after error
+            interpreter.environment = interpreter.frames.pop().unwrap();
+            return Err(err);
+        }
+    }
+
+    interpreter.environment = interpreter.frames.pop().unwrap();
+    Ok(())
+}
+
+struct Compiler<'a> {
+    locals: &'a HashMap<ExprId, (usize, usize)>,
+}
+
+impl Compiler<'_> {
+    fn block(&self, statements: &[Option<Box<Stmt>>]) -> Vec<StmtFn> {
+        statements
+            .iter()
+            .flatten()
+            .map(|stmt| self.stmt(stmt))
+            .collect()
+    }
+
+    // Every statement counts against the budget, see `Interpreter::execute`
+    fn stmt(&self, stmt: &Stmt) -> StmtFn {
+        let stmt: StmtFn = self.stmt_body(stmt);
+
+        Box::new(move |interpreter| {
+            if interpreter.budget.spend() {
+                interpreter.budget.check()?;
+            }
+            stmt(interpreter)?;
+            interpreter.check_memory()
+        })
+    }
+
+    fn stmt_body(&self, stmt: &Stmt) -> StmtFn {
+        match stmt {
+            Stmt::Expression { expression, .. } => {
+                let expression: ExprFn = self.expr(expression);
+                Box::new(move |interpreter| expression(interpreter).map(|_| ()))
+            }
+            Stmt::Function { .. } => {
+                let declaration: Rc<Declaration> = self.function(stmt);
+
+                Box::new(move |interpreter| {
+                    interpreter.declare_function(declaration.clone());
+                    Ok(())
+                })
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition: ExprFn = self.expr(condition);
+                let then_branch: StmtFn = self.stmt(then_branch);
+                let else_branch: Option<StmtFn> =
+                    (**else_branch).as_ref().map(|stmt| self.stmt(stmt));
+
+                Box::new(move |interpreter| {
+                    if is_truthy(&condition(interpreter)?) {
+                        then_branch(interpreter)
+                    } else if let Some(else_branch) = &else_branch {
+                        else_branch(interpreter)
+                    } else {
+                        Ok(())
+                    }
+                })
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                let condition: ExprFn = self.expr(condition);
+                let body: StmtFn = self.stmt(body);
+                let increment: Option<ExprFn> = increment.as_ref().map(|expr| self.expr(expr));
+
+                Box::new(move |interpreter| {
+                    while is_truthy(&condition(interpreter)?) {
+                        match body(interpreter) {
+                            Ok(()) | Err(LoxError::Continue) => (),
+                            Err(LoxError::Break) => break,
+                            Err(err) => return Err(err),
+                        }
+
+                        if let Some(increment) = &increment {
+                            increment(interpreter)?;
+                        }
+                    }
+                    Ok(())
+                })
+            }
+            Stmt::Break { .. } => Box::new(|_| Err(LoxError::Break)),
+            Stmt::Continue { .. } => Box::new(|_| Err(LoxError::Continue)),
+            Stmt::Print {
+                expression, stderr, ..
+            } => {
+                let expression: ExprFn = self.expr(expression);
+                let stderr: bool = *stderr;
+
+                Box::new(move |interpreter| {
+                    let text: String = stringify(&expression(interpreter)?);
+                    let _ = writeln!(interpreter.output(stderr), "{}", text);
+                    Ok(())
+                })
+            }
+            Stmt::Return { value, .. } => {
+                let value: Option<ExprFn> = value.as_ref().map(|expr| self.expr(expr));
+
+                Box::new(move |interpreter| {
+                    let value: Object = match &value {
+                        Some(value) => value(interpreter)?,
+                        None => Object::None,
+                    };
+                    Err(LoxError::Return { value })
+                })
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                let symbol = name.symbol;
+                let initializer: Option<ExprFn> = initializer.as_ref().map(|expr| self.expr(expr));
+
+                Box::new(move |interpreter| {
+                    let value: Object = match &initializer {
+                        Some(initializer) => initializer(interpreter)?,
+                        None => Object::None,
+                    };
+                    interpreter.environment.borrow_mut().define(symbol, value);
+                    Ok(())
+                })
+            }
+            Stmt::Block { statements, .. } => {
+                let statements: Vec<StmtFn> = self.block(statements);
+
+                Box::new(move |interpreter| {
+                    let environment: Rc<RefCell<Environment>> = interpreter
+                        .env_pool
+                        .acquire(interpreter.environment.clone());
+                    let res = run_block(interpreter, &statements, environment.clone());
+                    interpreter.env_pool.release(environment);
+                    res
+                })
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let (name, superclass_expr) = (name.clone(), superclass.clone());
+                let superclass: Option<ExprFn> = superclass.as_ref().map(|expr| self.expr(expr));
+                let methods: Vec<Rc<Declaration>> =
+                    methods.iter().map(|method| self.function(method)).collect();
+
+                Box::new(move |interpreter| {
+                    let superclass: Option<(Object, &Expr)> = match &superclass {
+                        Some(superclass) => {
+                            Some((superclass(interpreter)?, superclass_expr.as_ref().unwrap()))
+                        }
+                        None => None,
+                    };
+                    interpreter.declare_class(&name, superclass, &methods)
+                })
+            }
+        }
+    }
+
+    // Compiled once, every function the declaration creates shares it
+    fn function(&self, stmt: &Stmt) -> Rc<Declaration> {
+        match stmt {
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                let compiled: Compiled = Compiled(self.block(body).into());
+                Declaration::new(name, params, body, Some(compiled))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> ExprFn {
+        match expr {
+            Expr::Literal { value, .. } => {
+                let value: Object = interpreter::literal(value);
+                Box::new(move |_| Ok(value.clone()))
+            }
+            Expr::Grouping { expression, .. } => self.expr(expression),
+            Expr::List { elements, .. } => {
+                let elements: Vec<ExprFn> = elements.iter().map(|expr| self.expr(expr)).collect();
+
+                Box::new(move |interpreter| {
+                    let mut values: Vec<Object> = Vec::with_capacity(elements.len());
+                    for element in elements.iter() {
+                        values.push(element(interpreter)?);
+                    }
+                    let list: Object = native::new_list(values);
+                    interpreter.charge(&list);
+                    Ok(list)
+                })
+            }
+            Expr::Map { brace, entries, .. } => {
+                let brace: Token = brace.clone();
+                let entries: Vec<(ExprFn, ExprFn)> = entries
+                    .iter()
+                    .map(|(key, value)| (self.expr(key), self.expr(value)))
+                    .collect();
+
+                Box::new(move |interpreter| {
+                    let mut map: IndexMap<Literal, Object> = IndexMap::new();
+                    for (key, value) in entries.iter() {
+                        let key: Literal = interpreter::map_key(key(interpreter)?, &brace)?;
+                        map.insert(key, value(interpreter)?);
+                    }
+                    let map: Object = native::new_map(map);
+                    interpreter.charge(&map);
+                    Ok(map)
+                })
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+                ..
+            } => {
+                let (object, index) = (self.expr(object), self.expr(index));
+                let bracket: Token = bracket.clone();
+
+                Box::new(move |interpreter| {
+                    let object: Object = object(interpreter)?;
+                    let index: Object = index(interpreter)?;
+                    interpreter.index(object, index, &bracket)
+                })
+            }
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                value,
+                ..
+            } => {
+                let (object, index, value) =
+                    (self.expr(object), self.expr(index), self.expr(value));
+                let bracket: Token = bracket.clone();
+
+                Box::new(move |interpreter| {
+                    let object: Object = object(interpreter)?;
+                    let index: Object = index(interpreter)?;
+                    interpreter.index_set(object, index, &bracket, |interpreter| value(interpreter))
+                })
+            }
+            Expr::Assign {
+                id, name, value, ..
+            } => {
+                let resolved: Option<(usize, usize)> = self.locals.get(id).copied();
+                let name: Token = name.clone();
+                let value: ExprFn = self.expr(value);
+
+                Box::new(move |interpreter| {
+                    let value: Object = value(interpreter)?;
+                    interpreter.assign(resolved, &name, value)
+                })
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let (left, right) = (self.expr(left), self.expr(right));
+                let is_or: bool = operator.token_type == TokenType::Or;
+
+                Box::new(move |interpreter| {
+                    let left: Object = left(interpreter)?;
+                    match is_truthy(&left) == is_or {
+                        true => Ok(left),
+                        false => right(interpreter),
+                    }
+                })
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                ..
+            } => {
+                let callee: ExprFn = self.expr(callee);
+                let arguments: Vec<ExprFn> = arguments.iter().map(|arg| self.expr(arg)).collect();
+                let paren: Token = paren.clone();
+
+                Box::new(move |interpreter| {
+                    let mut values: Vec<Object> = Vec::with_capacity(arguments.len());
+                    for argument in arguments.iter() {
+                        values.push(argument(interpreter)?);
+                    }
+                    let callee: Object = callee(interpreter)?;
+                    interpreter.call(callee, &values, &paren)
+                })
+            }
+            Expr::Get {
+                id, object, name, ..
+            } => {
+                let (id, name) = (*id, name.clone());
+                let object: ExprFn = self.expr(object);
+
+                Box::new(move |interpreter| {
+                    let object: Object = object(interpreter)?;
+                    interpreter.get(object, id, &name)
+                })
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                let (object, value) = (self.expr(object), self.expr(value));
+                let name: Token = name.clone();
+
+                Box::new(move |interpreter| {
+                    let object: Object = object(interpreter)?;
+                    interpreter.set(object, &name, |interpreter| value(interpreter))
+                })
+            }
+            Expr::Super { id, method, .. } => {
+                let (distance, slot) = *self.locals.get(id).unwrap();
+                let method: Token = method.clone();
+
+                Box::new(move |interpreter| interpreter.super_method(distance, slot, &method))
+            }
+            Expr::This {
+                id, keyword: name, ..
+            }
+            | Expr::Variable { id, name, .. } => {
+                let resolved: Option<(usize, usize)> = self.locals.get(id).copied();
+                let name: Token = name.clone();
+
+                Box::new(move |interpreter| interpreter.look_up(resolved, &name))
+            }
+            Expr::Unary {
+                operator, right, ..
+            } => {
+                let operator: Token = operator.clone();
+                let right: ExprFn = self.expr(right);
+
+                Box::new(move |interpreter| interpreter::unary(&operator, right(interpreter)?))
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => self.binary(self.expr(left), operator.clone(), self.expr(right)),
+        }
+    }
+
+    // The operator is known ahead of time, so arithmetic on numbers (the
+    // common case) gets a closure of its own. Anything else goes through
+    // `interpreter::binary`, errors included.
+    fn binary(&self, left: ExprFn, operator: Token, right: ExprFn) -> ExprFn {
+        macro_rules! numbers {
+            ($op:tt, $variant:ident) => {
+                Box::new(move |interpreter| {
+                    match (left(interpreter)?, right(interpreter)?) {
+                        (Object::Number(a), Object::Number(b)) => Ok(Object::$variant(a $op b)),
+                        (a, b) => {
+                            let value: Object = interpreter::binary(&operator, a, b)?;
+                            interpreter.charge(&value);
+                            Ok(value)
+                        }
+                    }
+                })
+            };
+        }
+
+        match operator.token_type {
+            TokenType::Plus => numbers!(+, Number),
+            TokenType::Minus => numbers!(-, Number),
+            TokenType::Star => numbers!(*, Number),
+            TokenType::Slash => numbers!(/, Number),
+            TokenType::Greater => numbers!(>, Boolean),
+            TokenType::GreaterEqual => numbers!(>=, Boolean),
+            TokenType::Less => numbers!(<, Boolean),
+            TokenType::LessEqual => numbers!(<=, Boolean),
+            _ => Box::new(move |interpreter| {
+                let left: Object = left(interpreter)?;
+                let right: Object = right(interpreter)?;
+                let value: Object = interpreter::binary(&operator, left, right)?;
+                interpreter.charge(&value);
+                Ok(value)
+            }),
+        }
+    }
+}
@@ -0,0 +1,434 @@
+// Conversions between Rust and Lox values, for natives and host programs.
+// `ToLox` turns a Rust value into an `Object`, `FromLox` reads one back and
+// fails with a runtime error (as a native would) if it's of another type:
+//
+//     let name: String = String::from_lox(&arguments[0])?;
+//     let scores: Vec<f64> = Vec::from_lox(&arguments[1])?;
+//     Ok(scores.iter().sum::<f64>().to_lox())
+//
+// The `From<T> for Object` and `TryFrom<Object>` impls are the same
+// conversions. `Option` has no `TryFrom`, the standard library's blanket
+// impl takes that spot.
+//
+// JSON values (`serde_json::Value`) convert too, e.g. to pass a host's
+// configuration to a script. `jsonParse` and `jsonStringify` are built on
+// them.
+
+use std::collections::HashMap;
+
+use serde_json::Value as Json;
+
+use crate::{
+    error::LoxError,
+    native::{self, from_key},
+    object::Object,
+    sync::Rc,
+    token::Literal,
+};
+
+pub trait ToLox {
+    fn to_lox(self) -> Object;
+}
+
+pub trait FromLox: Sized {
+    fn from_lox(value: &Object) -> Result<Self, LoxError>;
+}
+
+impl ToLox for Object {
+    fn to_lox(self) -> Object {
+        self
+    }
+}
+
+impl ToLox for f64 {
+    fn to_lox(self) -> Object {
+        Object::Number(self)
+    }
+}
+
+// Lox numbers are doubles, past 2^53 an integer loses precision
+impl ToLox for i64 {
+    fn to_lox(self) -> Object {
+        Object::Number(self as f64)
+    }
+}
+
+impl ToLox for bool {
+    fn to_lox(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl ToLox for String {
+    fn to_lox(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl ToLox for &str {
+    fn to_lox(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl<T: ToLox> ToLox for Vec<T> {
+    fn to_lox(self) -> Object {
+        native::new_list(self.into_iter().map(ToLox::to_lox).collect())
+    }
+}
+
+// Sorted by key, Lox maps keep their insertion order
+impl<T: ToLox> ToLox for HashMap<String, T> {
+    fn to_lox(self) -> Object {
+        let mut entries: Vec<(String, T)> = self.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        native::new_map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Literal::String(key.into()), value.to_lox()))
+                .collect(),
+        )
+    }
+}
+
+// `None` is nil
+impl<T: ToLox> ToLox for Option<T> {
+    fn to_lox(self) -> Object {
+        self.map_or(Object::None, ToLox::to_lox)
+    }
+}
+
+// null, arrays and objects are nil, lists and maps. Objects come out with
+// their keys sorted.
+impl ToLox for Json {
+    fn to_lox(self) -> Object {
+        match self {
+            Json::Null => Object::None,
+            Json::Bool(val) => Object::Boolean(val),
+            // Always some, serde_json doesn't keep arbitrary precision here
+            Json::Number(val) => Object::Number(val.as_f64().unwrap_or(f64::NAN)),
+            Json::String(val) => val.to_lox(),
+            Json::Array(items) => items.to_lox(),
+            Json::Object(entries) => native::new_map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Literal::String(key.into()), value.to_lox()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl FromLox for Object {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::Number(val) => Ok(*val),
+            _ => Err(expected("a number", value)),
+        }
+    }
+}
+
+impl FromLox for i64 {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::Number(val) if val.fract() == 0.0 => Ok(*val as i64),
+            _ => Err(expected("an integer", value)),
+        }
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::Boolean(val) => Ok(*val),
+            _ => Err(expected("a boolean", value)),
+        }
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::String(val) => Ok(val.to_string()),
+            _ => Err(expected("a string", value)),
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::List(list) => list.borrow().iter().map(T::from_lox).collect(),
+            _ => Err(expected("a list", value)),
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for HashMap<String, T> {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        let Object::Map(map) = value else {
+            return Err(expected("a map", value));
+        };
+        let map = map.borrow();
+
+        map.iter()
+            .map(|(key, value)| match key {
+                Literal::String(key) => Ok((key.to_string(), T::from_lox(value)?)),
+                _ => Err(expected("a string key", &from_key(key))),
+            })
+            .collect()
+    }
+}
+
+// nil is `None`
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        match value {
+            Object::None => Ok(None),
+            _ => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+// Fails for what JSON has no counterpart of: functions, instances, bytes,
+// NaN and infinities, non-string keys and lists or maps inside themselves
+impl FromLox for Json {
+    fn from_lox(value: &Object) -> Result<Self, LoxError> {
+        to_json(value, &mut vec![])
+    }
+}
+
+// 2^53, past it not every integer is a double
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+// `containers` are the lists and maps `value` is inside of, to catch cycles
+fn to_json(value: &Object, containers: &mut Vec<usize>) -> Result<Json, LoxError> {
+    let address: Option<usize> = match value {
+        Object::List(list) => Some(Rc::as_ptr(list) as usize),
+        Object::Map(map) => Some(Rc::as_ptr(map) as usize),
+        _ => None,
+    };
+    if let Some(address) = address {
+        if containers.contains(&address) {
+            return Err(native::error(format!(
+                "Can't convert {} inside itself to JSON.",
+                describe(value)
+            )));
+        }
+        containers.push(address);
+    }
+
+    let json: Json = match value {
+        Object::None => Json::Null,
+        Object::Boolean(val) => Json::Bool(*val),
+        // Integers print without a fraction, as Lox prints them
+        Object::Number(val) if val.fract() == 0.0 && val.abs() < MAX_SAFE_INTEGER => {
+            Json::from(*val as i64)
+        }
+        Object::Number(val) => match serde_json::Number::from_f64(*val) {
+            Some(number) => Json::Number(number),
+            None => return Err(native::error(format!("Can't convert {} to JSON.", val))),
+        },
+        Object::String(val) => Json::String(val.to_string()),
+        Object::List(list) => Json::Array(
+            list.borrow()
+                .iter()
+                .map(|element| to_json(element, containers))
+                .collect::<Result<_, _>>()?,
+        ),
+        Object::Map(map) => Json::Object(
+            map.borrow()
+                .iter()
+                .map(|(key, value)| match key {
+                    Literal::String(key) => Ok((key.to_string(), to_json(value, containers)?)),
+                    _ => Err(expected("a string key", &from_key(key))),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => {
+            return Err(native::error(format!(
+                "Can't convert {} to JSON.",
+                describe(value)
+            )))
+        }
+    };
+
+    if address.is_some() {
+        containers.pop();
+    }
+    Ok(json)
+}
+
+// The arguments of a call from Rust, see `Lox::call`: a tuple of values
+// `ToLox` converts, or a `Vec` of them
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Object>;
+}
+
+impl<T: ToLox> IntoArgs for Vec<T> {
+    fn into_args(self) -> Vec<Object> {
+        self.into_iter().map(ToLox::to_lox).collect()
+    }
+}
+
+impl IntoArgs for &[Object] {
+    fn into_args(self) -> Vec<Object> {
+        self.to_vec()
+    }
+}
+
+macro_rules! tuple_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: ToLox),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Object> {
+                let ($($arg,)*) = self;
+                vec![$($arg.to_lox()),*]
+            }
+        }
+    };
+}
+
+tuple_args!();
+tuple_args!(A);
+tuple_args!(A, B);
+tuple_args!(A, B, C);
+tuple_args!(A, B, C, D);
+tuple_args!(A, B, C, D, E);
+tuple_args!(A, B, C, D, E, F);
+
+fn expected(what: &str, value: &Object) -> LoxError {
+    native::error(format!("Expected {}, got {}.", what, describe(value)))
+}
+
+fn describe(value: &Object) -> &'static str {
+    match value {
+        Object::String(_) => "a string",
+        Object::Bytes(_) => "bytes",
+        Object::Number(_) => "a number",
+        Object::Boolean(_) => "a boolean",
+        Object::Callable(_) => "a function",
+        Object::Class(_) => "a class",
+        Object::Instance(_) | Object::Foreign(_) => "an instance",
+        Object::List(_) => "a list",
+        Object::Map(_) => "a map",
+        Object::None => "nil",
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        value.to_lox()
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        value.to_lox()
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        value.to_lox()
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        value.to_lox()
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        value.to_lox()
+    }
+}
+
+impl<T: ToLox> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        value.to_lox()
+    }
+}
+
+impl<T: ToLox> From<HashMap<String, T>> for Object {
+    fn from(value: HashMap<String, T>) -> Self {
+        value.to_lox()
+    }
+}
+
+impl<T: ToLox> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        value.to_lox()
+    }
+}
+
+impl From<Json> for Object {
+    fn from(value: Json) -> Self {
+        value.to_lox()
+    }
+}
+
+impl TryFrom<Object> for f64 {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        f64::from_lox(&value)
+    }
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        i64::from_lox(&value)
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        bool::from_lox(&value)
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        String::from_lox(&value)
+    }
+}
+
+impl<T: FromLox> TryFrom<Object> for Vec<T> {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        Vec::from_lox(&value)
+    }
+}
+
+impl<T: FromLox> TryFrom<Object> for HashMap<String, T> {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        HashMap::from_lox(&value)
+    }
+}
+
+impl TryFrom<Object> for Json {
+    type Error = LoxError;
+
+    fn try_from(value: Object) -> Result<Self, LoxError> {
+        Json::from_lox(&value)
+    }
+}
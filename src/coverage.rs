@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::stmt::Stmt;
+
+// Which lines of a script ran, for `--coverage`. Only the statements passed
+// to `add` are counted, so code from elsewhere (e.g. the prelude) is left out.
+#[derive(Default)]
+pub struct Coverage {
+    // How many times each statement ran, keyed by the offset of its token
+    // (see `Stmt::token`), along with its line
+    statements: HashMap<usize, (usize, u64)>,
+}
+
+impl Coverage {
+    // Registers every statement of a program, including function bodies,
+    // so the ones that never run are reported too
+    pub fn add(&mut self, statements: &[Option<Stmt>]) {
+        for stmt in statements.iter().flatten() {
+            self.add_stmt(stmt);
+        }
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt) {
+        if let Some(token) = stmt.token() {
+            self.statements.insert(token.offset, (token.line, 0));
+        }
+
+        let children: Vec<&Stmt> = match stmt {
+            Stmt::Block { statements, .. }
+            | Stmt::Function {
+                body: statements, ..
+            } => statements.iter().flatten().map(|stmt| &**stmt).collect(),
+            Stmt::Class { methods, .. } => methods.iter().map(|method| &**method).collect(),
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => [Some(&**then_branch), (**else_branch).as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            Stmt::While { body, .. } => vec![&**body],
+            _ => vec![],
+        };
+
+        for child in children {
+            self.add_stmt(child);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    pub fn hit(&mut self, stmt: &Stmt) {
+        if let Some(token) = stmt.token() {
+            if let Some((_, hits)) = self.statements.get_mut(&token.offset) {
+                *hits += 1;
+            }
+        }
+    }
+
+    // The hits of every line with a statement. A line with several
+    // statements counts as often as the one that ran the most.
+    fn lines(&self) -> BTreeMap<usize, u64> {
+        let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+        for (line, hits) in self.statements.values() {
+            let max: &mut u64 = lines.entry(*line).or_default();
+            *max = (*max).max(*hits);
+        }
+
+        lines
+    }
+
+    // e.g. `Coverage: 9/12 lines (75.0%), not run: 4, 10-11`
+    pub fn summary(&self) -> String {
+        let lines: BTreeMap<usize, u64> = self.lines();
+        let covered: usize = lines.values().filter(|hits| **hits > 0).count();
+        let percent: f64 = match lines.len() {
+            0 => 100.0,
+            total => covered as f64 / total as f64 * 100.0,
+        };
+
+        let mut res: String = format!(
+            "Coverage: {}/{} lines ({:.1}%)",
+            covered,
+            lines.len(),
+            percent
+        );
+
+        // Consecutive lines that didn't run are shown as ranges
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for (line, _) in lines.iter().filter(|(_, hits)| **hits == 0) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == *line => *end = *line,
+                _ => ranges.push((*line, *line)),
+            }
+        }
+        if !ranges.is_empty() {
+            let ranges: Vec<String> = ranges
+                .iter()
+                .map(|(start, end)| match start == end {
+                    true => start.to_string(),
+                    false => format!("{}-{}", start, end),
+                })
+                .collect();
+            res += &format!(", not run: {}", ranges.join(", "));
+        }
+
+        res
+    }
+
+    // In the lcov tracefile format, which most coverage tools read
+    pub fn lcov(&self, path: &str) -> String {
+        let lines: BTreeMap<usize, u64> = self.lines();
+
+        let mut res: String = format!("TN:\nSF:{}\n", path);
+        for (line, hits) in lines.iter() {
+            let _ = writeln!(res, "DA:{},{}", line, hits);
+        }
+        let _ = writeln!(res, "LF:{}", lines.len());
+        let _ = writeln!(
+            res,
+            "LH:{}",
+            lines.values().filter(|hits| **hits > 0).count()
+        );
+        res += "end_of_record\n";
+
+        res
+    }
+}
@@ -0,0 +1,323 @@
+use std::{collections::BTreeSet, io::Write};
+
+use crate::{
+    callable::LoxCallable,
+    environment::{self, Environment},
+    error::LoxError,
+    interpreter::{stringify, Interpreter},
+    object::Object,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    stmt::Stmt,
+    symbol::Symbol,
+    sync::{Rc, RefCell},
+};
+
+const HELP: &str = "\
+break N     (b)  pause at line N
+delete N    (d)  remove the breakpoint at line N
+breakpoints      list the breakpoints
+step        (s)  run until the next statement
+next        (n)  run until the next statement, stepping over calls
+continue    (c)  run until a breakpoint
+print EXPR  (p)  evaluate EXPR where the script is paused
+locals           list the variables in scope, innermost first
+backtrace   (bt) list the calls in progress, innermost first
+quit        (q)  stop the script
+An empty line repeats the last command.";
+
+// Pauses a script at breakpoints and between steps, for `rustlox debug`.
+// Called by the interpreter before every statement, see `Debugger::before`.
+pub struct Debugger {
+    // The script's source, to show the statement paused at
+    lines: Vec<String>,
+    // Offset of the script's first character, statements before it (e.g.
+    // from the prelude) are never paused at. See `Token::offset`.
+    start: usize,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    last_command: String,
+    // Calls in progress, innermost last: what was called and the line it
+    // was called from
+    frames: Vec<(String, usize)>,
+}
+
+enum Mode {
+    // Until a breakpoint
+    Continue,
+    // At the next statement
+    Step,
+    // At the next statement no deeper than the given call depth
+    Next(usize),
+}
+
+impl Debugger {
+    // Pauses at the script's first statement, so breakpoints can be set
+    pub fn new(source: &str, start: usize) -> Self {
+        Debugger {
+            lines: source.lines().map(str::to_owned).collect(),
+            start,
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last_command: String::new(),
+            frames: vec![],
+        }
+    }
+
+    pub fn enter(&mut self, name: String, line: usize) {
+        self.frames.push((name, line));
+    }
+
+    // Also called when the call fails, so the frames stay balanced
+    pub fn exit(&mut self) {
+        self.frames.pop();
+    }
+
+    // Fails with `LoxError::Exit` once the user quits
+    pub fn before(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) -> Result<(), LoxError> {
+        // Blocks have no line of their own, their statements are paused at
+        let Some(token) = stmt.token() else {
+            return Ok(());
+        };
+        if token.offset < self.start {
+            return Ok(());
+        }
+
+        let pause: bool = match self.mode {
+            Mode::Continue => false,
+            Mode::Step => true,
+            Mode::Next(depth) => interpreter.call_depth <= depth,
+        } || self.breakpoints.contains(&token.line);
+
+        if pause {
+            self.show(interpreter, token.line);
+            self.prompt(interpreter, token.line)?;
+        }
+        Ok(())
+    }
+
+    fn show(&self, interpreter: &mut Interpreter, line: usize) {
+        let text: &str = self.lines.get(line - 1).map_or("", |text| text.trim());
+        let _ = writeln!(interpreter.stdout, "line {}: {}", line, text);
+    }
+
+    // Reads commands until one resumes the script, paused at `line`
+    fn prompt(&mut self, interpreter: &mut Interpreter, line: usize) -> Result<(), LoxError> {
+        loop {
+            let _ = write!(interpreter.stdout, "(debug) ");
+            let _ = interpreter.stdout.flush();
+
+            let mut input: String = String::new();
+            if interpreter
+                .read_input(|stdin| stdin.read_line(&mut input))
+                .unwrap_or(0)
+                == 0
+            {
+                // End of input, nobody is left to resume the script
+                let _ = writeln!(interpreter.stdout);
+                return Err(LoxError::Exit);
+            }
+
+            let mut command: &str = input.trim();
+            if command.is_empty() {
+                command = &self.last_command;
+            }
+            if command.is_empty() {
+                continue;
+            }
+            let command: String = command.to_owned();
+            self.last_command = command.clone();
+
+            // The rest of the line is an expression, spaces included
+            if let Some(expr) = ["print ", "p "]
+                .iter()
+                .find_map(|prefix| command.strip_prefix(prefix))
+            {
+                let value: String = evaluate(interpreter, expr);
+                let _ = writeln!(interpreter.stdout, "{}", value);
+                continue;
+            }
+
+            let mut words = command.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("step" | "s"), None) => {
+                    self.mode = Mode::Step;
+                    return Ok(());
+                }
+                (Some("next" | "n"), None) => {
+                    self.mode = Mode::Next(interpreter.call_depth);
+                    return Ok(());
+                }
+                (Some("continue" | "c"), None) => {
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                }
+                (Some("break" | "b"), Some(line)) => match self.line_arg(line) {
+                    Some(line) => {
+                        self.breakpoints.insert(line);
+                        let _ = writeln!(interpreter.stdout, "Breakpoint at line {}.", line);
+                    }
+                    None => {
+                        let _ = writeln!(interpreter.stdout, "No line '{}' in the script.", line);
+                    }
+                },
+                (Some("delete" | "d"), Some(line)) => {
+                    match self
+                        .line_arg(line)
+                        .filter(|line| self.breakpoints.remove(line))
+                    {
+                        Some(line) => {
+                            let _ = writeln!(
+                                interpreter.stdout,
+                                "Removed the breakpoint at line {}.",
+                                line
+                            );
+                        }
+                        None => {
+                            let _ =
+                                writeln!(interpreter.stdout, "No breakpoint at line '{}'.", line);
+                        }
+                    }
+                }
+                (Some("breakpoints"), None) => match self.breakpoints.is_empty() {
+                    true => {
+                        let _ = writeln!(interpreter.stdout, "No breakpoints.");
+                    }
+                    false => {
+                        for line in self.breakpoints.iter() {
+                            self.show(interpreter, *line);
+                        }
+                    }
+                },
+                (Some("locals"), None) => self.print_locals(interpreter),
+                (Some("backtrace" | "bt"), None) => self.print_backtrace(interpreter, line),
+                (Some("quit" | "q"), None) => return Err(LoxError::Exit),
+                (Some("help" | "h"), None) => {
+                    let _ = writeln!(interpreter.stdout, "{}", HELP);
+                }
+                _ => {
+                    let _ = writeln!(
+                        interpreter.stdout,
+                        "Unknown command '{}', type 'help' for the commands.",
+                        command
+                    );
+                }
+            }
+        }
+    }
+
+    // Every local scope, then the globals the script defined
+    fn print_locals(&self, interpreter: &mut Interpreter) {
+        let chain: Vec<Rc<RefCell<Environment>>> =
+            environment::chain(interpreter.environment.clone());
+
+        for (depth, env) in chain.iter().enumerate() {
+            let is_global: bool = env.borrow().enclosing.is_none();
+            let bindings: Vec<(Symbol, Object)> = env
+                .borrow()
+                .bindings()
+                .into_iter()
+                .filter(|(_, value)| !is_global || self.is_from_script(value))
+                .collect();
+
+            if bindings.is_empty() && !is_global {
+                continue;
+            }
+            let _ = match is_global {
+                true => writeln!(interpreter.stdout, "globals:"),
+                false => writeln!(interpreter.stdout, "scope {}:", depth),
+            };
+            for (name, value) in bindings {
+                let _ = writeln!(
+                    interpreter.stdout,
+                    "  {} = {}",
+                    name.as_str(),
+                    stringify(&value)
+                );
+            }
+        }
+    }
+
+    // Natives and the prelude's functions would drown the script's globals
+    fn is_from_script(&self, value: &Object) -> bool {
+        match value {
+            Object::Callable(LoxCallable::User { declaration, .. }) => {
+                declaration.name.offset >= self.start
+            }
+            Object::Callable(_) => false,
+            _ => true,
+        }
+    }
+
+    // `line` is where the innermost call is paused, the others are paused
+    // where they made the next call
+    fn print_backtrace(&self, interpreter: &mut Interpreter, line: usize) {
+        let mut line: usize = line;
+        for (i, (name, call_line)) in self.frames.iter().rev().enumerate() {
+            let _ = writeln!(interpreter.stdout, "#{} {} at line {}", i, name, line);
+            line = *call_line;
+        }
+        let _ = writeln!(
+            interpreter.stdout,
+            "#{} <script> at line {}",
+            self.frames.len(),
+            line
+        );
+    }
+
+    fn line_arg(&self, arg: &str) -> Option<usize> {
+        arg.parse::<usize>()
+            .ok()
+            .filter(|line| (1..=self.lines.len()).contains(line))
+    }
+}
+
+// Evaluates `source`, an expression, in the paused environment. Returns its
+// value or the error it raised. Also used by `inspect()`.
+pub fn evaluate(interpreter: &mut Interpreter, source: &str) -> String {
+    let mut parser: Parser = Parser::new(Scanner::new(format!("{};", source), 0));
+    let statements: Vec<Option<Stmt>> = parser.parse();
+    if let Some(diagnostic) = parser.diagnostics.first() {
+        return diagnostic.message.clone();
+    }
+    let expr = match statements.as_slice() {
+        [Some(Stmt::Expression { expression, .. })] => expression,
+        _ => return "Expect an expression.".to_owned(),
+    };
+
+    // Locals are resolved against the names defined in each environment,
+    // outermost first, the globals are looked up by name anyway
+    let mut chain: Vec<Rc<RefCell<Environment>>> =
+        environment::chain(interpreter.environment.clone());
+    chain.pop();
+    let scopes: Vec<Vec<Symbol>> = chain
+        .iter()
+        .rev()
+        .map(|env| {
+            env.borrow()
+                .bindings()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        })
+        .collect();
+
+    let mut resolver: Resolver = Resolver::with_scopes(scopes);
+    resolver.resolve_expr(expr);
+    if let Some(diagnostic) = resolver.diagnostics.iter().find(|d| d.is_error()) {
+        return diagnostic.message.clone();
+    }
+
+    interpreter.locals.extend(resolver.locals.clone());
+    let value: Result<Object, LoxError> = interpreter.evaluate(expr);
+    for id in resolver.locals.keys() {
+        interpreter.locals.remove(id);
+    }
+
+    match value {
+        Ok(value) => stringify(&value),
+        Err(LoxError::RuntimeError { message, .. }) => message,
+        Err(_) => unreachable!(),
+    }
+}
@@ -0,0 +1,288 @@
+use std::{fmt, ops::Range};
+
+use crate::{
+    error::LoxError,
+    token::{Span, Token, TokenType},
+};
+
+// The parser stops after reporting this many (scan or parse) errors, as
+// later ones are likely caused by the first
+pub const MAX_ERRORS: usize = 20;
+pub const TOO_MANY_ERRORS: &str = "Too many errors, stopping here.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    // Found while scanning, parsing or resolving, the program doesn't run
+    Error,
+    RuntimeError,
+    // Doesn't stop the program from running
+    Warning,
+}
+
+// An error or warning, collected by each phase instead of being printed
+// right away. Callers (the CLI, the REPL, embedders) decide how to show it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // The offsets it covers, see `Token::offset`
+    pub span: Option<Range<usize>>,
+    // The token the diagnostic points at, if any
+    pub token: Option<Token>,
+    // The lint rule that reported it, see `lint::RULES`
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    // An error without a token, e.g. from the scanner
+    pub fn error(line: usize, column: usize, span: Range<usize>, message: &str) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Error,
+            message: message.to_owned(),
+            line: Some(line),
+            column: Some(column),
+            span: Some(span),
+            token: None,
+            code: None,
+        }
+    }
+
+    pub fn error_at(token: &Token, message: &str) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Error,
+            message: message.to_owned(),
+            line: Some(token.line),
+            column: Some(token.column),
+            span: Some(token.span()),
+            token: Some(token.clone()),
+            code: None,
+        }
+    }
+
+    pub fn warning(token: &Token, message: &str) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Warning,
+            ..Diagnostic::error_at(token, message)
+        }
+    }
+
+    // About a whole expression or statement, underlining all of it
+    pub fn error_in(span: Span, message: &str) -> Self {
+        Diagnostic::error(span.line, span.column, span.range(), message)
+    }
+
+    pub fn warning_in(span: Span, message: &str) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Warning,
+            ..Diagnostic::error_in(span, message)
+        }
+    }
+
+    pub fn runtime(error: LoxError) -> Self {
+        match error {
+            LoxError::RuntimeError { message, token } => Diagnostic {
+                kind: DiagnosticKind::RuntimeError,
+                message,
+                line: token.as_ref().map(|token| token.line),
+                column: token.as_ref().map(|token| token.column),
+                span: token.as_ref().map(Token::span),
+                token,
+                code: None,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.kind != DiagnosticKind::Warning
+    }
+
+    // A short suggestion for common mistakes
+    pub fn hint(&self) -> Option<&'static str> {
+        let hint: &str = match &self.message {
+            m if m.starts_with("Expect ';'") => "Add a ';' at the end of the statement.",
+            m if m.starts_with("Undefined variable") => "Declare it with 'var' before using it.",
+            m if m.starts_with("Already a variable") => "Assign to it without 'var' instead.",
+            m if m.starts_with("Operands must be numbers") => "Convert strings with 'num()'.",
+            m if m.starts_with("Stack overflow") => {
+                "Check that the recursion has a base case, or raise '--max-depth'."
+            }
+            m if m.starts_with("Local variable is never used") => {
+                "Start its name with '_' if that's intended."
+            }
+            m if m.starts_with("Block comment") => "Close it with '*/'.",
+            m if m.starts_with("Unterminated") => "Close the string with '\"'.",
+            _ => return None,
+        };
+
+        Some(hint)
+    }
+
+    // The message followed by the line it points at, with its span
+    // underlined, and a hint. `color` adds ANSI colors.
+    pub fn render(&self, sources: &Sources, color: bool) -> String {
+        let paint = |text: &str, code: &str| match color {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text.to_owned(),
+        };
+        let accent: &str = match self.kind {
+            DiagnosticKind::Warning => "1;33",
+            _ => "1;31",
+        };
+
+        let mut res: String = paint(&self.to_string(), accent);
+
+        let snippet = match (self.line, self.column, &self.span) {
+            (Some(line), Some(column), Some(span)) => sources
+                .line(span.start, line)
+                .map(|text| (line, column, span.len(), text)),
+            _ => None,
+        };
+        if let Some((line, column, len, text)) = snippet {
+            let number: String = line.to_string();
+            let gutter: String = " ".repeat(number.len());
+            // Keep tabs so the carets line up with the text above them
+            let padding: String = text
+                .chars()
+                .take(column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            // The span is in bytes, underline as many characters. One running
+            // past the line is only underlined up to its end.
+            let width: usize = text
+                .chars()
+                .skip(column - 1)
+                .scan(0, |bytes, c| {
+                    *bytes += c.len_utf8();
+                    Some(*bytes)
+                })
+                .take_while(|bytes| *bytes <= len)
+                .count();
+
+            res += &format!("\n{} {}", gutter, paint("|", "1;34"));
+            res += &format!("\n{} {}", paint(&number, "1;34"), paint("|", "1;34"));
+            res += &format!(" {}", text);
+            res += &format!("\n{} {}", gutter, paint("|", "1;34"));
+            res += &format!(" {}{}", padding, paint(&"^".repeat(width.max(1)), accent));
+
+            if let Some(hint) = self.hint() {
+                res += &format!(
+                    "\n{} {} {}",
+                    gutter,
+                    paint("=", "1;34"),
+                    paint("hint:", "1")
+                );
+                res += &format!(" {}", hint);
+            }
+        } else if let Some(hint) = self.hint() {
+            res += &format!("\n{} {}", paint("hint:", "1"), hint);
+        }
+
+        res
+    }
+
+    // One line of JSON for `--error-format=json`, e.g.
+    // `{"severity": "warning", "code": "shadowing", "message": "...",
+    // "file": "main.lox", "line": 3, "column": 7, "span": [21, 22]}`. The span
+    // is in bytes from the start of the file, `code` is the lint rule if any.
+    pub fn to_json(&self, sources: &Sources) -> String {
+        let severity: &str = match self.kind {
+            DiagnosticKind::Warning => "warning",
+            _ => "error",
+        };
+        let source: Option<&(usize, String, String)> = self
+            .span
+            .as_ref()
+            .and_then(|span| sources.source_of(span.start));
+
+        serde_json::json!({
+            "severity": severity,
+            "code": self.code,
+            "message": self.message,
+            "file": source.map(|(_, name, _)| name),
+            "line": self.line,
+            "column": self.column,
+            "span": self.span.as_ref().zip(source).map(|(span, (base, _, _))| {
+                [span.start - base, span.end - base]
+            }),
+        })
+        .to_string()
+    }
+
+    // e.g. `line 3, col 14`
+    fn position(&self) -> String {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("line {}, col {}", line, column),
+            (Some(line), None) => format!("line {}", line),
+            _ => "line 0".to_owned(),
+        }
+    }
+
+    // Where in the line the diagnostic points at, e.g. ` at 'foo'`
+    fn location(&self) -> String {
+        match &self.token {
+            Some(token) if token.token_type == TokenType::Eof => " at end".to_owned(),
+            Some(token) => format!(" at '{}'", token.lexeme),
+            None => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.kind, self.line) {
+            (DiagnosticKind::RuntimeError, Some(_)) => {
+                write!(f, "{}\n[{}]", self.message, self.position())
+            }
+            (DiagnosticKind::RuntimeError, None) => write!(f, "{}", self.message),
+            (kind, _) => {
+                write!(
+                    f,
+                    "[{}] {}{}: {}",
+                    self.position(),
+                    if *kind == DiagnosticKind::Warning {
+                        "Warning"
+                    } else {
+                        "Error"
+                    },
+                    self.location(),
+                    self.message
+                )?;
+                match self.code {
+                    Some(code) => write!(f, " [{}]", code),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// The sources run by a `Lox`, so diagnostics can show the lines they point at
+#[derive(Default)]
+pub struct Sources {
+    // Each source's name (e.g. its path) and text, after the offset of its
+    // first character
+    sources: Vec<(usize, String, String)>,
+}
+
+impl Sources {
+    pub fn add(&mut self, base: usize, name: &str, text: String) {
+        self.sources.push((base, name.to_owned(), text));
+    }
+
+    // The source containing `offset`
+    fn source_of(&self, offset: usize) -> Option<&(usize, String, String)> {
+        self.sources
+            .iter()
+            .rev()
+            .find(|(base, _, _)| *base <= offset)
+    }
+
+    // The text of `line` (starting at 1) in the source containing `offset`
+    pub fn line(&self, offset: usize, line: usize) -> Option<&str> {
+        let (_, _, text) = self.source_of(offset)?;
+        text.lines().nth(line.checked_sub(1)?)
+    }
+}
@@ -0,0 +1,51 @@
+// `rustlox doc`: a Markdown summary of a script's top-level functions and
+// classes (with their methods), each under its signature, followed by its
+// `///` doc comment if it has one.
+
+use crate::{stmt::Stmt, token::Token};
+
+pub fn to_markdown(title: &str, statements: &[Option<Stmt>]) -> String {
+    let mut res: String = format!("# {}\n", title);
+
+    for stmt in statements.iter().flatten() {
+        match stmt {
+            Stmt::Function { name, params, .. } => {
+                section(&mut res, "##", &signature("fn ", name, params), name);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let heading: String = match superclass.as_ref().and_then(|expr| expr.token()) {
+                    Some(superclass) => format!("class {} < {}", name.lexeme, superclass.lexeme),
+                    None => format!("class {}", name.lexeme),
+                };
+                section(&mut res, "##", &heading, name);
+
+                for method in methods {
+                    if let Stmt::Function { name, params, .. } = &**method {
+                        section(&mut res, "###", &signature("", name, params), name);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    res
+}
+
+// e.g. `fn add(a, b)`
+fn signature(keyword: &str, name: &Token, params: &[Token]) -> String {
+    let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+    format!("{}{}({})", keyword, name.lexeme, params.join(", "))
+}
+
+fn section(res: &mut String, level: &str, heading: &str, name: &Token) {
+    res.push_str(&format!("\n{} `{}`\n", level, heading));
+    if let Some(doc) = &name.doc {
+        res.push_str(&format!("\n{}\n", doc));
+    }
+}
@@ -1,102 +1,177 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 
-use crate::{error::LoxError, object::Object, token::Token};
+use crate::{
+    error::LoxError,
+    object::Object,
+    symbol::Symbol,
+    sync::{Rc, RefCell},
+    token::Token,
+};
 
 type OptPointer<T> = Option<Rc<RefCell<T>>>;
 
+// Most calls and blocks keep no more environments than this for reuse, see
+// `Pool`
+const POOL_SIZE: usize = 64;
+
 #[derive(Debug, Default, Clone)]
 pub struct Environment {
     pub enclosing: OptPointer<Environment>,
-    values: HashMap<String, Object>,
+    // Locals, indexed by the slot the resolver assigned them. Slots are
+    // handed out in declaration order, which is also the order they're defined.
+    // Named, for the debugger.
+    slots: Vec<(Symbol, Object)>,
+    // Globals are late bound (they can be used before they're declared), so
+    // the global environment still looks them up by name
+    values: HashMap<Symbol, Object>,
 }
 
 impl Environment {
     pub fn new(enclosing: OptPointer<Environment>) -> Self {
         Environment {
             enclosing,
+            slots: vec![],
             values: HashMap::new(),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Object) {
-        self.values.insert(name, value);
+    pub fn define(&mut self, name: Symbol, value: Object) {
+        match self.enclosing {
+            Some(_) => self.slots.push((name, value)),
+            None => {
+                self.values.insert(name, value);
+            }
+        }
     }
 
-    pub fn get(&self, var_name: &Token) -> Result<Object, LoxError> {
-        match self.values.get(&var_name.lexeme) {
-            Some(val) => Ok(val.to_owned()),
+    // Every variable defined here, in the order they were defined (by name
+    // for globals)
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        match self.enclosing {
+            Some(_) => self.slots.clone(),
             None => {
-                if let Some(env) = &self.enclosing {
-                    return env.borrow_mut().get(var_name);
-                }
-
-                Err(LoxError::RuntimeError {
-                    message: format!("Undefined variable '{}'.", var_name.lexeme),
-                    token: Some(var_name.to_owned()),
-                })
+                let mut bindings: Vec<(Symbol, Object)> = self
+                    .values
+                    .iter()
+                    .map(|(name, value)| (*name, value.clone()))
+                    .collect();
+                bindings.sort_by_cached_key(|(name, _)| name.as_str());
+                bindings
             }
         }
     }
 
+    // Every value held, for `memory::measure`
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.slots
+            .iter()
+            .map(|(_, value)| value)
+            .chain(self.values.values())
+    }
+
+    // How many variables fit without growing, for `memory::measure`
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity() + self.values.capacity()
+    }
+
+    // The global called `name`, if it's defined
+    pub fn lookup(&self, name: Symbol) -> Option<Object> {
+        self.values.get(&name).cloned()
+    }
+
+    pub fn get(&self, var_name: &Token) -> Result<Object, LoxError> {
+        match self.values.get(&var_name.symbol) {
+            Some(val) => Ok(val.to_owned()),
+            None => Err(LoxError::RuntimeError {
+                message: format!("Undefined variable '{}'.", var_name.lexeme),
+                token: Some(var_name.to_owned()),
+            }),
+        }
+    }
+
     pub fn assign(&mut self, var_name: &Token, value: Object) -> Result<(), LoxError> {
-        match self.values.contains_key(&var_name.lexeme) {
-            true => {
-                self.values.insert(var_name.lexeme.to_owned(), value);
+        match self.values.get_mut(&var_name.symbol) {
+            Some(val) => {
+                *val = value;
                 Ok(())
             }
-            false => {
-                if let Some(env) = &self.enclosing {
-                    let _ = env.borrow_mut().assign(var_name, value)?;
-                    return Ok(());
-                }
-
-                Err(LoxError::RuntimeError {
-                    message: format!("Undefined variable '{}'", var_name.lexeme),
-                    token: Some(var_name.to_owned()),
-                })
-            }
+            None => Err(LoxError::RuntimeError {
+                message: format!("Undefined variable '{}'.", var_name.lexeme),
+                token: Some(var_name.to_owned()),
+            }),
         }
     }
 }
 
-pub fn get_at(
-    environment: Rc<RefCell<Environment>>,
-    distance: usize,
-    name: String,
-) -> Result<Object, LoxError> {
-    if let Some(val) = ancestor(environment, distance)
-        .borrow_mut()
-        .values
-        .get(&name)
-    {
-        return Ok(val.clone());
+// Environments of finished calls and blocks, reused by the next ones rather
+// than allocated anew. Only those nothing captured (e.g. a closure declared
+// in the block) come back, so reusing one can't be observed.
+#[derive(Default)]
+pub struct Pool(Vec<Rc<RefCell<Environment>>>);
+
+impl Pool {
+    // An empty environment enclosed by `enclosing`
+    pub fn acquire(&mut self, enclosing: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        match self.0.pop() {
+            Some(environment) => {
+                environment.borrow_mut().enclosing = Some(enclosing);
+                environment
+            }
+            None => Rc::new(RefCell::new(Environment::new(Some(enclosing)))),
+        }
+    }
+
+    // Takes `environment` back once its call or block is over. Its slots keep
+    // their capacity.
+    pub fn release(&mut self, environment: Rc<RefCell<Environment>>) {
+        if Rc::strong_count(&environment) > 1 || self.0.len() >= POOL_SIZE {
+            return;
+        }
+
+        {
+            let mut env = environment.borrow_mut();
+            env.enclosing = None;
+            env.slots.clear();
+        }
+        self.0.push(environment);
     }
+}
 
-    Ok(Object::None)
+// Walks up by borrowing each enclosing environment in turn, rather than
+// cloning the handles along the way
+pub fn get_at(environment: &Rc<RefCell<Environment>>, distance: usize, slot: usize) -> Object {
+    match distance {
+        0 => environment.borrow().slots[slot].1.clone(),
+        _ => get_at(enclosing(&environment.borrow()), distance - 1, slot),
+    }
 }
 
 pub fn assign_at(
-    environment: Rc<RefCell<Environment>>,
+    environment: &Rc<RefCell<Environment>>,
     distance: usize,
-    name: Token,
+    slot: usize,
     value: Object,
-) -> Result<(), LoxError> {
-    ancestor(environment.clone(), distance)
-        .borrow_mut()
-        .values
-        .insert(name.lexeme, value);
-
-    Ok(())
+) {
+    match distance {
+        0 => environment.borrow_mut().slots[slot].1 = value,
+        _ => assign_at(enclosing(&environment.borrow()), distance - 1, slot, value),
+    }
 }
 
-fn ancestor(environment: Rc<RefCell<Environment>>, distance: usize) -> Rc<RefCell<Environment>> {
-    let mut env = Some(environment.clone());
+// `environment` and the ones enclosing it, innermost first. The last one is
+// the global environment.
+pub fn chain(environment: Rc<RefCell<Environment>>) -> Vec<Rc<RefCell<Environment>>> {
+    let mut chain: Vec<Rc<RefCell<Environment>>> = vec![];
+    let mut env: Option<Rc<RefCell<Environment>>> = Some(environment);
 
-    for _ in 0..distance {
-        env = env.unwrap().borrow_mut().enclosing.clone();
+    while let Some(current) = env {
+        env = current.borrow().enclosing.clone();
+        chain.push(current);
     }
 
-    env.unwrap()
+    chain
+}
+
+fn enclosing(environment: &Environment) -> &Rc<RefCell<Environment>> {
+    environment.enclosing.as_ref().unwrap()
 }
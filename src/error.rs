@@ -10,4 +10,8 @@ pub enum LoxError {
     Return {
         value: Object,
     },
+    Break,
+    Continue,
+    // Ends the program where it is, e.g. the debugger's `quit`
+    Exit,
 }
@@ -1,53 +1,191 @@
-use crate::token::{Literal, Token};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(strum_macros::Display, Debug, Clone, Eq, PartialEq, Hash)]
+use crate::token::{Literal, Span, Token};
+
+// Identifies an expression the resolver resolves (variables, assignments,
+// `this` and `super`). Ids are unique across everything parsed by the
+// process, so the prelude, scripts and REPL lines never share one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(usize);
+
+impl ExprId {
+    pub fn fresh() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ExprId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// Serialized with its variant in a `type` field, see `ast::to_json`
+#[derive(strum_macros::Display, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
 pub enum Expr {
     Assign {
+        // Only meaningful to the process that parsed it, so left out of the
+        // JSON and allocated anew when reading it back
+        #[serde(skip, default = "ExprId::fresh")]
+        id: ExprId,
         name: Token,
         value: Box<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Call {
         callee: Box<Expr>,
         paren: Token,
         arguments: Vec<Box<Expr>>,
+        #[serde(default)]
+        span: Span,
     },
     Get {
+        // Keys the method cache, see `Interpreter::method_cache`. Left out
+        // of the JSON like the resolver's ids.
+        #[serde(skip, default = "ExprId::fresh")]
+        id: ExprId,
         object: Box<Expr>,
         name: Token,
+        #[serde(default)]
+        span: Span,
     },
     Grouping {
         expression: Box<Expr>,
+        #[serde(default)]
+        span: Span,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        #[serde(default)]
+        span: Span,
+    },
+    IndexSet {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        #[serde(default)]
+        span: Span,
+    },
+    List {
+        bracket: Token,
+        elements: Vec<Box<Expr>>,
+        #[serde(default)]
+        span: Span,
     },
     Literal {
         value: Literal,
+        #[serde(default)]
+        span: Span,
+    },
+    Map {
+        brace: Token,
+        entries: Vec<(Box<Expr>, Box<Expr>)>,
+        #[serde(default)]
+        span: Span,
     },
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Set {
         object: Box<Expr>,
         name: Token,
         value: Box<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Super {
+        // Only meaningful to the process that parsed it, so left out of the
+        // JSON and allocated anew when reading it back
+        #[serde(skip, default = "ExprId::fresh")]
+        id: ExprId,
         keyword: Token,
         method: Token,
+        #[serde(default)]
+        span: Span,
     },
     This {
+        // Only meaningful to the process that parsed it, so left out of the
+        // JSON and allocated anew when reading it back
+        #[serde(skip, default = "ExprId::fresh")]
+        id: ExprId,
         keyword: Token,
+        #[serde(default)]
+        span: Span,
     },
     Unary {
         operator: Token,
         right: Box<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Variable {
+        // Only meaningful to the process that parsed it, so left out of the
+        // JSON and allocated anew when reading it back
+        #[serde(skip, default = "ExprId::fresh")]
+        id: ExprId,
         name: Token,
+        #[serde(default)]
+        span: Span,
     },
 }
+
+impl Expr {
+    // Where the expression is in the source, see `Span`
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Get { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::IndexSet { span, .. }
+            | Expr::List { span, .. }
+            | Expr::Literal { span, .. }
+            | Expr::Map { span, .. }
+            | Expr::Logical { span, .. }
+            | Expr::Set { span, .. }
+            | Expr::Super { span, .. }
+            | Expr::This { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Variable { span, .. } => *span,
+        }
+    }
+
+    // The leftmost token of the expression, `None` for literals
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Expr::Assign { name, .. } | Expr::Variable { name, .. } => Some(name),
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => {
+                left.token().or(Some(operator))
+            }
+            Expr::Call { callee, paren, .. } => callee.token().or(Some(paren)),
+            Expr::Get { object, name, .. } | Expr::Set { object, name, .. } => {
+                object.token().or(Some(name))
+            }
+            Expr::Grouping { expression, .. } => expression.token(),
+            Expr::Index {
+                object, bracket, ..
+            }
+            | Expr::IndexSet {
+                object, bracket, ..
+            } => object.token().or(Some(bracket)),
+            Expr::List { bracket, .. } => Some(bracket),
+            Expr::Map { brace, .. } => Some(brace),
+            Expr::Literal { .. } => None,
+            Expr::Super { keyword, .. } | Expr::This { keyword, .. } => Some(keyword),
+            Expr::Unary { operator, .. } => Some(operator),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// A C API over `Lox`, for embedding the interpreter in C or C++ programs
+// through the `cdylib` (`librustlox.so`), see `include/rustlox.h`:
+//
+//     Lox *lox = lox_new();
+//     LoxValue result;
+//     if (!lox_run(lox, "return 1 + 2;", &result)) { /* result is the error */ }
+//     lox_free(lox);
+//
+// Values cross as the plugins' `LoxValue`s (nil, booleans, numbers and
+// strings), and natives have the signature of a plugin's functions. The
+// ownership rules:
+//
+// - A `Lox *` comes from `lox_new` and is freed once, by `lox_free`. It's
+//   not thread-safe, without the `sync` feature its values can't even be
+//   moved to another thread.
+// - Strings passed in are NUL-terminated and only borrowed for the call.
+// - A string in a result points into the interpreter, it's valid until
+//   the next call on the same `Lox *`, copy it to keep it.
+//
+// Passing a null or freed `Lox *` is undefined behavior, as in C.
+
+// The rules above are the functions' safety section
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, CStr};
+
+use crate::{
+    callable::LoxCallable,
+    interpreter::stringify,
+    lox::Lox,
+    object::Object,
+    plugin::{self, LoxValue, PluginFn},
+};
+
+// What a `Lox *` points to
+pub struct LoxHandle {
+    lox: Lox,
+    // The last result, whose string `LoxValue`s point into
+    result: Object,
+}
+
+impl LoxHandle {
+    // Keeps `outcome`, a value or an error message, and points `*result` to
+    // it. Returns whether it's a value.
+    unsafe fn set_result(
+        &mut self,
+        outcome: Result<Object, String>,
+        result: *mut LoxValue,
+    ) -> bool {
+        let (ok, value): (bool, Object) = match outcome {
+            Ok(value) if plugin::value(&value).is_some() => (true, value),
+            Ok(value) => (
+                false,
+                Object::String(
+                    format!(
+                        "Can't pass '{}' to C, only nil, booleans, numbers and strings.",
+                        stringify(&value)
+                    )
+                    .into(),
+                ),
+            ),
+            Err(message) => (false, Object::String(message.into())),
+        };
+
+        self.result = value;
+        if !result.is_null() {
+            // Always some, see above
+            *result = plugin::value(&self.result).unwrap();
+        }
+        ok
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut LoxHandle {
+    Box::into_raw(Box::new(LoxHandle {
+        lox: Lox::new(),
+        result: Object::None,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(lox: *mut LoxHandle) {
+    if !lox.is_null() {
+        drop(Box::from_raw(lox));
+    }
+}
+
+// Runs `source`. On success `*result` is the value of its top-level
+// `return` (nil without one), otherwise its diagnostics.
+#[no_mangle]
+pub unsafe extern "C" fn lox_run(
+    lox: *mut LoxHandle,
+    source: *const c_char,
+    result: *mut LoxValue,
+) -> bool {
+    let handle: &mut LoxHandle = &mut *lox;
+
+    let outcome: Result<Object, String> = match CStr::from_ptr(source).to_str() {
+        Ok(source) => handle.lox.run_source(source).map_err(|diagnostics| {
+            let rendered: Vec<String> = diagnostics.iter().map(|d| handle.lox.render(d)).collect();
+            rendered.join("\n")
+        }),
+        Err(_) => Err("The source isn't valid UTF-8.".to_owned()),
+    };
+    handle.set_result(outcome, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_get_global(
+    lox: *mut LoxHandle,
+    name: *const c_char,
+    result: *mut LoxValue,
+) -> bool {
+    let handle: &mut LoxHandle = &mut *lox;
+    let name = CStr::from_ptr(name).to_string_lossy();
+
+    let value: Option<Object> = handle.lox.interpreter().borrow().get_global(&name);
+    let outcome = value.ok_or_else(|| format!("Undefined variable '{}'.", name));
+    handle.set_result(outcome, result)
+}
+
+// Defines the global function `name`, see `plugin` for how `function` gets
+// its arguments and returns
+#[no_mangle]
+pub unsafe extern "C" fn lox_register_native(
+    lox: *mut LoxHandle,
+    name: *const c_char,
+    arity: usize,
+    function: PluginFn,
+) {
+    let handle: &mut LoxHandle = &mut *lox;
+    let name: String = CStr::from_ptr(name).to_string_lossy().into_owned();
+
+    let callable = LoxCallable::Plugin {
+        name: name.clone(),
+        arity,
+        function,
+    };
+    handle
+        .lox
+        .interpreter()
+        .borrow_mut()
+        .set_global(&name, Object::Callable(callable));
+}
@@ -0,0 +1,171 @@
+// Rust values handed to scripts as opaque instances of a class the host
+// program registers, e.g. file handles or game entities:
+//
+//     interpreter
+//         .register_class::<Counter>("Counter")
+//         .constructor(0, |_, _| Ok(Counter::default()))
+//         .method("add", 1, |counter, _, arguments| {
+//             counter.total += f64::from_lox(&arguments[0])?;
+//             Ok(Object::None)
+//         })
+//         .method("total", 0, |counter, _, _| Ok(counter.total.to_lox()))
+//         .build();
+//
+// Scripts only see the methods: fields can't be read or set. Without a
+// constructor, instances only come from the host, see `Interpreter::wrap`,
+// and `Foreign::with` gets the Rust value back out of one.
+//
+// A method borrows its receiver while it runs, so it must not call back into
+// Lox code that calls another method of the same object.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+};
+
+use crate::{
+    error::LoxError,
+    interpreter::Interpreter,
+    native,
+    object::Object,
+    symbol::Symbol,
+    sync::{MaybeSend, Rc, RefCell},
+};
+
+#[cfg(not(feature = "sync"))]
+pub type AnyValue = dyn Any;
+#[cfg(feature = "sync")]
+pub type AnyValue = dyn Any + Send + Sync;
+
+// A method with the Rust type of its receiver erased
+pub trait ForeignMethod:
+    Fn(&Foreign, &mut Interpreter, &[Object]) -> Result<Object, LoxError> + MaybeSend + 'static
+{
+}
+
+impl<F> ForeignMethod for F where
+    F: Fn(&Foreign, &mut Interpreter, &[Object]) -> Result<Object, LoxError> + MaybeSend + 'static
+{
+}
+
+pub struct ForeignClass {
+    pub name: String,
+    methods: HashMap<Symbol, (usize, Rc<dyn ForeignMethod>)>,
+}
+
+impl ForeignClass {
+    pub fn method_names(&self) -> Vec<Symbol> {
+        self.methods.keys().copied().collect()
+    }
+}
+
+// An instance of a `ForeignClass`. Copies share the Rust value.
+#[derive(Clone)]
+pub struct Foreign {
+    pub class: Rc<ForeignClass>,
+    pub value: Rc<RefCell<AnyValue>>,
+}
+
+impl Foreign {
+    // Calls `f` with the Rust value, if it's a `T`
+    pub fn with<T: Any, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut value = self.value.borrow_mut();
+        value.downcast_mut::<T>().map(f)
+    }
+
+    // The method `name`, bound to this instance
+    pub fn method(&self, name: Symbol) -> Option<Object> {
+        let (arity, method) = self.class.methods.get(&name)?;
+        let (this, method) = (self.clone(), method.clone());
+
+        Some(native::native(*arity, move |interpreter, arguments| {
+            method(&this, interpreter, arguments)
+        }))
+    }
+}
+
+impl fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+// See `Interpreter::register_class`
+pub struct ClassBuilder<'a, T> {
+    interpreter: &'a mut Interpreter,
+    class: ForeignClass,
+    // The global native creating instances, if scripts can
+    constructor: Option<Object>,
+    value: PhantomData<T>,
+}
+
+impl<'a, T: Any + MaybeSend> ClassBuilder<'a, T> {
+    pub fn new(interpreter: &'a mut Interpreter, name: &str) -> Self {
+        ClassBuilder {
+            interpreter,
+            class: ForeignClass {
+                name: name.to_owned(),
+                methods: HashMap::new(),
+            },
+            constructor: None,
+            value: PhantomData,
+        }
+    }
+
+    // Lets scripts create instances by calling the class, `body` makes the
+    // Rust value out of the arguments
+    pub fn constructor(
+        mut self,
+        arity: usize,
+        body: impl Fn(&mut Interpreter, &[Object]) -> Result<T, LoxError> + MaybeSend + 'static,
+    ) -> Self {
+        let constructor: Object = native::native(arity, move |interpreter, arguments| {
+            let value: T = body(interpreter, arguments)?;
+            interpreter.wrap(value)
+        });
+        self.constructor = Some(constructor);
+        self
+    }
+
+    pub fn method(
+        mut self,
+        name: &str,
+        arity: usize,
+        body: impl Fn(&mut T, &mut Interpreter, &[Object]) -> Result<Object, LoxError>
+            + MaybeSend
+            + 'static,
+    ) -> Self {
+        let method = move |this: &Foreign, interpreter: &mut Interpreter, arguments: &[Object]| {
+            let mut value = this.value.borrow_mut();
+            // The class only ever wraps `T`s
+            body(value.downcast_mut::<T>().unwrap(), interpreter, arguments)
+        };
+        self.class
+            .methods
+            .insert(Symbol::intern(name), (arity, Rc::new(method)));
+        self
+    }
+
+    // Registers the class, replacing the one for `T` if any
+    pub fn build(self) {
+        let name: Symbol = Symbol::intern(&self.class.name);
+        self.interpreter
+            .foreign_classes
+            .insert(TypeId::of::<T>(), Rc::new(self.class));
+
+        if let Some(constructor) = self.constructor {
+            self.interpreter
+                .globals
+                .borrow_mut()
+                .define(name, constructor);
+        }
+    }
+}
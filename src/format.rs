@@ -0,0 +1,227 @@
+// Format strings for the `format()` and `printf()` natives.
+//
+// A placeholder looks like `{[index][:[[fill]align][0][width][.precision][type]]}`:
+//  - `index` picks an argument explicitly, otherwise arguments are consumed in order
+//  - `align` is one of `<`, `>`, `^` (numbers are right-aligned by default)
+//  - `0` pads numbers with zeros after the sign
+//  - `precision` is the number of decimals, or the max. length of a string
+//  - `type` is one of `x`, `X`, `o`, `b` (integers only) or `e` (scientific)
+// `{{` and `}}` are literal braces.
+
+use std::{iter::Peekable, str::Chars};
+
+use crate::{error::LoxError, interpreter, native, object::Object, util};
+
+// Widths and precisions past this fail, rather than padding a string out
+// to gigabytes
+const MAX_WIDTH: usize = 1024;
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+pub fn format(template: &str, arguments: &[Object]) -> Result<String, LoxError> {
+    let mut res = String::new();
+    let mut chars: Peekable<Chars> = template.chars().peekable();
+    let mut next_arg: usize = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                res.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                res.push('}');
+            }
+            '}' => return Err(native::error("Unmatched '}' in format string.".to_owned())),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err(native::error("Unclosed '{' in format string.".to_owned()))
+                        }
+                    }
+                }
+
+                let (index, spec) = match placeholder.split_once(':') {
+                    Some((index, spec)) => (index, parse_spec(spec)?),
+                    None => (placeholder.as_str(), Spec::default()),
+                };
+
+                let index: usize = if index.is_empty() {
+                    next_arg += 1;
+                    next_arg - 1
+                } else {
+                    index.parse::<usize>().map_err(|_| {
+                        native::error(format!("Invalid placeholder '{{{}}}'.", placeholder))
+                    })?
+                };
+
+                match arguments.get(index) {
+                    Some(value) => res.push_str(&render(value, &spec)?),
+                    None => {
+                        return Err(native::error(format!(
+                            "Format string refers to argument {} but only {} were given.",
+                            index,
+                            arguments.len()
+                        )))
+                    }
+                }
+            }
+            _ => res.push(c),
+        }
+    }
+
+    Ok(res)
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, LoxError> {
+    let invalid = || native::error(format!("Invalid format spec ':{}'.", spec));
+    let mut res = Spec::default();
+    let mut chars: Peekable<Chars> = spec.chars().peekable();
+
+    // Fill character, only allowed in front of an alignment
+    let mut lookahead = spec.chars();
+    if let (Some(fill), Some(align @ ('<' | '>' | '^'))) = (lookahead.next(), lookahead.next()) {
+        res.fill = Some(fill);
+        res.align = Some(align);
+        chars.next();
+        chars.next();
+    } else if let Some(align @ ('<' | '>' | '^')) = chars.peek().copied() {
+        res.align = Some(align);
+        chars.next();
+    }
+
+    if chars.peek() == Some(&'0') {
+        res.zero = true;
+        chars.next();
+    }
+
+    res.width = take_number(&mut chars, "width")?.unwrap_or(0);
+
+    if chars.peek() == Some(&'.') {
+        chars.next();
+        res.precision = Some(take_number(&mut chars, "precision")?.ok_or_else(invalid)?);
+    }
+
+    if let Some(kind @ ('x' | 'X' | 'o' | 'b' | 'e')) = chars.peek().copied() {
+        res.kind = Some(kind);
+        chars.next();
+    }
+
+    match chars.next() {
+        Some(_) => Err(invalid()),
+        None => Ok(res),
+    }
+}
+
+// `None` if there are no digits, `what` names the number in errors
+fn take_number(chars: &mut Peekable<Chars>, what: &str) -> Result<Option<usize>, LoxError> {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    match digits.parse::<usize>() {
+        Ok(number) if number <= MAX_WIDTH => Ok(Some(number)),
+        _ => Err(native::error(format!(
+            "Format {} {} is too large, the maximum is {}.",
+            what, digits, MAX_WIDTH
+        ))),
+    }
+}
+
+fn render(value: &Object, spec: &Spec) -> Result<String, LoxError> {
+    let body: String = match (value, spec.kind) {
+        (Object::Number(val), Some(kind @ ('x' | 'X' | 'o' | 'b'))) => {
+            if val.fract() != 0.0 {
+                return Err(native::error(format!(
+                    "Format type '{}' requires an integer, got {}.",
+                    kind, val
+                )));
+            }
+
+            let magnitude: u64 = (*val as i64).unsigned_abs();
+            let digits: String = match kind {
+                'x' => format!("{:x}", magnitude),
+                'X' => format!("{:X}", magnitude),
+                'o' => format!("{:o}", magnitude),
+                _ => format!("{:b}", magnitude),
+            };
+
+            if *val < 0.0 {
+                format!("-{}", digits)
+            } else {
+                digits
+            }
+        }
+        (Object::Number(val), Some('e')) => match spec.precision {
+            Some(precision) => format!("{:.*e}", precision, val),
+            None => format!("{:e}", val),
+        },
+        (Object::Number(val), None) => match spec.precision {
+            Some(precision) => util::format_fixed(*val, precision),
+            None => interpreter::stringify(value),
+        },
+        (_, Some(kind)) => {
+            return Err(native::error(format!(
+                "Format type '{}' requires a number.",
+                kind
+            )))
+        }
+        (_, None) => {
+            let text: String = interpreter::stringify(value);
+            match spec.precision {
+                Some(precision) => text.chars().take(precision).collect(),
+                None => text,
+            }
+        }
+    };
+
+    Ok(pad(body, spec, matches!(value, Object::Number(_))))
+}
+
+fn pad(body: String, spec: &Spec, is_number: bool) -> String {
+    let len: usize = body.chars().count();
+    if len >= spec.width {
+        return body;
+    }
+    let missing: usize = spec.width - len;
+
+    // Zero padding goes between the sign and the digits
+    if spec.zero && is_number && spec.align.is_none() {
+        return match body.strip_prefix('-') {
+            Some(digits) => format!("-{}{}", "0".repeat(missing), digits),
+            None => format!("{}{}", "0".repeat(missing), body),
+        };
+    }
+
+    let fill: String = spec.fill.unwrap_or(' ').to_string();
+    let default_align: char = if is_number { '>' } else { '<' };
+
+    match spec.align.unwrap_or(default_align) {
+        '>' => format!("{}{}", fill.repeat(missing), body),
+        '^' => format!(
+            "{}{}{}",
+            fill.repeat(missing / 2),
+            body,
+            fill.repeat(missing - missing / 2)
+        ),
+        _ => format!("{}{}", body, fill.repeat(missing)),
+    }
+}
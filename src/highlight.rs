@@ -0,0 +1,97 @@
+// `rustlox highlight`: the script as a standalone HTML page, colored the way
+// the REPL colors it (keywords, strings, numbers) plus comments. Everything
+// but the tokens' coloring is kept as written.
+
+use crate::{
+    scanner::Scanner,
+    token::{Token, TokenType},
+};
+
+const STYLE: &str = "\
+body { margin: 0; background: #fafafa; }
+pre { margin: 0; padding: 1em; font: 14px/1.5 monospace; color: #24292e; }
+.keyword { color: #a626a4; font-weight: bold; }
+.string { color: #50a14f; }
+.number { color: #0184bc; }
+.comment { color: #a0a1a7; font-style: italic; }
+";
+
+pub fn to_html(title: &str, source: &str) -> String {
+    let mut code = String::new();
+    // Where the text not yet added to `code` starts
+    let mut last: usize = 0;
+
+    for result in Scanner::new(source.to_owned(), 0) {
+        let token: Token = match result {
+            Ok(token) if token.token_type == TokenType::Eof => break,
+            Ok(token) => token,
+            // A string left open runs to the end, as in the REPL
+            Err(error) if error.message == "Unterminated" => {
+                add_gap(&mut code, &source[last..error.span.start]);
+                add(&mut code, Some("string"), &source[error.span.start..]);
+                last = source.len();
+                break;
+            }
+            Err(_) => continue,
+        };
+        // Between two tokens there's only whitespace and comments, the
+        // characters the scanner rejected aside
+        add_gap(&mut code, &source[last..token.offset]);
+
+        let end: usize = token.offset + token.lexeme.len();
+        add(&mut code, class_of(&token), &source[token.offset..end]);
+        last = end;
+    }
+    add_gap(&mut code, &source[last..]);
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{}</style>\n</head>\n<body>\n<pre><code>{}</code></pre>\n</body>\n</html>",
+        escape(title),
+        STYLE,
+        code
+    )
+}
+
+fn class_of(token: &Token) -> Option<&'static str> {
+    match token.token_type {
+        TokenType::String | TokenType::Bytes => Some("string"),
+        TokenType::Number => Some("number"),
+        TokenType::Identifier => None,
+        // Keywords are the words that don't scan as identifiers
+        _ if Scanner::text2token(&token.lexeme) == token.token_type => Some("keyword"),
+        _ => None,
+    }
+}
+
+// Whitespace as is, the rest (from its first to its last character) as a comment
+fn add_gap(code: &mut String, gap: &str) {
+    let trimmed: &str = gap.trim();
+    if !trimmed.starts_with('/') {
+        add(code, None, gap);
+        return;
+    }
+
+    let start: usize = gap.len() - gap.trim_start().len();
+    add(code, None, &gap[..start]);
+    add(code, Some("comment"), trimmed);
+    add(code, None, &gap[start + trimmed.len()..]);
+}
+
+fn add(code: &mut String, class: Option<&str>, text: &str) {
+    match class {
+        Some(class) => code.push_str(&format!(
+            "<span class=\"{}\">{}</span>",
+            class,
+            escape(text)
+        )),
+        None => code.push_str(&escape(text)),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
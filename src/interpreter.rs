@@ -1,565 +1,1601 @@
+use indexmap::IndexMap;
 use std::{
-    cell::RefCell,
-    collections::HashMap,
-    rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use crate::{
-    callable::LoxCallable,
+    callable::{Declaration, LoxCallable},
+    capabilities::Capabilities,
     class::{LoxClass, LoxInstance},
+    compiler::StmtFn,
+    coverage::Coverage,
+    debugger::Debugger,
     environment::{self, Environment},
     error::LoxError,
-    expr::Expr,
-    lox::Lox,
+    expr::{Expr, ExprId},
+    foreign::{ClassBuilder, Foreign, ForeignClass},
+    memory,
+    native::{self, Builtin, NativeFn},
     object::Object,
+    profiler::Profiler,
     stmt::Stmt,
+    symbol::Symbol,
+    sync::{MaybeSend, Rc, RefCell, Weak},
+    time::{Instant, SystemTime, UNIX_EPOCH},
     token::{Literal, Token, TokenType},
+    util::{self, escape_bytes, Rng},
+    visitor::{ExprVisitor, StmtVisitor},
 };
 
 type Pointer<T> = Rc<RefCell<T>>;
 
-#[derive(Default)]
+// Where the script's output goes, see `Interpreter::output`
+#[cfg(not(feature = "sync"))]
+pub type Output = Box<dyn Write>;
+#[cfg(feature = "sync")]
+pub type Output = Box<dyn Write + Send + Sync>;
+
+// Where `readLine` and `readAll` read from, see `Interpreter::read_input`
+#[cfg(not(feature = "sync"))]
+pub type Input = Box<dyn BufRead>;
+#[cfg(feature = "sync")]
+pub type Input = Box<dyn BufRead + Send + Sync>;
+
 pub struct Interpreter {
     pub globals: Pointer<Environment>,
     pub environment: Pointer<Environment>,
-    pub locals: HashMap<Expr, usize>,
+    // The environments of the blocks and calls `environment` is nested in,
+    // innermost last, whose values are still in use. Restored as they end.
+    pub frames: Vec<Pointer<Environment>>,
+    // Reused environments for calls and blocks
+    pub env_pool: environment::Pool,
+    // The (depth, slot) of every local variable, filled in by the resolver
+    pub locals: HashMap<ExprId, (usize, usize)>,
+    // Inline caches of property accesses (`Expr::Get`): the class the method
+    // was last found on, and the method, which is reused as long as the
+    // instances accessed there have that class. Weak, so a freed class's
+    // address can't be reused by another class while it's cached.
+    method_cache: HashMap<ExprId, (Weak<RefCell<LoxClass>>, LoxCallable)>,
+    pub rng: Rng,
+    // Set through an `InterruptHandle`, e.g. by the REPL's SIGINT handler,
+    // polled by `Budget` and long-running natives
+    pub interrupt: Arc<AtomicBool>,
+    // Whether stdin belongs to the REPL rather than the script
+    pub repl: bool,
+    // Set by `rustlox watch`, see `declare_class`
+    pub hot_reload: bool,
+    // Shared libraries loaded by `plugin::load`, unloaded along with the
+    // interpreter, whose natives call into them
+    #[cfg(not(target_arch = "wasm32"))]
+    pub plugins: Vec<libloading::Library>,
+    // Registered by the host, keyed by the Rust type their instances wrap,
+    // see `foreign`
+    pub foreign_classes: HashMap<TypeId, Rc<ForeignClass>>,
+    // Stdout and stderr unless a host program captures them, see
+    // `Lox::set_stdout`
+    pub stdout: Output,
+    pub stderr: Output,
+    // What natives may do, see `capabilities`
+    pub capabilities: Capabilities,
+    // Stdin if `None`, see `Lox::set_stdin`
+    pub stdin: Option<Input>,
+    // The runtime async natives block on, while `Lox::run_async` runs
+    #[cfg(feature = "async")]
+    pub runtime: Option<tokio::runtime::Handle>,
+    // Lox function calls in progress, deeper calls fail with a stack overflow
+    pub call_depth: usize,
+    pub max_call_depth: usize,
+    // The most `setMaxCallDepth` may raise `max_call_depth` to, set by the
+    // host (e.g. `--max-depth`). Calls grow the stack as deep as they go,
+    // which the memory limit doesn't count.
+    pub call_depth_cap: usize,
+    pub budget: Budget,
+    // Set by `--profile`
+    pub profiler: Option<Profiler>,
+    // Set by `--coverage`
+    pub coverage: Option<Coverage>,
+    // Set by `--trace`
+    pub trace: bool,
+    // Set by `rustlox debug`
+    pub debugger: Option<Debugger>,
+}
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+// Caps how much a single `interpret` call (a script, a REPL line) may run,
+// and how much memory the program may use, for untrusted scripts and
+// accidental infinite loops
+#[derive(Default)]
+pub struct Budget {
+    pub max_statements: Option<u64>,
+    pub time_limit: Option<Duration>,
+    // In bytes, of the values this interpreter can reach, see `memory`.
+    // Checked after every statement, a single statement can still allocate
+    // past it.
+    pub max_memory: Option<usize>,
+    // Set once there's an `InterruptHandle`, see
+    // `Interpreter::interrupt_handle`
+    pub interrupt: Option<Arc<AtomicBool>>,
+    statements: u64,
+    deadline: Option<Instant>,
+    // What the reachable values took when last measured, and what was
+    // allocated since
+    memory_used: usize,
+    allocated: usize,
+}
+
+impl Budget {
+    pub fn start(&mut self) {
+        self.statements = 0;
+        self.deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        // An interrupt while nothing ran is dropped
+        if let Some(interrupt) = &self.interrupt {
+            interrupt.store(false, Ordering::SeqCst);
+        }
+    }
+
+    // How many statements the current (or last) `interpret` call ran
+    pub fn statements(&self) -> u64 {
+        self.statements
+    }
+
+    // When `--timeout` runs out for the current `interpret` call, if set
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // Called before every statement, tells whether the slower `check` is due
+    #[inline]
+    pub fn spend(&mut self) -> bool {
+        self.statements += 1;
+
+        // Reading the clock on every statement would be too slow, the
+        // interrupt flag is a plain load
+        self.max_statements.is_some_and(|max| self.statements > max)
+            || self
+                .interrupt
+                .as_ref()
+                .is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+            || (self.deadline.is_some() && self.statements.is_multiple_of(1024))
+    }
+
+    // Counts `bytes` as just allocated, while there's a memory limit
+    #[inline]
+    pub fn allocate(&mut self, bytes: usize) {
+        if self.max_memory.is_some() {
+            self.allocated += bytes;
+        }
+    }
+
+    // Whether enough was allocated since the last measurement to maybe be
+    // over the limit. Measuring walks every reachable value, so it's also
+    // spaced out by a sixteenth of the limit.
+    #[inline]
+    fn memory_check_due(&self) -> bool {
+        self.max_memory
+            .is_some_and(|max| self.allocated > max.saturating_sub(self.memory_used).max(max / 16))
+    }
+
+    #[cold]
+    pub fn check(&self) -> Result<(), LoxError> {
+        if let Some(interrupt) = &self.interrupt {
+            if interrupt.swap(false, Ordering::SeqCst) {
+                return Err(native::error("Interrupted.".to_owned()));
+            }
+        }
+
+        if let Some(max) = self.max_statements.filter(|max| self.statements > *max) {
+            return Err(native::error(format!(
+                "Execution budget exceeded: ran more than {} statements.",
+                max
+            )));
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(native::error(format!(
+                "Execution budget exceeded: ran for more than {} ms.",
+                self.time_limit.unwrap().as_millis()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Stops the code an interpreter runs, from any thread, e.g. a GUI's stop
+// button. See `Interpreter::interrupt_handle`.
+#[derive(Clone, Debug)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    // The running code fails with "Interrupted." before its next statement,
+    // or while `sleep()` waits. Returns whether an interrupt was already
+    // pending, i.e. the code hasn't stopped yet.
+    pub fn interrupt(&self) -> bool {
+        self.0.swap(true, Ordering::SeqCst)
+    }
+}
+
+#[cold]
+fn memory_exceeded(max: usize) -> LoxError {
+    native::error(format!(
+        "Memory limit exceeded: using more than {:.1} MB.",
+        max as f64 / (1024.0 * 1024.0)
+    ))
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         let globals = Rc::new(RefCell::new(Environment::new(None)));
 
-        let clock: Object = Object::Callable(LoxCallable::Native {
-            arity: 0,
-            body: Box::new(|_arguments: &Vec<Object>| {
-                Object::Number(
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs_f64(),
-                )
-            }),
+        let clock: Object = native::native(0, |_, _| {
+            Ok(Object::Number(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs_f64(),
+            ))
         });
-        globals.borrow_mut().define("clock".to_string(), clock);
+        globals.borrow_mut().define(Symbol::intern("clock"), clock);
+
+        // `Math.sqrt(x)`, `Math.PI`, ...
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("Math"), native::math_module());
+
+        for (name, arity, body) in [
+            ("random", 0, native::random as Builtin),
+            ("randomInt", 2, native::random_int),
+            ("randomSeed", 1, native::random_seed),
+            ("shuffle", 1, native::shuffle),
+            ("num", 1, native::num),
+            ("str", 1, native::str),
+            ("sum", 1, native::sum),
+            ("min", 1, native::min),
+            ("max", 1, native::max),
+            ("avg", 1, native::avg),
+            ("parseInt", 2, native::parse_int),
+            ("ord", 1, native::ord),
+            ("chr", 1, native::chr),
+            ("codePoints", 1, native::code_points),
+            ("encode", 2, native::encode),
+            ("jsonParse", 1, native::json_parse),
+            ("jsonStringify", 1, native::json_stringify),
+            ("readFileBytes", 1, native::read_file_bytes),
+            ("writeFileBytes", 2, native::write_file_bytes),
+            ("readLine", 0, native::read_line),
+            ("readAll", 0, native::read_all),
+            ("getenv", 1, native::getenv),
+            ("setenv", 2, native::setenv),
+            ("osName", 0, native::os_name),
+            ("cpuCount", 0, native::cpu_count),
+            ("hostname", 0, native::hostname),
+            ("cwd", 0, native::cwd),
+            ("chdir", 1, native::chdir),
+            ("exec", 2, native::exec),
+            ("execStream", 3, native::exec_stream),
+            ("sleep", 1, native::sleep),
+            ("panic", 1, native::panic),
+            ("hash", 1, native::hash),
+            ("clone", 1, native::clone),
+            ("freeze", 1, native::freeze),
+            ("uuid", 0, native::uuid),
+            ("uuidV7", 0, native::uuid_v7),
+            ("sha256", 1, native::sha256),
+            ("sha1", 1, native::sha1),
+            ("md5", 1, native::md5),
+            ("hmacSha256", 2, native::hmac_sha256),
+            ("monotonicClock", 0, native::monotonic_clock),
+            ("stopwatch", 0, native::stopwatch),
+            ("setMaxCallDepth", 1, native::set_max_call_depth),
+            ("inspect", 0, native::inspect),
+            ("loadPlugin", 1, native::load_plugin),
+            ("saveState", 1, native::save_state),
+            ("loadState", 1, native::load_state),
+        ] {
+            globals
+                .borrow_mut()
+                .define(Symbol::intern(name), native::native(arity, body));
+        }
+
+        for (name, arity, body) in [
+            ("format", 1, native::format as Builtin),
+            ("printf", 1, native::printf),
+        ] {
+            globals
+                .borrow_mut()
+                .define(Symbol::intern(name), native::variadic(arity, body));
+        }
 
         Interpreter {
             globals: globals.clone(),
             environment: globals.clone(),
+            frames: vec![],
+            env_pool: environment::Pool::default(),
             locals: HashMap::new(),
+            method_cache: HashMap::new(),
+            rng: Rng::default(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            repl: false,
+            hot_reload: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            plugins: vec![],
+            foreign_classes: HashMap::new(),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            capabilities: Capabilities::default(),
+            stdin: None,
+            #[cfg(feature = "async")]
+            runtime: None,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_depth_cap: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            profiler: None,
+            coverage: None,
+            trace: false,
+            debugger: None,
         }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Option<Stmt>>) {
+    // Defines the global native function `name`, e.g. one a host program
+    // registers, capturing its own state. It fails with `native::error` to
+    // raise a runtime error in the calling script.
+    pub fn define_native(&mut self, name: &str, arity: usize, body: impl NativeFn) {
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), native::native(arity, body));
+    }
+
+    // Defines the global `name`, or replaces its value
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        let name: Symbol = Symbol::find(name)?;
+        self.globals.borrow().lookup(name)
+    }
+
+    // The handle to stop the code this interpreter runs. Until there's one,
+    // statements don't check for interrupts.
+    pub fn interrupt_handle(&mut self) -> InterruptHandle {
+        self.budget.interrupt = Some(self.interrupt.clone());
+        InterruptHandle(self.interrupt.clone())
+    }
+
+    // Like `define_native`, for a function whose result comes from a future,
+    // see `async_native`
+    #[cfg(feature = "async")]
+    pub fn define_async_native<F, Fut>(&mut self, name: &str, arity: usize, body: F)
+    where
+        F: Fn(Vec<Object>) -> Fut + MaybeSend + 'static,
+        Fut: std::future::Future<Output = Result<Object, LoxError>> + 'static,
+    {
+        self.define_native(name, arity, crate::async_native::native(name, body));
+    }
+
+    // Returns the value of a top-level `return`, which ends the program early,
+    // or the runtime error that stopped it
+    pub fn interpret(&mut self, statements: Vec<Option<Stmt>>) -> Result<Option<Object>, LoxError> {
+        self.budget.start();
+
         for stmt in statements.into_iter().flatten() {
-            let _ = self.execute(&stmt);
+            match self.execute(&stmt) {
+                Err(error @ LoxError::RuntimeError { .. }) => return Err(error),
+                Err(LoxError::Return { value }) => return Ok(Some(value)),
+                Err(LoxError::Exit) => return Ok(None),
+                _ => (),
+            }
         }
+
+        Ok(None)
     }
 
-    // TODO: Modularize
-    pub fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
-        match stmt {
-            Stmt::Expression { expression: expr } => match self.evaluate(expr) {
-                Ok(_) => Ok(()),
-                Err(LoxError::Return { value }) => return Err(LoxError::Return { value }),
-                Err(error) => {
-                    Lox::runtime_error(error);
-                    Ok(())
-                }
-            },
-            Stmt::Function { name, params, body } => {
-                let function: LoxCallable = LoxCallable::User {
-                    name: name.clone(),
-                    params: params.clone(),
-                    body: body.to_vec(),
-                    closure: self.environment.clone(),
-                    is_initializer: false,
-                };
-                self.environment
-                    .borrow_mut()
-                    .define(name.lexeme.clone(), Object::Callable(function));
-                Ok(())
-            }
-            Stmt::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => {
-                let _cond: Object = match self.evaluate(condition) {
-                    Ok(literal) => literal,
-                    Err(LoxError::Return { value }) => return Err(LoxError::Return { value }),
-                    Err(error) => {
-                        Lox::runtime_error(error);
-                        return Ok(());
-                    }
-                };
+    // Like `interpret`, for a program compiled to closures, see `compiler`
+    pub fn interpret_compiled(
+        &mut self,
+        statements: &[StmtFn],
+    ) -> Result<Option<Object>, LoxError> {
+        self.budget.start();
 
-                if is_truthy(_cond) {
-                    self.execute(then_branch)?;
-                } else {
-                    match &**else_branch {
-                        Some(else_stmt) => self.execute(else_stmt),
-                        _ => Ok(()), // do nothing
-                    }?
-                }
-                Ok(())
-            }
-            Stmt::While { condition, body } => {
-                while is_truthy(match self.evaluate(condition) {
-                    Ok(literal) => literal,
-                    Err(LoxError::Return { value }) => return Err(LoxError::Return { value }),
-                    Err(error) => {
-                        Lox::runtime_error(error);
-                        return Ok(());
-                    }
-                }) {
-                    self.execute(body)?;
-                }
-                Ok(())
+        for stmt in statements {
+            match stmt(self) {
+                Err(error @ LoxError::RuntimeError { .. }) => return Err(error),
+                Err(LoxError::Return { value }) => return Ok(Some(value)),
+                _ => (),
             }
-            Stmt::Print { expression: expr } => match self.evaluate(expr) {
-                Ok(lit) => {
-                    println!("{}", stringify(lit));
-                    Ok(())
-                }
-                Err(LoxError::Return { value }) => return Err(LoxError::Return { value }),
-                Err(error) => Err(error),
-            },
-            Stmt::Return { value, .. } => {
-                let ret_val: Object = match value {
-                    Some(expr) => {
-                        let res = self.evaluate(&expr)?;
-                        res
-                    }
-                    None => Object::None,
-                };
+        }
 
-                Err(LoxError::Return { value: ret_val })
-            }
-            Stmt::Var { name, initializer } => {
-                let value: Object = match initializer {
-                    Some(init_expr) => self.evaluate(init_expr)?,
-                    None => Object::None,
-                };
+        Ok(None)
+    }
 
-                self.environment
-                    .borrow_mut()
-                    .define(name.lexeme.to_owned(), value);
+    pub fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+        if self.budget.spend() {
+            self.budget.check()?;
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.hit(stmt);
+        }
+        // Taken out while paused, so that it can evaluate code in the
+        // paused frame without being called back
+        if let Some(mut debugger) = self.debugger.take() {
+            let res = debugger.before(self, stmt);
+            self.debugger = Some(debugger);
+            res?;
+        }
 
-                Ok(())
-            }
-            Stmt::Block { statements } => self.execute_block(
-                statements,
-                Rc::new(RefCell::new(Environment::new(Some(
-                    self.environment.clone(),
-                )))),
-            ),
-            Stmt::Class {
-                name,
-                superclass,
-                methods,
-            } => {
-                let mut superclass_obj = Object::None;
-                if let Some(_superclass) = superclass {
-                    if let Object::Class(class) = self.evaluate(_superclass)? {
-                        superclass_obj = Object::Class(class);
-                    } else if let Expr::Variable { name: _name } = _superclass {
-                        return Err(LoxError::RuntimeError {
-                            message: "Superclass must be a class.".to_owned(),
-                            token: Some(_name.clone()),
-                        });
-                    }
-                }
+        stmt.accept(self)?;
+        self.check_memory()
+    }
 
-                self.environment
-                    .borrow_mut()
-                    .define(name.lexeme.clone(), Object::None);
-
-                if !superclass.is_none() {
-                    self.environment = Rc::new(RefCell::new(Environment::new(Some(
-                        self.environment.clone(),
-                    ))));
-                    self.environment
-                        .borrow_mut()
-                        .define("super".to_owned(), superclass_obj.clone());
-                }
+    // Counts `value` as just made, see `memory`
+    #[inline]
+    pub fn charge(&mut self, value: &Object) {
+        if self.budget.max_memory.is_some() {
+            self.budget.allocate(memory::size_of_value(value));
+        }
+    }
 
-                let mut methods_stmts: HashMap<String, LoxCallable> = HashMap::new();
-                for method in methods {
-                    if let Stmt::Function { name, params, body } = *method.to_owned() {
-                        let function: LoxCallable = LoxCallable::User {
-                            name: name.clone(),
-                            params: params.clone(),
-                            body: body.to_vec(),
-                            closure: self.environment.clone(),
-                            is_initializer: name.lexeme.eq("init"),
-                        };
-                        methods_stmts.insert(name.lexeme, function);
-                    }
-                }
+    // Counts `environment` as just made, for a block or a call. Those from
+    // `env_pool` are counted again, the next measurement corrects it.
+    #[inline]
+    pub fn charge_environment(&mut self, environment: &Pointer<Environment>) {
+        if self.budget.max_memory.is_some() {
+            self.budget
+                .allocate(memory::size_of_environment(&environment.borrow()));
+        }
+    }
+
+    // After the statement rather than before, so that one freeing memory
+    // (e.g. `big = nil;`) can still run once the limit is hit
+    #[inline]
+    pub fn check_memory(&mut self) -> Result<(), LoxError> {
+        match self.budget.memory_check_due() {
+            true => self.measure_memory(),
+            false => Ok(()),
+        }
+    }
 
-                let class = LoxClass::new(name.lexeme.clone(), superclass_obj, methods_stmts);
+    #[cold]
+    fn measure_memory(&mut self) -> Result<(), LoxError> {
+        let roots = [&self.globals, &self.environment]
+            .into_iter()
+            .chain(&self.frames);
+        self.budget.memory_used = memory::measure(roots);
+        self.budget.allocated = 0;
 
-                if !superclass.is_none() {
-                    self.environment = self.environment.clone().borrow().enclosing.clone().unwrap();
-                }
+        match self.budget.max_memory {
+            Some(max) if self.budget.memory_used > max => Err(memory_exceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn declare_function(&mut self, declaration: Rc<Declaration>) {
+        let name: Symbol = declaration.name.symbol;
+        let function: LoxCallable = LoxCallable::User {
+            declaration,
+            closure: self.environment.clone(),
+            is_initializer: false,
+        };
+        self.environment
+            .borrow_mut()
+            .define(name, Object::Callable(function));
+    }
+
+    // `superclass` is the value of the superclass expression, if any
+    pub fn declare_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<(Object, &Expr)>,
+        methods: &[Rc<Declaration>],
+    ) -> Result<(), LoxError> {
+        let mut superclass_obj = Object::None;
+        let has_superclass: bool = superclass.is_some();
+        if let Some((superclass, superclass_expr)) = superclass {
+            if let Object::Class(class) = superclass {
+                superclass_obj = Object::Class(class);
+            } else if let Expr::Variable { name: _name, .. } = superclass_expr {
+                return Err(LoxError::RuntimeError {
+                    message: "Superclass must be a class.".to_owned(),
+                    token: Some(_name.clone()),
+                });
+            }
+        }
+
+        if has_superclass {
+            self.environment = Rc::new(RefCell::new(Environment::new(Some(
+                self.environment.clone(),
+            ))));
+            self.environment
+                .borrow_mut()
+                .define(Symbol::SUPER, superclass_obj.clone());
+        }
+
+        let mut methods_stmts: HashMap<Symbol, LoxCallable> = HashMap::new();
+        for method in methods {
+            let function: LoxCallable = LoxCallable::User {
+                declaration: method.clone(),
+                closure: self.environment.clone(),
+                is_initializer: method.name.symbol == Symbol::INIT,
+            };
+            methods_stmts.insert(method.name.symbol, function);
+        }
+
+        let class = LoxClass::new(name.lexeme.clone(), superclass_obj, methods_stmts);
 
-                let _ = self
-                    .environment
-                    .borrow_mut()
-                    .assign(name, Object::Class(class));
+        if has_superclass {
+            self.environment = self.environment.clone().borrow().enclosing.clone().unwrap();
+        }
 
-                Ok(())
+        // When the script is reloaded, a global class declared again is
+        // updated in place, so that the instances and subclasses of the old
+        // declaration get the new methods
+        if self.hot_reload && Rc::ptr_eq(&self.environment, &self.globals) {
+            let old: Option<Object> = self.globals.borrow().lookup(name.symbol);
+            if let Some(Object::Class(old)) = old {
+                let class: LoxClass = class.borrow().clone();
+                *old.borrow_mut() = class;
+                // Methods of the old declaration may be cached under it
+                self.method_cache.clear();
+                return Ok(());
             }
         }
+
+        // Defined only now that the class exists. Methods look the
+        // class up when they're called, by then it's in its slot.
+        self.environment
+            .borrow_mut()
+            .define(name.symbol, Object::Class(class));
+
+        Ok(())
     }
 
     pub fn execute_block(
         &mut self,
-        statements: &Vec<Option<Box<Stmt>>>,
+        statements: &[Option<Box<Stmt>>],
         environment: Rc<RefCell<Environment>>,
     ) -> Result<(), LoxError> {
-        let previous = self.environment.clone();
-        self.environment = environment.clone();
+        self.charge_environment(&environment);
+        let previous = std::mem::replace(&mut self.environment, environment);
+        self.frames.push(previous);
 
-        for stmt in statements.to_owned().iter().flatten() {
+        for stmt in statements.iter().flatten() {
             match self.execute(stmt) {
                 Ok(()) => (), // All good, do nothing
                 Err(err) => {
                     // Restore the original environment even after error
-                    self.environment = previous;
+                    self.environment = self.frames.pop().unwrap();
                     return Err(err);
                 }
             };
         }
 
         // Restore the original env
-        self.environment = previous;
+        self.environment = self.frames.pop().unwrap();
         Ok(())
     }
 
-    pub fn resolve(&mut self, expr: Expr, depth: usize) {
-        self.locals.insert(expr, depth);
+    // What `print` (or `eprint`, `printf`, `--trace`) writes to. Write
+    // errors, e.g. a closed pipe, are ignored.
+    pub fn output(&mut self, stderr: bool) -> &mut Output {
+        match stderr {
+            true => &mut self.stderr,
+            false => &mut self.stdout,
+        }
     }
 
-    // TODO: Modularize
-    fn evaluate(&mut self, expr: &Expr) -> Result<Object, LoxError> {
-        match expr {
-            Expr::Literal { value } => match value {
-                Literal::String(val) => Ok(Object::String(val.clone())),
-                Literal::Number(val) => Ok(Object::Number(val.clone())),
-                Literal::Boolean(val) => Ok(Object::Boolean(val.clone())),
-                Literal::None => Ok(Object::None),
-            },
-            Expr::Grouping { expression } => self.evaluate(expression),
-            Expr::Assign { name, value } => {
-                let val: Object = self.evaluate(value)?;
-
-                if let Some(distance) = self.locals.get(expr) {
-                    environment::assign_at(
-                        self.environment.clone(),
-                        *distance,
-                        name.clone(),
-                        val.clone(),
-                    )?;
-                } else {
-                    self.globals.borrow_mut().assign(name, val.clone())?;
-                }
+    // Calls `f` with the script's input. The real stdin is only locked for
+    // the call, the debugger and `inspect()` read from it too.
+    pub fn read_input<R>(&mut self, f: impl FnOnce(&mut dyn BufRead) -> R) -> R {
+        match self.stdin.as_mut() {
+            Some(input) => f(input),
+            None => f(&mut io::stdin().lock()),
+        }
+    }
 
-                Ok(val)
-            }
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => {
-                let left_lit: Object = self.evaluate(left)?;
-
-                match operator.token_type {
-                    TokenType::Or => {
-                        if is_truthy(left_lit.clone()) {
-                            return Ok(left_lit);
-                        }
-                    }
-                    _ => {
-                        if !is_truthy(left_lit.clone()) {
-                            return Ok(left_lit);
-                        }
-                    }
-                }
+    // One line of `--trace`, indented by the depth of Lox calls
+    fn trace(&mut self, line: usize, message: String) {
+        let indent: String = "  ".repeat(self.call_depth);
+        let _ = writeln!(self.stderr, "{}line {}: {}", indent, line, message);
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Object, LoxError> {
+        expr.accept(self)
+    }
+
+    // The operations below are shared by both backends, the tree-walker
+    // (`evaluate`) and the closures of `compiler`. They take their operands
+    // already evaluated, or as a closure when they're only evaluated if the
+    // operation gets that far.
 
-                self.evaluate(right)
+    pub fn index(
+        &self,
+        object: Object,
+        index: Object,
+        bracket: &Token,
+    ) -> Result<Object, LoxError> {
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                let i: usize = list_index(index, list.len(), bracket)?;
+                Ok(list[i].clone())
             }
-            Expr::Call {
-                callee,
-                paren,
-                arguments,
-            } => {
-                let mut arguments_vals: Vec<Object> = vec![];
-                for arg in arguments.iter() {
-                    arguments_vals.push(self.evaluate(arg)?);
-                }
+            // Missing keys read as `nil`
+            Object::Map(map) => Ok(map
+                .borrow()
+                .get(&map_key(index, bracket)?)
+                .cloned()
+                .unwrap_or(Object::None)),
+            Object::Bytes(bytes) => {
+                let i: usize = list_index(index, bytes.len(), bracket)?;
+                Ok(Object::Number(bytes[i] as f64))
+            }
+            _ => Err(LoxError::RuntimeError {
+                message: "Only lists, maps and bytes can be indexed.".to_owned(),
+                token: Some(bracket.clone()),
+            }),
+        }
+    }
 
-                match self.evaluate(callee)? {
-                    Object::Class(class) => {
-                        let instance = Object::Instance(LoxInstance::new(class.clone()));
-
-                        if let Some(initializer) = class.borrow().find_method("init") {
-                            if arguments_vals.len() != initializer.arity() {
-                                return Err(LoxError::RuntimeError {
-                                    message: format!(
-                                        "Initializer expected {} arguments but got {}.",
-                                        initializer.arity(),
-                                        arguments.len()
-                                    ),
-                                    token: Some(paren.clone()),
-                                });
-                            }
-                            initializer
-                                .bind(instance.clone())
-                                .call(self, &arguments_vals);
-                        }
-
-                        Ok(instance)
-                    }
-                    Object::Callable(function) => {
-                        if arguments_vals.len() != function.arity() {
-                            return Err(LoxError::RuntimeError {
-                                message: format!(
-                                    "Expected {} arguments but got {}.",
-                                    function.arity(),
-                                    arguments.len()
-                                ),
-                                token: Some(paren.clone()),
-                            });
-                        }
-                        Ok(function.call(self, &arguments_vals))
-                    }
-                    _ => Err(LoxError::RuntimeError {
-                        message: "Callee must be a callable or a class".to_string(),
-                        token: Some(paren.clone()),
-                    }),
-                }
+    pub fn index_set(
+        &mut self,
+        object: Object,
+        index: Object,
+        bracket: &Token,
+        value: impl FnOnce(&mut Self) -> Result<Object, LoxError>,
+    ) -> Result<Object, LoxError> {
+        match object {
+            Object::List(list) => {
+                let value: Object = value(self)?;
+                let i: usize = list_index(index, list.borrow().len(), bracket)?;
+                list.borrow_mut()
+                    .get_mut()
+                    .map_err(|error| at(error, bracket))?[i] = value.clone();
+                Ok(value)
             }
-            Expr::Get { object, name } => match self.evaluate(object)? {
-                Object::Instance(instance) => {
-                    Ok(instance.borrow().get(name.clone(), instance.clone()))?
+            Object::Map(map) => {
+                let key: Literal = map_key(index, bracket)?;
+                let value: Object = value(self)?;
+                let mut map = map.borrow_mut();
+                let entries = map.get_mut().map_err(|error| at(error, bracket))?;
+                let capacity: usize = entries.capacity();
+                entries.insert(key, value.clone());
+                let grown: usize = entries.capacity() - capacity;
+                drop(map);
+                self.budget
+                    .allocate(grown * (size_of::<Literal>() + size_of::<Object>()));
+                Ok(value)
+            }
+            Object::Bytes(_) => Err(LoxError::RuntimeError {
+                message: "Bytes are immutable.".to_owned(),
+                token: Some(bracket.clone()),
+            }),
+            _ => Err(LoxError::RuntimeError {
+                message: "Only lists and maps can be indexed.".to_owned(),
+                token: Some(bracket.clone()),
+            }),
+        }
+    }
+
+    // `resolved` is where the resolver found the variable, `None` for globals
+    pub fn assign(
+        &mut self,
+        resolved: Option<(usize, usize)>,
+        name: &Token,
+        val: Object,
+    ) -> Result<Object, LoxError> {
+        if self.trace {
+            self.trace(name.line, format!("{} = {}", name.lexeme, stringify(&val)));
+        }
+
+        if let Some((distance, slot)) = resolved {
+            environment::assign_at(&self.environment, distance, slot, val.clone());
+        } else {
+            let assigned = self.globals.borrow_mut().assign(name, val.clone());
+            assigned.map_err(|error| did_you_mean(error, name, &self.variable_names()))?;
+        }
+
+        Ok(val)
+    }
+
+    pub fn call(
+        &mut self,
+        callee: Object,
+        arguments: &[Object],
+        paren: &Token,
+    ) -> Result<Object, LoxError> {
+        if !self.trace && self.debugger.is_none() {
+            // Errors raised by natives don't know where they were called from
+            return self
+                .call_value(callee, arguments)
+                .map_err(|error| at(error, paren));
+        }
+
+        let name: String = callee_name(&callee);
+        if self.trace {
+            let arguments_text: Vec<String> = arguments.iter().map(stringify).collect();
+            self.trace(
+                paren.line,
+                format!("call {}({})", name, arguments_text.join(", ")),
+            );
+        }
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.enter(name.clone(), paren.line);
+        }
+        let value: Result<Object, LoxError> = self
+            .call_value(callee, arguments)
+            .map_err(|error| at(error, paren));
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.exit();
+        }
+        let value: Object = value?;
+        if self.trace {
+            self.trace(
+                paren.line,
+                format!("{} returned {}", name, stringify(&value)),
+            );
+        }
+        Ok(value)
+    }
+
+    // `id` keys the method cache, see `method_cache`
+    pub fn get(&mut self, object: Object, id: ExprId, name: &Token) -> Result<Object, LoxError> {
+        match object {
+            Object::Instance(instance) => self.get_property(id, name, instance),
+            Object::Foreign(foreign) => match foreign.method(name.symbol) {
+                Some(method) => Ok(method),
+                None => {
+                    let error = LoxError::RuntimeError {
+                        message: format!("Undefined property '{}'.", name.lexeme),
+                        token: Some(name.to_owned()),
+                    };
+                    let methods: Vec<Rc<str>> = foreign
+                        .class
+                        .method_names()
+                        .iter()
+                        .map(Symbol::as_str)
+                        .collect();
+                    Err(did_you_mean(error, name, &methods))
                 }
-                _ => Err(LoxError::RuntimeError {
-                    message: "Only instances have properties.".to_owned(),
-                    token: Some(name.to_owned()),
-                }),
             },
-            Expr::Set {
-                object,
-                name,
-                value,
-            } => match self.evaluate(object)? {
-                Object::Instance(instance) => {
-                    let value: Object = self.evaluate(value)?;
-                    instance.borrow_mut().set(name.clone(), value.clone());
-                    Ok(value)
+            receiver @ (Object::String(_)
+            | Object::Bytes(_)
+            | Object::Number(_)
+            | Object::List(_)
+            | Object::Map(_)) => match native::find_method(&receiver, &name.lexeme) {
+                Some(method) => Ok(Object::Callable(method)),
+                None => {
+                    let methods: Vec<Rc<str>> = native::METHOD_NAMES
+                        .iter()
+                        .filter(|method| native::find_method(&receiver, method).is_some())
+                        .map(|method| Rc::from(*method))
+                        .collect();
+                    let error = LoxError::RuntimeError {
+                        message: format!("Undefined property '{}'.", name.lexeme),
+                        token: Some(name.to_owned()),
+                    };
+                    Err(did_you_mean(error, name, &methods))
                 }
-                _ => Err(LoxError::RuntimeError {
-                    message: "Only instances have fields".to_owned(),
-                    token: Some(name.clone()),
-                }),
             },
-            Expr::Super { method, .. } => {
-                let distance: usize = *self.locals.get(&expr).unwrap();
-                let superclass =
-                    environment::get_at(self.environment.clone(), distance, "super".to_owned())?;
-                let instance =
-                    environment::get_at(self.environment.clone(), distance - 1, "this".to_owned())?;
-
-                let maybe_method = if let Object::Class(_superclass) = superclass {
-                    _superclass.borrow().find_method(&method.lexeme)
-                } else {
-                    None
-                };
+            _ => Err(LoxError::RuntimeError {
+                message: "Only instances have properties.".to_owned(),
+                token: Some(name.to_owned()),
+            }),
+        }
+    }
 
-                match maybe_method {
-                    Some(method) => Ok(Object::Callable(method.bind(instance))),
-                    _ => Err(LoxError::RuntimeError {
-                        message: format!("Undefined property '{}'.", method.lexeme),
-                        token: Some(method.clone()),
-                    }),
+    pub fn set(
+        &mut self,
+        object: Object,
+        name: &Token,
+        value: impl FnOnce(&mut Self) -> Result<Object, LoxError>,
+    ) -> Result<Object, LoxError> {
+        match object {
+            Object::Instance(instance) => {
+                let value: Object = value(self)?;
+                if self.trace {
+                    let target: String = format!("{}.{}", instance.borrow(), name.lexeme);
+                    let text: String = stringify(&value);
+                    self.trace(name.line, format!("{} = {}", target, text));
                 }
+                instance.borrow_mut().set(name.clone(), value.clone())?;
+                Ok(value)
             }
-            Expr::This { keyword } => {
-                return self.look_up_variable(keyword, expr);
+            _ => Err(LoxError::RuntimeError {
+                message: "Only instances have fields".to_owned(),
+                token: Some(name.clone()),
+            }),
+        }
+    }
+
+    // `super` is at (`distance`, `slot`), see `Resolver`
+    pub fn super_method(
+        &self,
+        distance: usize,
+        slot: usize,
+        method: &Token,
+    ) -> Result<Object, LoxError> {
+        // `super` and `this` are alone in their scopes, both in slot 0
+        let superclass = environment::get_at(&self.environment, distance, slot);
+        let instance = environment::get_at(&self.environment, distance - 1, 0);
+
+        let maybe_method = if let Object::Class(_superclass) = &superclass {
+            _superclass.borrow().find_method(method.symbol)
+        } else {
+            None
+        };
+
+        match maybe_method {
+            Some(method) => Ok(Object::Callable(method.bind(instance))),
+            _ => {
+                let methods: Vec<Rc<str>> = match superclass {
+                    Object::Class(superclass) => superclass
+                        .borrow()
+                        .method_names()
+                        .into_iter()
+                        .map(|name| name.as_str())
+                        .collect(),
+                    _ => vec![],
+                };
+                let error = LoxError::RuntimeError {
+                    message: format!("Undefined property '{}'.", method.lexeme),
+                    token: Some(method.clone()),
+                };
+                Err(did_you_mean(error, method, &methods))
             }
-            Expr::Unary { operator, right } => {
-                // Recursion to get the leaf (always a literal)
-                let right: Object = self.evaluate(right)?;
-
-                // Apply the unary operator
-                match operator.token_type {
-                    TokenType::Bang => match right {
-                        Object::Boolean(value) => Ok(Object::Boolean(!value)),
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operand must be a boolean.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Minus => match right {
-                        Object::Number(value) => Ok(Object::Number(-value.clone())),
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operand must be a number.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    _ => Err(LoxError::RuntimeError {
-                        message: "Invalid operator.".to_string(),
-                        token: Some(operator.clone()),
-                    }),
+        }
+    }
+
+    // Exposes the Rust type `T` to scripts as the class `name`, see `foreign`
+    pub fn register_class<T: Any + MaybeSend>(&mut self, name: &str) -> ClassBuilder<'_, T> {
+        ClassBuilder::new(self, name)
+    }
+
+    // `value` as an instance of the class registered for `T`
+    pub fn wrap<T: Any + MaybeSend>(&self, value: T) -> Result<Object, LoxError> {
+        let Some(class) = self.foreign_classes.get(&TypeId::of::<T>()) else {
+            return Err(native::error(format!(
+                "No class is registered for '{}'.",
+                std::any::type_name::<T>()
+            )));
+        };
+        Ok(Object::Foreign(Foreign {
+            class: class.clone(),
+            value: Rc::new(RefCell::new(value)),
+        }))
+    }
+
+    // Calls the global function (or class) `name`, e.g. a callback a host
+    // program calls once the script defining it ran, see `Lox::call`
+    pub fn call_function(&mut self, name: &str, arguments: &[Object]) -> Result<Object, LoxError> {
+        let callee: Option<Object> =
+            Symbol::find(name).and_then(|symbol| self.globals.borrow().lookup(symbol));
+        let Some(callee) = callee else {
+            return Err(native::error(format!("Undefined function '{}'.", name)));
+        };
+        self.call_value(callee, arguments)
+    }
+
+    // Calls a class or callable with already evaluated arguments. Used by
+    // call expressions and by natives that call back into Lox code.
+    pub fn call_value(&mut self, callee: Object, arguments: &[Object]) -> Result<Object, LoxError> {
+        match callee {
+            Object::Class(class) => {
+                let instance = Object::Instance(LoxInstance::new(class.clone()));
+                self.charge(&instance);
+
+                if let Some(initializer) = class.borrow().find_method(Symbol::INIT) {
+                    if arguments.len() != initializer.arity() {
+                        return Err(LoxError::RuntimeError {
+                            message: format!(
+                                "Initializer expected {} arguments but got {}.",
+                                initializer.arity(),
+                                arguments.len()
+                            ),
+                            token: None,
+                        });
+                    }
+                    initializer.bind(instance.clone()).call(self, arguments)?;
                 }
+
+                Ok(instance)
             }
-            Expr::Variable { name } => self.look_up_variable(name, expr),
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => {
-                // DFS
-                let left: Object = self.evaluate(left)?;
-                let right: Object = self.evaluate(right)?;
-
-                match operator.token_type {
-                    TokenType::Minus => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Number(val1 - val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Slash => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Number(val1 / val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Plus => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Number(val1 + val2))
-                        }
-                        (Object::String(val1), Object::String(val2)) => {
-                            let mut res: String = val1.clone();
-                            res.push_str(&val2);
-                            Ok(Object::String(res))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be both numbers or strings.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Star => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Number(val1 * val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Greater => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Boolean(val1 > val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::GreaterEqual => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Boolean(val1 >= val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::Less => match (left.clone(), right.clone()) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Boolean(val1 < val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::LessEqual => match (left, right) {
-                        (Object::Number(val1), Object::Number(val2)) => {
-                            Ok(Object::Boolean(val1 <= val2))
-                        }
-                        _ => Err(LoxError::RuntimeError {
-                            message: "Operands must be numbers.".to_string(),
-                            token: Some(operator.clone()),
-                        }),
-                    },
-                    TokenType::BangEqual => Ok(Object::Boolean(!is_equal(left, right))),
-                    TokenType::EqualEqual => Ok(Object::Boolean(is_equal(left, right))),
-                    _ => Err(LoxError::RuntimeError {
-                        message: "Invalid operator.".to_string(),
-                        token: Some(operator.clone()),
-                    }),
+            Object::Callable(function) => {
+                if function.is_variadic() && arguments.len() < function.arity() {
+                    return Err(LoxError::RuntimeError {
+                        message: format!(
+                            "Expected at least {} arguments but got {}.",
+                            function.arity(),
+                            arguments.len()
+                        ),
+                        token: None,
+                    });
+                } else if !function.is_variadic() && arguments.len() != function.arity() {
+                    return Err(LoxError::RuntimeError {
+                        message: format!(
+                            "Expected {} arguments but got {}.",
+                            function.arity(),
+                            arguments.len()
+                        ),
+                        token: None,
+                    });
                 }
+                function.call(self, arguments)
             }
+            _ => Err(LoxError::RuntimeError {
+                message: "Callee must be a callable or a class".to_string(),
+                token: None,
+            }),
         }
     }
 
-    fn look_up_variable(&self, name: &Token, expr: &Expr) -> Result<Object, LoxError> {
-        if let Some(distance) = self.locals.get(expr) {
-            environment::get_at(self.environment.clone(), *distance, name.lexeme.clone())
+    // Fields shadow methods, so they're still looked up first
+    fn get_property(
+        &mut self,
+        id: ExprId,
+        name: &Token,
+        instance: Rc<RefCell<LoxInstance>>,
+    ) -> Result<Object, LoxError> {
+        if let Some(field) = instance.borrow().fields().get(&name.symbol) {
+            return Ok(field.clone());
+        }
+
+        let class: Rc<RefCell<LoxClass>> = instance.borrow().class();
+        let method: LoxCallable = match self.method_cache.get(&id) {
+            Some((cached, method)) if std::ptr::eq(cached.as_ptr(), Rc::as_ptr(&class)) => {
+                method.clone()
+            }
+            _ => {
+                let method: Option<LoxCallable> = class.borrow().find_method(name.symbol);
+                let Some(method) = method else {
+                    // Fields and methods alike
+                    let members: Vec<Rc<str>> = instance
+                        .borrow()
+                        .fields()
+                        .keys()
+                        .copied()
+                        .chain(class.borrow().method_names())
+                        .map(|name| name.as_str())
+                        .collect();
+                    let error = LoxError::RuntimeError {
+                        message: format!("Undefined property '{}'.", name.lexeme),
+                        token: Some(name.clone()),
+                    };
+                    return Err(did_you_mean(error, name, &members));
+                };
+                self.method_cache
+                    .insert(id, (Rc::downgrade(&class), method.clone()));
+                method
+            }
+        };
+
+        Ok(Object::Callable(method.bind(Object::Instance(instance))))
+    }
+
+    // `resolved` is where the resolver found the variable, `None` for globals
+    pub fn look_up(
+        &self,
+        resolved: Option<(usize, usize)>,
+        name: &Token,
+    ) -> Result<Object, LoxError> {
+        if let Some((distance, slot)) = resolved {
+            Ok(environment::get_at(&self.environment, distance, slot))
         } else {
-            self.globals.borrow_mut().get(name)
+            let value = self.globals.borrow().get(name);
+            value.map_err(|error| did_you_mean(error, name, &self.variable_names()))
+        }
+    }
+
+    // Every variable in scope, globals included, for suggestions
+    fn variable_names(&self) -> Vec<Rc<str>> {
+        environment::chain(self.environment.clone())
+            .iter()
+            .flat_map(|environment| environment.borrow().bindings())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+impl StmtVisitor<Result<(), LoxError>> for Interpreter {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) -> Result<(), LoxError> {
+        let environment: Pointer<Environment> = self.env_pool.acquire(self.environment.clone());
+        let res = self.execute_block(statements, environment.clone());
+        self.env_pool.release(environment);
+        res
+    }
+
+    fn visit_break(&mut self, _keyword: &Token) -> Result<(), LoxError> {
+        Err(LoxError::Break)
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Box<Stmt>],
+    ) -> Result<(), LoxError> {
+        let superclass: Option<(Object, &Expr)> = match superclass {
+            Some(expr) => Some((self.evaluate(expr)?, expr)),
+            None => None,
+        };
+        let methods: Vec<Rc<Declaration>> = methods
+            .iter()
+            .map(|method| match &**method {
+                Stmt::Function {
+                    name, params, body, ..
+                } => Declaration::new(name, params, body, None),
+                _ => unreachable!(),
+            })
+            .collect();
+        self.declare_class(name, superclass, &methods)
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token) -> Result<(), LoxError> {
+        Err(LoxError::Continue)
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) -> Result<(), LoxError> {
+        self.evaluate(expression)?;
+        Ok(())
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
+    ) -> Result<(), LoxError> {
+        self.declare_function(Declaration::new(name, params, body, None));
+        Ok(())
+    }
+
+    fn visit_if(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> Result<(), LoxError> {
+        let condition: Object = self.evaluate(condition)?;
+        if self.trace {
+            self.trace(keyword.line, format!("if {}", stringify(&condition)));
+        }
+        if is_truthy(&condition) {
+            self.execute(then_branch)?;
+        } else if let Some(else_stmt) = else_branch {
+            self.execute(else_stmt)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print(
+        &mut self,
+        keyword: &Token,
+        expression: &Expr,
+        stderr: bool,
+    ) -> Result<(), LoxError> {
+        let text: String = stringify(&self.evaluate(expression)?);
+        if self.trace {
+            self.trace(keyword.line, format!("{} {}", keyword.lexeme, text));
+        }
+        let _ = writeln!(self.output(stderr), "{}", text);
+        Ok(())
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expr>) -> Result<(), LoxError> {
+        let ret_val: Object = match value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Object::None,
+        };
+        if self.trace {
+            self.trace(keyword.line, format!("return {}", stringify(&ret_val)));
+        }
+
+        Err(LoxError::Return { value: ret_val })
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> Result<(), LoxError> {
+        let value: Object = match initializer {
+            Some(init_expr) => self.evaluate(init_expr)?,
+            None => Object::None,
+        };
+        if self.trace {
+            let text: String = stringify(&value);
+            self.trace(name.line, format!("var {} = {}", name.lexeme, text));
+        }
+
+        self.environment.borrow_mut().define(name.symbol, value);
+
+        Ok(())
+    }
+
+    fn visit_while(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<(), LoxError> {
+        loop {
+            let condition: Object = self.evaluate(condition)?;
+            if self.trace {
+                self.trace(keyword.line, format!("while {}", stringify(&condition)));
+            }
+            if !is_truthy(&condition) {
+                break;
+            }
+
+            match self.execute(body) {
+                Ok(()) | Err(LoxError::Continue) => (),
+                Err(LoxError::Break) => break,
+                Err(err) => return Err(err),
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ExprVisitor<Result<Object, LoxError>> for Interpreter {
+    fn visit_assign(&mut self, id: ExprId, name: &Token, value: &Expr) -> Result<Object, LoxError> {
+        let val: Object = self.evaluate(value)?;
+        self.assign(self.locals.get(&id).copied(), name, val)
+    }
+
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<Object, LoxError> {
+        // DFS
+        let left: Object = self.evaluate(left)?;
+        let right: Object = self.evaluate(right)?;
+        let value: Object = binary(operator, left, right)?;
+        self.charge(&value);
+        Ok(value)
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Box<Expr>],
+    ) -> Result<Object, LoxError> {
+        let mut arguments_vals: Vec<Object> = vec![];
+        for arg in arguments.iter() {
+            arguments_vals.push(self.evaluate(arg)?);
+        }
+
+        let callee: Object = self.evaluate(callee)?;
+        self.call(callee, &arguments_vals, paren)
+    }
+
+    fn visit_get(&mut self, id: ExprId, object: &Expr, name: &Token) -> Result<Object, LoxError> {
+        let object: Object = self.evaluate(object)?;
+        self.get(object, id, name)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> Result<Object, LoxError> {
+        self.evaluate(expression)
+    }
+
+    fn visit_index(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<Object, LoxError> {
+        let object: Object = self.evaluate(object)?;
+        let index: Object = self.evaluate(index)?;
+        self.index(object, index, bracket)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<Object, LoxError> {
+        let object: Object = self.evaluate(object)?;
+        let index: Object = self.evaluate(index)?;
+        self.index_set(object, index, bracket, |this| this.evaluate(value))
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Box<Expr>]) -> Result<Object, LoxError> {
+        let mut values: Vec<Object> = vec![];
+        for element in elements.iter() {
+            values.push(self.evaluate(element)?);
+        }
+
+        let list: Object = native::new_list(values);
+        self.charge(&list);
+        Ok(list)
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> Result<Object, LoxError> {
+        Ok(literal(value))
+    }
+
+    fn visit_map(
+        &mut self,
+        brace: &Token,
+        entries: &[(Box<Expr>, Box<Expr>)],
+    ) -> Result<Object, LoxError> {
+        let mut map: IndexMap<Literal, Object> = IndexMap::new();
+        for (key, value) in entries.iter() {
+            let key: Literal = map_key(self.evaluate(key)?, brace)?;
+            map.insert(key, self.evaluate(value)?);
+        }
+
+        let map: Object = native::new_map(map);
+        self.charge(&map);
+        Ok(map)
+    }
+
+    fn visit_logical(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<Object, LoxError> {
+        let left_lit: Object = self.evaluate(left)?;
+
+        match operator.token_type {
+            TokenType::Or => {
+                if is_truthy(&left_lit) {
+                    return Ok(left_lit);
+                }
+            }
+            _ => {
+                if !is_truthy(&left_lit) {
+                    return Ok(left_lit);
+                }
+            }
         }
+
+        self.evaluate(right)
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<Object, LoxError> {
+        let object: Object = self.evaluate(object)?;
+        self.set(object, name, |this| this.evaluate(value))
+    }
+
+    fn visit_super(
+        &mut self,
+        id: ExprId,
+        _keyword: &Token,
+        method: &Token,
+    ) -> Result<Object, LoxError> {
+        let (distance, slot) = *self.locals.get(&id).unwrap();
+        self.super_method(distance, slot, method)
+    }
+
+    fn visit_this(&mut self, id: ExprId, keyword: &Token) -> Result<Object, LoxError> {
+        self.look_up(self.locals.get(&id).copied(), keyword)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> Result<Object, LoxError> {
+        // Recursion to get the leaf (always a literal)
+        let right: Object = self.evaluate(right)?;
+        unary(operator, right)
+    }
+
+    fn visit_variable(&mut self, id: ExprId, name: &Token) -> Result<Object, LoxError> {
+        self.look_up(self.locals.get(&id).copied(), name)
+    }
+}
+
+// Runtime errors raised without a location (e.g. by natives) get `token`'s
+pub fn at(error: LoxError, token: &Token) -> LoxError {
+    match error {
+        LoxError::RuntimeError {
+            message,
+            token: None,
+        } => LoxError::RuntimeError {
+            message,
+            token: Some(token.clone()),
+        },
+        _ => error,
+    }
+}
+
+// Ends the message of `error` (about `name` not being found) with the
+// closest of `candidates`, e.g. "Did you mean 'counter'?"
+fn did_you_mean(error: LoxError, name: &Token, candidates: &[Rc<str>]) -> LoxError {
+    let candidates = candidates.iter().map(|candidate| &**candidate);
+    match (error, util::closest(&name.lexeme, candidates)) {
+        (LoxError::RuntimeError { message, token }, Some(closest)) => LoxError::RuntimeError {
+            message: format!("{} Did you mean '{}'?", message, closest),
+            token,
+        },
+        (error, _) => error,
+    }
+}
+
+pub fn literal(value: &Literal) -> Object {
+    match value {
+        Literal::String(val) => Object::String(val.clone()),
+        Literal::Bytes(val) => Object::Bytes(Rc::new(val.clone())),
+        Literal::Number(val) => Object::Number(*val),
+        Literal::Boolean(val) => Object::Boolean(*val),
+        Literal::None => Object::None,
     }
 }
 
-fn is_truthy(a: Object) -> bool {
+pub fn unary(operator: &Token, right: Object) -> Result<Object, LoxError> {
+    // Apply the unary operator
+    match operator.token_type {
+        TokenType::Bang => match right {
+            Object::Boolean(value) => Ok(Object::Boolean(!value)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operand must be a boolean.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Minus => match right {
+            Object::Number(value) => Ok(Object::Number(-value)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operand must be a number.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        _ => Err(LoxError::RuntimeError {
+            message: "Invalid operator.".to_string(),
+            token: Some(operator.clone()),
+        }),
+    }
+}
+
+pub fn binary(operator: &Token, left: Object, right: Object) -> Result<Object, LoxError> {
+    match operator.token_type {
+        TokenType::Minus => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Number(val1 - val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Slash => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Number(val1 / val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Plus => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Number(val1 + val2)),
+            (Object::String(val1), Object::String(val2)) => {
+                let mut res: String = String::with_capacity(val1.len() + val2.len());
+                res.push_str(&val1);
+                res.push_str(&val2);
+                Ok(Object::String(res.into()))
+            }
+            (Object::List(val1), Object::List(val2)) => {
+                // Always a fresh list, the operands are left untouched
+                let mut res: Vec<Object> = val1.borrow().clone();
+                res.extend(val2.borrow().iter().cloned());
+                Ok(native::new_list(res))
+            }
+            (Object::Bytes(val1), Object::Bytes(val2)) => {
+                Ok(Object::Bytes(Rc::new([&val1[..], &val2[..]].concat())))
+            }
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be both numbers, strings, lists, or bytes.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Star => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Number(val1 * val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Greater => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Boolean(val1 > val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::GreaterEqual => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Boolean(val1 >= val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::Less => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Boolean(val1 < val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::LessEqual => match (left, right) {
+            (Object::Number(val1), Object::Number(val2)) => Ok(Object::Boolean(val1 <= val2)),
+            _ => Err(LoxError::RuntimeError {
+                message: "Operands must be numbers.".to_string(),
+                token: Some(operator.clone()),
+            }),
+        },
+        TokenType::BangEqual => Ok(Object::Boolean(!is_equal(&left, &right))),
+        TokenType::EqualEqual => Ok(Object::Boolean(is_equal(&left, &right))),
+        _ => Err(LoxError::RuntimeError {
+            message: "Invalid operator.".to_string(),
+            token: Some(operator.clone()),
+        }),
+    }
+}
+
+pub fn is_truthy(a: &Object) -> bool {
     match a {
         Object::None => false,
-        Object::Boolean(val) => val,
+        Object::Boolean(val) => *val,
         _ => true,
     }
 }
 
-fn is_equal(a: Object, b: Object) -> bool {
+fn is_equal(a: &Object, b: &Object) -> bool {
+    is_equal_nested(a, b, &mut HashSet::new())
+}
+
+// `compared` holds the address pairs of the lists and maps being compared
+// further up, a pair met again (through a list or map containing itself) is
+// taken as equal rather than compared forever
+fn is_equal_nested(a: &Object, b: &Object, compared: &mut HashSet<(usize, usize)>) -> bool {
     match (a, b) {
         (Object::None, Object::None) => true,
         (Object::None, _) => false,
         (_, Object::None) => false,
         (Object::Number(val1), Object::Number(val2)) => val1 == val2,
         (Object::String(val1), Object::String(val2)) => val1 == val2,
+        (Object::Bytes(val1), Object::Bytes(val2)) => val1 == val2,
         (Object::Boolean(val1), Object::Boolean(val2)) => val1 == val2,
+        (Object::Foreign(val1), Object::Foreign(val2)) => Rc::ptr_eq(&val1.value, &val2.value),
+        (Object::List(val1), Object::List(val2)) => {
+            if Rc::ptr_eq(val1, val2)
+                || !compared.insert((Rc::as_ptr(val1) as usize, Rc::as_ptr(val2) as usize))
+            {
+                return true;
+            }
+
+            let (val1, val2) = (val1.borrow(), val2.borrow());
+            val1.len() == val2.len()
+                && val1
+                    .iter()
+                    .zip(val2.iter())
+                    .all(|(a, b)| is_equal_nested(a, b, compared))
+        }
+        (Object::Map(val1), Object::Map(val2)) => {
+            if Rc::ptr_eq(val1, val2)
+                || !compared.insert((Rc::as_ptr(val1) as usize, Rc::as_ptr(val2) as usize))
+            {
+                return true;
+            }
+
+            // Same entries, in any order
+            let (val1, val2) = (val1.borrow(), val2.borrow());
+            val1.len() == val2.len()
+                && val1.iter().all(|(key, a)| match val2.get(key) {
+                    Some(b) => is_equal_nested(a, b, compared),
+                    None => false,
+                })
+        }
         _ => false,
     }
 }
 
-fn stringify(obj: Object) -> String {
-    match obj {
-        Object::None => "nil".to_owned(),
-        Object::Number(val) => {
-            // Integers are also stored as doubles.
-            // So we need to cast back.
-            let val_str: String = val.to_string();
-
-            match val_str.strip_suffix(".0") {
-                Some(stripped) => stripped.to_owned(),
-                None => val_str,
-            }
+pub fn map_key(key: Object, bracket: &Token) -> Result<Literal, LoxError> {
+    native::to_key(&key).map_err(|_| LoxError::RuntimeError {
+        message: "Map keys must be strings, numbers, booleans, or nil.".to_owned(),
+        token: Some(bracket.clone()),
+    })
+}
+
+fn list_index(index: Object, len: usize, bracket: &Token) -> Result<usize, LoxError> {
+    match index {
+        Object::Number(val) if val.fract() == 0.0 && val >= 0.0 && (val as usize) < len => {
+            Ok(val as usize)
+        }
+        Object::Number(val) if val.fract() == 0.0 => Err(LoxError::RuntimeError {
+            message: format!("Index {} out of bounds for list of length {}.", val, len),
+            token: Some(bracket.clone()),
+        }),
+        _ => Err(LoxError::RuntimeError {
+            message: "List index must be an integer.".to_owned(),
+            token: Some(bracket.clone()),
+        }),
+    }
+}
+
+// How `--trace` names what's being called
+fn callee_name(callee: &Object) -> String {
+    match callee {
+        Object::Callable(LoxCallable::User { declaration, .. }) => declaration.name.lexeme.clone(),
+        Object::Callable(LoxCallable::Method { name, .. })
+        | Object::Callable(LoxCallable::Plugin { name, .. }) => name.clone(),
+        Object::Class(class) => class.borrow().name.clone(),
+        _ => stringify(callee),
+    }
+}
+
+pub fn stringify(obj: &Object) -> String {
+    stringify_nested(obj, &mut HashSet::new())
+}
+
+// `enclosing` holds the address of each list and map `obj` is nested in, one
+// containing itself prints as `[...]` or `{...}` there
+fn stringify_nested(obj: &Object, enclosing: &mut HashSet<usize>) -> String {
+    let address: Option<usize> = match obj {
+        Object::List(list) => Some(Rc::as_ptr(list) as usize),
+        Object::Map(map) => Some(Rc::as_ptr(map) as usize),
+        _ => None,
+    };
+    if let Some(address) = address {
+        if !enclosing.insert(address) {
+            return match obj {
+                Object::List(_) => "[...]".to_owned(),
+                _ => "{...}".to_owned(),
+            };
         }
+    }
+
+    let res: String = match obj {
+        Object::None => "nil".to_owned(),
+        Object::Number(val) => util::format_number(*val),
         Object::Boolean(val) => val.to_string(),
-        Object::String(val) => format!("{val}"),
+        Object::String(val) => val.to_string(),
+        Object::Bytes(val) => escape_bytes(val),
         Object::Callable(name) => format!("{name}"),
         Object::Class(class) => format!("{}", class.borrow()),
         Object::Instance(instance) => format!("{}", instance.borrow()),
+        Object::Foreign(foreign) => format!("{}", foreign),
+        Object::List(list) => format!(
+            "[{}]",
+            list.borrow()
+                .iter()
+                .map(|element| stringify_nested(element, enclosing))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Object::Map(map) => format!(
+            "{{{}}}",
+            map.borrow()
+                .iter()
+                .map(|(key, value)| format!(
+                    "{}: {}",
+                    stringify(&native::from_key(key)),
+                    stringify_nested(value, enclosing)
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    };
+
+    if let Some(address) = address {
+        enclosing.remove(&address);
     }
+    res
 }
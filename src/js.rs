@@ -0,0 +1,562 @@
+// `rustlox emit-js`: transpiles a program to JavaScript, so it can run in a
+// browser (or Node). The operators, calls and property accesses go through
+// the helpers of `js_runtime.js`, which keep Lox's semantics (truthiness,
+// runtime errors, stringification). Natives are mapped by `NATIVES`, the
+// ones with no JavaScript counterpart fail when called.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    symbol::Symbol,
+    token::{Literal, Span, Token, TokenType},
+    visitor::{ExprVisitor, StmtVisitor},
+};
+
+const RUNTIME: &str = include_str!("js_runtime.js");
+
+// The JavaScript of each native, see `Interpreter::new`
+const NATIVES: &[(&str, &str)] = &[
+    ("clock", "() => Date.now() / 1000"),
+    ("monotonicClock", "() => performance.now() / 1000"),
+    ("Math", "$Math"),
+    ("random", "() => Math.random()"),
+    (
+        "randomInt",
+        "(lo, hi) => lo + Math.floor(Math.random() * (hi - lo + 1))",
+    ),
+    ("shuffle", "$shuffle"),
+    (
+        "num",
+        "(s) => (s.trim() === \"\" || isNaN(Number(s)) ? null : Number(s))",
+    ),
+    ("str", "(value) => $str(value)"),
+    ("sum", "(xs) => $sum($numbersOf(xs))"),
+    ("min", "(xs) => Math.min(...$aggregate(xs, \"min\"))"),
+    ("max", "(xs) => Math.max(...$aggregate(xs, \"max\"))"),
+    ("avg", "(xs) => $sum($aggregate(xs, \"avg\")) / xs.length"),
+    ("parseInt", "$parseInt"),
+    ("ord", "(c) => c.codePointAt(0)"),
+    ("chr", "(n) => String.fromCodePoint(n)"),
+    (
+        "codePoints",
+        "(s) => Array.from(s, (c) => c.codePointAt(0))",
+    ),
+    ("encode", "$encode"),
+    ("format", "$format"),
+    ("panic", "(value) => $error($str(value))"),
+    ("uuid", "() => crypto.randomUUID()"),
+];
+
+// Words JavaScript reserves that Lox doesn't, renamed with a trailing `$`
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "case",
+    "catch",
+    "const",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "finally",
+    "function",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "static",
+    "switch",
+    "throw",
+    "try",
+    "typeof",
+    "undefined",
+    "void",
+    "with",
+    "yield",
+];
+
+// The whole script: the runtime, the `natives` (every global defined before
+// the program runs) and the script's `scriptPath` and `args`, then the
+// program itself in a function, so a top-level `return` sets the exit code
+pub fn to_js(path: &str, natives: &[Symbol], statements: &[Option<Stmt>]) -> String {
+    let mut res: String = RUNTIME.to_owned();
+
+    // Defined in the function too, so they don't hide the globals the
+    // runtime uses (e.g. `Math`)
+    res += "\nconst $code = (() => {\n";
+    for native in natives {
+        let name: &str = &native.as_str();
+        match NATIVES.iter().find(|(lox, _)| *lox == name) {
+            Some((_, js)) if name == "Math" => res += &format!("  var {} = {};\n", name, js),
+            Some((_, js)) => res += &format!("  var {} = $native({});\n", name, js),
+            None => res += &format!("  var {} = $unsupported(\"{}\");\n", name, name),
+        }
+    }
+    res += &format!("  var scriptPath = {};\n", string(path));
+    res += "  var args = typeof process !== \"undefined\" ? process.argv.slice(2) : [];\n";
+
+    res += "\n  try {";
+    let mut emitter = JsEmitter {
+        globals: natives
+            .iter()
+            .map(|native| native.as_str().to_string())
+            .chain(["scriptPath".to_owned(), "args".to_owned()])
+            .collect(),
+        ..Default::default()
+    };
+    let program: String = emitter.lines(statements.iter().flatten());
+    for line in program.lines().skip(1) {
+        match line.is_empty() {
+            true => res.push('\n'),
+            false => res += &format!("\n  {}", line),
+        }
+    }
+    res += "
+  } catch (error) {
+    if (!(error instanceof $RuntimeError) && !(error instanceof ReferenceError)) throw error;
+    console.log(error.message);
+    return 70;
+  }
+})();
+if (typeof process !== \"undefined\" && typeof $code === \"number\") {
+  if (Number.isInteger($code) && $code >= 0 && $code <= 255) {
+    process.exitCode = $code;
+  } else {
+    console.error(\"Exit code must be an integer from 0 to 255, got \" + $number($code) + \".\");
+    process.exitCode = 70;
+  }
+}
+";
+
+    res
+}
+
+#[derive(Default)]
+pub struct JsEmitter {
+    // How deep the statement being emitted is nested
+    depth: usize,
+    // In an `init` method, which returns `this`
+    in_init: bool,
+    // The JavaScript name of every local in scope, innermost scope last
+    scopes: Vec<HashMap<String, String>>,
+    // The globals declared so far
+    globals: HashSet<String>,
+    // Numbers the locals renamed by `define`
+    renamed: usize,
+}
+
+impl JsEmitter {
+    // `statements`, one level deeper, each on a line of its own
+    fn lines<'a>(&mut self, statements: impl IntoIterator<Item = &'a Stmt>) -> String {
+        self.depth += 1;
+
+        let mut res = String::new();
+        for stmt in statements {
+            res.push('\n');
+            res.push_str(&"  ".repeat(self.depth));
+            res.push_str(&stmt.accept(self));
+        }
+
+        self.depth -= 1;
+        res
+    }
+
+    // A block of `statements`, ending on its own line
+    fn block<'a>(&mut self, statements: impl IntoIterator<Item = &'a Stmt>) -> String {
+        format!(
+            "{{{}\n{}}}",
+            self.lines(statements),
+            "  ".repeat(self.depth)
+        )
+    }
+
+    // The body of a branch or loop, always a block
+    fn body(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Block { .. } => stmt.accept(self),
+            stmt => self.block([stmt]),
+        }
+    }
+
+    // Top-level declarations use `var`, so a script may declare a global
+    // twice like in Lox
+    fn declare(&self) -> &'static str {
+        match self.scopes.is_empty() {
+            true => "var",
+            false => "let",
+        }
+    }
+
+    // The JavaScript name of the variable `name` declares. A local that
+    // hides another variable gets a name of its own: JavaScript's `let` is in
+    // scope in its whole block, while in Lox code before the declaration
+    // still sees the outer variable.
+    fn define(&mut self, name: &Token) -> String {
+        let js: String = self::name(name);
+        let Some(scope) = self.scopes.last() else {
+            self.globals.insert(js.clone());
+            return js;
+        };
+        if scope.contains_key(&name.lexeme) {
+            return scope[&name.lexeme].clone();
+        }
+
+        let hides: bool = self.globals.contains(&js)
+            || self
+                .scopes
+                .iter()
+                .any(|scope| scope.contains_key(&name.lexeme));
+        let js: String = match hides {
+            true => {
+                self.renamed += 1;
+                format!("{}${}", js, self.renamed)
+            }
+            false => js,
+        };
+        let scope: &mut HashMap<String, String> = self.scopes.last_mut().unwrap();
+        scope.insert(name.lexeme.clone(), js.clone());
+        js
+    }
+
+    // The JavaScript name of the variable `name` refers to, as `Resolver`
+    // would find it
+    fn resolve(&self, name: &Token) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme).cloned())
+            .unwrap_or_else(|| self::name(name))
+    }
+
+    // The parameter list and the body of a function, which share a scope
+    fn function(&mut self, params: &[Token], body: &[Option<Box<Stmt>>]) -> (String, String) {
+        self.scopes.push(HashMap::new());
+        let params: Vec<String> = params.iter().map(|param| self.define(param)).collect();
+        let body: String = self.block(body.iter().flatten().map(|s| &**s));
+        self.scopes.pop();
+        (params.join(", "), body)
+    }
+
+    fn call(&mut self, function: &str, args: &[&Expr]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.accept(self)).collect();
+        format!("{}({})", function, args.join(", "))
+    }
+}
+
+// A Lox identifier as a JavaScript one
+fn name(token: &Token) -> String {
+    match RESERVED.contains(&token.lexeme.as_str()) {
+        true => format!("{}$", token.lexeme),
+        false => token.lexeme.clone(),
+    }
+}
+
+fn string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+impl ExprVisitor<String> for JsEmitter {
+    fn visit_assign(&mut self, _id: ExprId, name: &Token, value: &Expr) -> String {
+        let value: String = value.accept(self);
+        format!("({} = {})", self.resolve(name), value)
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        let helper: &str = match operator.token_type {
+            TokenType::Plus => "$add",
+            TokenType::Minus => "$sub",
+            TokenType::Star => "$mul",
+            TokenType::Slash => "$div",
+            TokenType::Less => "$lt",
+            TokenType::LessEqual => "$le",
+            TokenType::Greater => "$gt",
+            TokenType::GreaterEqual => "$ge",
+            TokenType::EqualEqual => "$equal",
+            TokenType::BangEqual => "!$equal",
+            _ => unreachable!(),
+        };
+        self.call(helper, &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Box<Expr>]) -> String {
+        let args: Vec<&Expr> = std::iter::once(callee)
+            .chain(arguments.iter().map(|arg| &**arg))
+            .collect();
+        self.call("$call", &args)
+    }
+
+    fn visit_get(&mut self, _id: ExprId, object: &Expr, name: &Token) -> String {
+        format!("$get({}, {})", object.accept(self), string(&name.lexeme))
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> String {
+        format!("({})", expression.accept(self))
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+        self.call("$index", &[object, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> String {
+        self.call("$setIndex", &[object, index, value])
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Box<Expr>]) -> String {
+        let elements: Vec<String> = elements.iter().map(|x| x.accept(self)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        match value {
+            Literal::None => "null".to_owned(),
+            Literal::String(val) => string(val),
+            Literal::Bytes(val) => format!(
+                "new Uint8Array([{}])",
+                val.iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Literal::Boolean(val) => val.to_string(),
+            Literal::Number(val) => val.to_string(),
+        }
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Box<Expr>, Box<Expr>)]) -> String {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| format!("[{}, {}]", key.accept(self), value.accept(self)))
+            .collect();
+        format!("$map([{}])", entries.join(", "))
+    }
+
+    // The right operand is only evaluated if needed, in an arrow function
+    // that `$t`, the left one, can't clash with
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        let (left, right) = (left.accept(self), right.accept(self));
+        match operator.token_type {
+            TokenType::Or => format!("(($t) => $truthy($t) ? $t : {})({})", right, left),
+            _ => format!("(($t) => $truthy($t) ? {} : $t)({})", right, left),
+        }
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> String {
+        format!(
+            "$set({}, {}, {})",
+            object.accept(self),
+            string(&name.lexeme),
+            value.accept(self)
+        )
+    }
+
+    fn visit_super(&mut self, _id: ExprId, _keyword: &Token, method: &Token) -> String {
+        format!("super[{}].bind(this)", string(&method.lexeme))
+    }
+
+    fn visit_this(&mut self, _id: ExprId, _keyword: &Token) -> String {
+        "this".to_owned()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
+        match operator.token_type {
+            TokenType::Bang => format!("!$truthy({})", right.accept(self)),
+            _ => self.call("$neg", &[right]),
+        }
+    }
+
+    fn visit_variable(&mut self, _id: ExprId, name: &Token) -> String {
+        self.resolve(name)
+    }
+}
+
+impl StmtVisitor<String> for JsEmitter {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) -> String {
+        self.scopes.push(HashMap::new());
+        let res: String = self.block(statements.iter().flatten().map(|s| &**s));
+        self.scopes.pop();
+        res
+    }
+
+    fn visit_break(&mut self, _keyword: &Token) -> String {
+        "break;".to_owned()
+    }
+
+    // A JavaScript class whose methods are on the prototype. `$call` makes
+    // instances, so there is no constructor.
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Box<Stmt>],
+    ) -> String {
+        let superclass: String = match superclass {
+            Some(superclass) => format!("$superclass({})", superclass.accept(self)),
+            None => "$Instance".to_owned(),
+        };
+        let declare: &str = self.declare();
+        let js: String = self.define(name);
+
+        self.depth += 1;
+        let mut body = format!("\n{}static $class = true;", "  ".repeat(self.depth));
+        for method in methods {
+            if let Stmt::Function {
+                name,
+                params,
+                body: statements,
+                ..
+            } = &**method
+            {
+                let enclosing: bool = self.in_init;
+                self.in_init = name.lexeme == "init";
+                let mut statements: Vec<Option<Box<Stmt>>> = statements.clone();
+                // An initializer returns `this`, even without `return`
+                if self.in_init {
+                    statements.push(Some(Box::new(Stmt::Return {
+                        keyword: name.clone(),
+                        value: None,
+                        span: Span::of(name),
+                    })));
+                }
+                // Method names are looked up as strings, so they're kept as
+                // they are, quoted if they'd be taken for the constructor
+                let method: String = match name.lexeme.as_str() {
+                    "constructor" => "[\"constructor\"]".to_owned(),
+                    lexeme => lexeme.to_owned(),
+                };
+                let (params, statements) = self.function(params, &statements);
+                body += &format!(
+                    "\n\n{}{}({}) {}",
+                    "  ".repeat(self.depth),
+                    method,
+                    params,
+                    statements
+                );
+                self.in_init = enclosing;
+            }
+        }
+        self.depth -= 1;
+
+        format!(
+            "{} {} = class {} extends {} {{{}\n{}}};",
+            declare,
+            js,
+            self::name(name),
+            superclass,
+            body,
+            "  ".repeat(self.depth)
+        )
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token) -> String {
+        "continue;".to_owned()
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) -> String {
+        format!("{};", expression.accept(self))
+    }
+
+    // An arrow function, which keeps the `this` of the method it's nested in
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
+    ) -> String {
+        let enclosing: bool = self.in_init;
+        self.in_init = false;
+        let declare: &str = self.declare();
+        let js: String = self.define(name);
+        let (params, body) = self.function(params, body);
+        self.in_init = enclosing;
+
+        // A renamed function keeps its name when printed
+        match js == self::name(name) {
+            true => format!("{} {} = ({}) => {};", declare, js, params, body),
+            false => format!(
+                "{} {} = $named({}, ({}) => {});",
+                declare,
+                js,
+                string(&name.lexeme),
+                params,
+                body
+            ),
+        }
+    }
+
+    fn visit_if(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> String {
+        let mut res: String = format!(
+            "if ($truthy({})) {}",
+            condition.accept(self),
+            self.body(then_branch)
+        );
+        if let Some(else_branch) = else_branch {
+            res += &format!(" else {}", self.body(else_branch));
+        }
+
+        res
+    }
+
+    fn visit_print(&mut self, _keyword: &Token, expression: &Expr, stderr: bool) -> String {
+        let console: &str = if stderr { "error" } else { "log" };
+        format!("console.{}($str({}));", console, expression.accept(self))
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> String {
+        match value {
+            Some(value) => format!("return {};", value.accept(self)),
+            None if self.in_init => "return this;".to_owned(),
+            None => "return null;".to_owned(),
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> String {
+        let value: String = match initializer {
+            Some(initializer) => initializer.accept(self),
+            None => "null".to_owned(),
+        };
+        format!("{} {} = {};", self.declare(), self.define(name), value)
+    }
+
+    // `continue` still runs a `for` loop's increment
+    fn visit_while(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> String {
+        let increment: String = increment.map(|x| x.accept(self)).unwrap_or_default();
+        format!(
+            "for (; $truthy({}); {}) {}",
+            condition.accept(self),
+            increment,
+            self.body(body)
+        )
+    }
+}
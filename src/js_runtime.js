@@ -0,0 +1,426 @@
+// The runtime of Lox programs transpiled by `rustlox emit-js`. Lox values
+// are mapped to JavaScript ones (nil is null, lists are arrays, maps are
+// Maps, bytes are Uint8Arrays), the helpers below give them Lox semantics.
+// Every name starts with `$`, which Lox identifiers can't contain.
+
+class $RuntimeError extends Error {}
+
+function $error(message) {
+  throw new $RuntimeError(message);
+}
+
+// The base class of every Lox class
+class $Instance {}
+
+function $truthy(value) {
+  return value !== null && value !== undefined && value !== false;
+}
+
+// `enclosing` holds the lists and maps `value` is nested in, one containing
+// itself prints as `[...]` or `{...}` there
+function $str(value, enclosing = new Set()) {
+  if (value === null || value === undefined) return "nil";
+  if (typeof value === "number") return $number(value);
+  if (typeof value === "string" || typeof value === "boolean") return String(value);
+  if (value instanceof Uint8Array) return $escapeBytes(value);
+  if (Array.isArray(value) || value instanceof Map) {
+    const list = Array.isArray(value);
+    if (enclosing.has(value)) return list ? "[...]" : "{...}";
+    enclosing.add(value);
+    const res = list
+      ? "[" + value.map((x) => $str(x, enclosing)).join(", ") + "]"
+      : "{" + Array.from(value, ([key, val]) => $str(key) + ": " + $str(val, enclosing)).join(", ") + "}";
+    enclosing.delete(value);
+    return res;
+  }
+  if (typeof value === "function") {
+    if (value.$class) return value.name;
+    if (value.$native) return "<native fn>";
+    return "<fn " + value.name.replace(/^bound /, "") + ">";
+  }
+  if (value instanceof $Instance) return value.constructor.name + " instance";
+  if (value === $Math) return "Math instance";
+  return String(value);
+}
+
+// See `util::format_number`, which switches to exponents where JavaScript
+// does, only without the `+`
+function $number(n) {
+  if (Object.is(n, -0)) return "-0";
+  if (Number.isNaN(n)) return "nan";
+  if (n === Infinity) return "inf";
+  if (n === -Infinity) return "-inf";
+  return String(n).replace("e+", "e");
+}
+
+// See `util::escape_bytes`
+function $escapeBytes(bytes) {
+  const escapes = { 0x0a: "\\n", 0x0d: "\\r", 0x09: "\\t", 0x5c: "\\\\", 0x22: '\\"' };
+  return 'b"' + Array.from(bytes, (byte) =>
+    escapes[byte] ?? (byte >= 0x20 && byte < 0x7f
+      ? String.fromCharCode(byte)
+      : "\\x" + byte.toString(16).padStart(2, "0"))).join("") + '"';
+}
+
+// `compared` maps each list or map compared further up to the ones it's
+// compared with, a pair met again is equal, see `interpreter::is_equal_nested`
+function $equal(a, b, compared = new Map()) {
+  if (a === b) return true;
+  if ((Array.isArray(a) && Array.isArray(b)) || (a instanceof Map && b instanceof Map)) {
+    const seen = compared.get(a) ?? new Set();
+    if (seen.has(b)) return true;
+    compared.set(a, seen.add(b));
+  }
+  if (Array.isArray(a) && Array.isArray(b)) {
+    return a.length === b.length && a.every((x, i) => $equal(x, b[i], compared));
+  }
+  if (a instanceof Map && b instanceof Map) {
+    return a.size === b.size && Array.from(a).every(([key, x]) => b.has(key) && $equal(x, b.get(key), compared));
+  }
+  if (a instanceof Uint8Array && b instanceof Uint8Array) {
+    return a.length === b.length && a.every((x, i) => x === b[i]);
+  }
+  return false;
+}
+
+function $numbers(a, b) {
+  if (typeof a !== "number" || typeof b !== "number") $error("Operands must be numbers.");
+}
+
+function $add(a, b) {
+  if (typeof a === "number" && typeof b === "number") return a + b;
+  if (typeof a === "string" && typeof b === "string") return a + b;
+  if (a instanceof Uint8Array && b instanceof Uint8Array) {
+    const res = new Uint8Array(a.length + b.length);
+    res.set(a);
+    res.set(b, a.length);
+    return res;
+  }
+  if (Array.isArray(a) && Array.isArray(b)) return a.concat(b);
+  $error("Operands must be both numbers, strings, lists, or bytes.");
+}
+
+const $sub = (a, b) => ($numbers(a, b), a - b);
+const $mul = (a, b) => ($numbers(a, b), a * b);
+const $div = (a, b) => ($numbers(a, b), a / b);
+const $lt = (a, b) => ($numbers(a, b), a < b);
+const $le = (a, b) => ($numbers(a, b), a <= b);
+const $gt = (a, b) => ($numbers(a, b), a > b);
+const $ge = (a, b) => ($numbers(a, b), a >= b);
+
+function $neg(value) {
+  if (typeof value !== "number") $error("Operand must be a number.");
+  return -value;
+}
+
+// Calling a class makes an instance and runs its `init`
+function $call(callee, ...args) {
+  if (typeof callee !== "function") $error("Can only call functions and classes.");
+  if (callee.$class) {
+    const instance = new callee();
+    if (typeof instance.init === "function") instance.init(...args);
+    return instance;
+  }
+  const res = callee(...args);
+  return res === undefined ? null : res;
+}
+
+function $named(name, f) {
+  return Object.defineProperty(f, "name", { value: name });
+}
+
+function $superclass(value) {
+  if (typeof value !== "function" || !value.$class) $error("Superclass must be a class.");
+  return value;
+}
+
+function $map(entries) {
+  return new Map(entries);
+}
+
+function $listIndex(list, index) {
+  if (!Number.isInteger(index)) $error("Index must be an integer.");
+  if (index < 0 || index >= list.length) $error("Index out of bounds.");
+  return index;
+}
+
+function $index(object, index) {
+  if (Array.isArray(object) || object instanceof Uint8Array) return object[$listIndex(object, index)];
+  if (object instanceof Map) return object.has(index) ? object.get(index) : null;
+  $error("Only lists, maps and bytes can be indexed.");
+}
+
+function $setIndex(object, index, value) {
+  if (Array.isArray(object)) object[$listIndex(object, index)] = value;
+  else if (object instanceof Map) object.set(index, value);
+  else $error("Only lists and maps can be indexed.");
+  return value;
+}
+
+function $round(x) {
+  return Math.sign(x) * Math.round(Math.abs(x));
+}
+
+// The methods of values that aren't instances
+const $methods = {
+  string: {
+    length: (s) => () => Array.from(s).length,
+    upper: (s) => () => s.toUpperCase(),
+    lower: (s) => () => s.toLowerCase(),
+    trim: (s) => () => s.trim(),
+    split: (s) => (separator) => s.split(separator),
+    contains: (s) => (x) => s.includes(x),
+    startsWith: (s) => (x) => s.startsWith(x),
+    endsWith: (s) => (x) => s.endsWith(x),
+    replace: (s) => (from, to) => s.replaceAll(from, to),
+  },
+  number: {
+    floor: (n) => () => Math.floor(n),
+    ceil: (n) => () => Math.ceil(n),
+    round: (n) => () => $round(n),
+    abs: (n) => () => Math.abs(n),
+    toFixed: (n) => (digits) => {
+      if (!Number.isInteger(digits)) $error("Argument 1 must be an integer.");
+      if (digits < 0 || digits > 100) $error(`toFixed() takes 0 to 100 digits, got ${digits}.`);
+      return Number.isFinite(n) ? n.toFixed(digits) : $number(n);
+    },
+  },
+  bytes: {
+    length: (b) => () => b.length,
+    slice: (b) => (start, end) => b.slice(start, end),
+    decode: (b) => (encoding) => new TextDecoder(encoding).decode(b),
+    toList: (b) => () => Array.from(b),
+  },
+  list: {
+    length: (xs) => () => xs.length,
+    map: (xs) => (f) => xs.slice().map((x) => $call(f, x)),
+    filter: (xs) => (f) => xs.slice().filter((x) => $truthy($call(f, x))),
+    join: (xs) => (separator) =>
+      xs.map((x) => (typeof x === "string" ? x : $str(x))).join(separator),
+    reduce: (xs) => (f, initial) => xs.slice().reduce((acc, x) => $call(f, acc, x), initial),
+    sort: (xs) => (comparator) => {
+      xs.sort(comparator === null
+        ? (a, b) => (a < b ? -1 : a > b ? 1 : 0)
+        : (a, b) => $call(comparator, a, b));
+      return null;
+    },
+    push: (xs) => (x) => (xs.push(x), null),
+    pop: (xs) => () => (xs.length === 0 ? $error("Can't pop from an empty list.") : xs.pop()),
+    insert: (xs) => (i, x) => (xs.splice(i, 0, x), null),
+    remove: (xs) => (i) => xs.splice($listIndex(xs, i), 1)[0],
+  },
+  map: {
+    size: (m) => () => m.size,
+    keys: (m) => () => Array.from(m.keys()),
+    values: (m) => () => Array.from(m.values()),
+    has: (m) => (key) => m.has(key),
+    remove: (m) => (key) => {
+      const res = m.has(key) ? m.get(key) : null;
+      m.delete(key);
+      return res;
+    },
+    merge: (m) => (other) => new Map([...m, ...other]),
+  },
+};
+
+function $typeOf(value) {
+  if (typeof value === "string") return "string";
+  if (typeof value === "number") return "number";
+  if (value instanceof Uint8Array) return "bytes";
+  if (Array.isArray(value)) return "list";
+  if (value instanceof Map) return "map";
+  return null;
+}
+
+// `object.name`: a field, or a method bound to `object`
+function $get(object, name) {
+  const type = $typeOf(object);
+  if (type !== null) {
+    const method = Object.prototype.hasOwnProperty.call($methods[type], name) ? $methods[type][name] : undefined;
+    if (method === undefined) $error("Undefined property '" + name + "'.");
+    return Object.defineProperty(method(object), "name", { value: name });
+  }
+  if (object === null || typeof object !== "object") $error("Only instances have properties.");
+  if (Object.prototype.hasOwnProperty.call(object, name)) return object[name];
+  const method = object[name];
+  if (method === undefined) $error("Undefined property '" + name + "'.");
+  return typeof method === "function" ? method.bind(object) : method;
+}
+
+function $set(object, name, value) {
+  if (!(object instanceof $Instance)) $error("Only instances have fields");
+  object[name] = value;
+  return value;
+}
+
+// `format()` and its placeholders, see `format.rs`
+const $MAX_WIDTH = 1024;
+
+function $format(template, ...args) {
+  const chars = Array.from(template);
+  let res = "";
+  let nextArg = 0;
+
+  for (let i = 0; i < chars.length; i++) {
+    const c = chars[i];
+    if ((c === "{" || c === "}") && chars[i + 1] === c) {
+      res += c;
+      i++;
+    } else if (c === "}") {
+      $error("Unmatched '}' in format string.");
+    } else if (c === "{") {
+      const end = chars.indexOf("}", i + 1);
+      if (end === -1) $error("Unclosed '{' in format string.");
+      const placeholder = chars.slice(i + 1, end).join("");
+      i = end;
+
+      const colon = placeholder.indexOf(":");
+      const index = colon === -1 ? placeholder : placeholder.slice(0, colon);
+      const spec = colon === -1 ? {} : $parseSpec(placeholder.slice(colon + 1));
+
+      let arg;
+      if (index === "") arg = nextArg++;
+      else if (/^[0-9]+$/.test(index)) arg = Number(index);
+      else $error("Invalid placeholder '{" + placeholder + "}'.");
+
+      if (arg >= args.length) {
+        $error("Format string refers to argument " + arg + " but only " + args.length + " were given.");
+      }
+      res += $render(args[arg], spec);
+    } else {
+      res += c;
+    }
+  }
+
+  return res;
+}
+
+// `[[fill]align][0][width][.precision][type]`
+function $parseSpec(spec) {
+  const match = /^(?:(.)?([<>^]))?(0)?([0-9]*)(?:\.([0-9]*))?([xXobe])?$/u.exec(spec);
+  if (match === null || match[5] === "") $error("Invalid format spec ':" + spec + "'.");
+  const [, fill, align, zero, width, precision, kind] = match;
+  return {
+    fill,
+    align,
+    zero: zero !== undefined,
+    width: $specNumber(width, "width") ?? 0,
+    precision: $specNumber(precision, "precision"),
+    kind,
+  };
+}
+
+function $specNumber(digits, what) {
+  if (digits === undefined || digits === "") return undefined;
+  const number = Number(digits);
+  if (number > $MAX_WIDTH) $error("Format " + what + " " + digits + " is too large, the maximum is " + $MAX_WIDTH + ".");
+  return number;
+}
+
+function $render(value, spec) {
+  const { kind, precision } = spec;
+  const isNumber = typeof value === "number";
+  let body;
+
+  if (isNumber && kind !== undefined && kind !== "e") {
+    if (!Number.isInteger(value)) $error("Format type '" + kind + "' requires an integer, got " + $number(value) + ".");
+    const radix = { x: 16, X: 16, o: 8, b: 2 }[kind];
+    const digits = BigInt(Math.abs(value)).toString(radix);
+    body = (value < 0 ? "-" : "") + (kind === "X" ? digits.toUpperCase() : digits);
+  } else if (isNumber && kind === "e") {
+    body = Number.isFinite(value) ? value.toExponential(precision).replace("e+", "e") : $number(value);
+  } else if (isNumber) {
+    body = precision !== undefined && Number.isFinite(value) ? value.toFixed(precision) : $number(value);
+  } else if (kind !== undefined) {
+    $error("Format type '" + kind + "' requires a number.");
+  } else {
+    body = $str(value);
+    if (precision !== undefined) body = Array.from(body).slice(0, precision).join("");
+  }
+
+  return $pad(body, spec, isNumber);
+}
+
+function $pad(body, spec, isNumber) {
+  const missing = spec.width - Array.from(body).length;
+  if (missing <= 0) return body;
+
+  // Zero padding goes between the sign and the digits
+  if (spec.zero && isNumber && spec.align === undefined) {
+    return body.startsWith("-") ? "-" + "0".repeat(missing) + body.slice(1) : "0".repeat(missing) + body;
+  }
+
+  const fill = spec.fill ?? " ";
+  switch (spec.align ?? (isNumber ? ">" : "<")) {
+    case ">":
+      return fill.repeat(missing) + body;
+    case "^":
+      return fill.repeat(Math.floor(missing / 2)) + body + fill.repeat(missing - Math.floor(missing / 2));
+    default:
+      return body + fill.repeat(missing);
+  }
+}
+
+// See `native::encode`
+function $encode(s, encoding) {
+  if (encoding === "utf-8" || encoding === "utf8") return new TextEncoder().encode(s);
+  if (encoding !== "latin1" && encoding !== "ascii") {
+    $error("Unknown encoding '" + encoding + "', expected 'utf-8', 'latin1' or 'ascii'.");
+  }
+  const max = encoding === "ascii" ? 0x7f : 0xff;
+  const chars = Array.from(s);
+  const c = chars.find((c) => c.codePointAt(0) > max);
+  if (c !== undefined) $error("Can't encode '" + c + "' as " + encoding + ".");
+  return Uint8Array.from(chars, (c) => c.codePointAt(0));
+}
+
+function $unsupported(name) {
+  const native = () => $error("'" + name + "' isn't available in JavaScript.");
+  native.$native = true;
+  return native;
+}
+
+function $native(f) {
+  f.$native = true;
+  return f;
+}
+
+function $numbersOf(xs) {
+  if (!Array.isArray(xs) || !xs.every((x) => typeof x === "number")) {
+    $error("Argument 1 must be a list of numbers.");
+  }
+  return xs;
+}
+
+// The numbers of an aggregate that is undefined for an empty list
+function $aggregate(xs, name) {
+  if ($numbersOf(xs).length === 0) $error("Can't take the " + name + " of an empty list.");
+  return xs;
+}
+
+// nil unless all of `s` is an integer in base `radix`
+function $parseInt(s, radix) {
+  if (!(radix >= 2 && radix <= 36)) $error("parseInt() base must be between 2 and 36, got " + radix + ".");
+  const text = s.trim();
+  const digits = Array.from(text.replace(/^[+-]/, "").toLowerCase());
+  if (digits.length === 0 || !digits.every((c) => parseInt(c, 36) < radix)) return null;
+  return parseInt(text, radix);
+}
+
+function $sum(xs) {
+  return xs.reduce((a, b) => a + b, 0);
+}
+
+function $shuffle(xs) {
+  for (let i = xs.length - 1; i > 0; i--) {
+    const j = Math.floor(Math.random() * (i + 1));
+    [xs[i], xs[j]] = [xs[j], xs[i]];
+  }
+  return null;
+}
+
+// Lox's `Math` module, with its rounding
+const $Math = Object.freeze({
+  ...Object.fromEntries(Object.getOwnPropertyNames(Math).map((name) => [name, Math[name]])),
+  round: $round,
+});
@@ -0,0 +1,84 @@
+// The interpreter as a library, for Rust programs that embed Lox. The
+// `lox` binary (`main.rs`) is a command line over it.
+//
+//     let mut lox = rustlox::Lox::new();
+//     let value: rustlox::Value = lox.run_source("return 1 + 2;")?;
+//     lox.run_source("fn greet(name) { return \"Hi \" + name; }")?;
+//     let greeting: String = lox.call("greet", ("Lox",))?;
+//
+// `Lox`, `Interpreter`, `parse`, `Value`, `Diagnostic`, the conversions
+// between Rust and Lox values (`ToLox`, `FromLox`, `IntoArgs`) and the
+// classes wrapping Rust types (`foreign`) are the stable API. The modules
+// are public too, for the tools built on them, but their items may change.
+//
+// Failures come back as `Diagnostic`s or exit codes for the caller to act
+// on, nothing here exits the process (but Ctrl-C once a REPL installed its
+// handler and none is running, see `Lox::run_prompt`).
+
+// `LoxError` carries whole `Object`s (e.g. for `Return`), so it is large by design.
+#![allow(clippy::result_large_err)]
+
+pub mod ast;
+#[cfg(feature = "async")]
+pub mod async_native;
+pub mod callable;
+pub mod capabilities;
+pub mod class;
+pub mod compiler;
+pub mod convert;
+pub mod coverage;
+pub mod debugger;
+pub mod diagnostic;
+pub mod doc;
+pub mod environment;
+pub mod error;
+pub mod expr;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+pub mod foreign;
+pub mod format;
+pub mod highlight;
+pub mod interpreter;
+pub mod js;
+pub mod lint;
+pub mod lox;
+pub mod lsp;
+pub mod memory;
+pub mod minify;
+pub mod native;
+pub mod object;
+pub mod optimizer;
+pub mod parser;
+pub mod plugin;
+pub mod pretty;
+pub mod profiler;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod resolver;
+pub mod scanner;
+pub mod state;
+pub mod stmt;
+pub mod symbol;
+pub mod sync;
+pub mod time;
+pub mod timings;
+pub mod token;
+pub mod util;
+pub mod visitor;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+pub use convert::{FromLox, IntoArgs, ToLox};
+pub use diagnostic::Diagnostic;
+pub use interpreter::Interpreter;
+pub use lox::Lox;
+// Any Lox value, as scripts and natives pass it around
+pub use object::Object as Value;
+
+// Parses `source` without running it. `None` statements are those the
+// parser recovered from, there are none when it succeeds.
+pub fn parse(source: &str) -> Result<Vec<Option<stmt::Stmt>>, Vec<Diagnostic>> {
+    Lox::new().parse(source.to_owned())
+}
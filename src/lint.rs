@@ -0,0 +1,313 @@
+// `rustlox lint`: the resolver's warnings, plus checks of its own that
+// don't belong in every run (empty blocks, constant conditions, overly long
+// functions). Every check is a rule that can be allowed, kept as a warning
+// or denied (reported as an error), in a config file or on the command line.
+
+use std::{collections::HashMap, fs};
+
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticKind},
+    expr::Expr,
+    optimizer,
+    stmt::Stmt,
+    token::{Literal, Span, Token},
+    visitor::StmtVisitor,
+};
+
+// Read from the current directory if there is one, see `Config::parse`
+pub const CONFIG_PATH: &str = ".loxlint";
+
+// Every rule, with what it checks
+pub const RULES: &[(&str, &str)] = &[
+    ("unused-variable", "a local variable that is never read"),
+    (
+        "shadowing",
+        "a local variable that hides one of an outer scope",
+    ),
+    ("self-assignment", "a variable assigned to itself"),
+    ("unreachable-code", "code after return, break or continue"),
+    ("empty-block", "a branch or loop body with nothing in it"),
+    (
+        "constant-condition",
+        "an if or while whose condition never changes",
+    ),
+    ("long-function", "a function with too many statements"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    // Reported as an error, `rustlox lint` fails
+    Deny,
+}
+
+impl Level {
+    fn parse(level: &str) -> Option<Level> {
+        match level {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+// Every rule warns unless configured otherwise
+pub struct Config {
+    levels: HashMap<&'static str, Level>,
+    // Above this many statements, a function is reported by `long-function`
+    pub max_function_statements: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            levels: HashMap::new(),
+            max_function_statements: 50,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read lint config '{}': {}.", path, err))?;
+        self.parse(&text)
+            .map_err(|err| format!("In lint config '{}': {}", path, err))
+    }
+
+    // One setting per line, `#` starts a comment, e.g.
+    //
+    //     unused-variable = deny
+    //     shadowing = allow
+    //     max-function-statements = 30
+    pub fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line: &str = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| format!("line {}: Expect 'rule = level'.", i + 1))?;
+
+            match name {
+                "max-function-statements" => {
+                    self.max_function_statements = value
+                        .parse()
+                        .map_err(|_| format!("line {}: Expect a number of statements.", i + 1))?;
+                }
+                _ => {
+                    let level: Level = Level::parse(value).ok_or_else(|| {
+                        format!("line {}: Expect 'allow', 'warn' or 'deny'.", i + 1)
+                    })?;
+                    self.set(name, level)
+                        .map_err(|err| format!("line {}: {}", i + 1, err))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, rule: &str, level: Level) -> Result<(), String> {
+        match RULES.iter().find(|(name, _)| *name == rule) {
+            Some((name, _)) => {
+                self.levels.insert(*name, level);
+                Ok(())
+            }
+            None => Err(format!("Unknown lint rule '{}'.", rule)),
+        }
+    }
+
+    fn level(&self, rule: &str) -> Level {
+        self.levels.get(rule).copied().unwrap_or(Level::Warn)
+    }
+}
+
+// The rule a warning of the resolver belongs to, see `Diagnostic::hint` for
+// the same matching
+fn rule_of(diagnostic: &Diagnostic) -> Option<&'static str> {
+    let rule: &str = match &diagnostic.message {
+        m if m.starts_with("Local variable is never used") => "unused-variable",
+        m if m.starts_with("Shadows the variable") => "shadowing",
+        m if m.starts_with("Assigning a variable to itself") => "self-assignment",
+        m if m.starts_with("Code after this is unreachable") => "unreachable-code",
+        _ => return None,
+    };
+
+    Some(rule)
+}
+
+// Lints a resolved program, `warnings` are the resolver's. Returns what
+// to report, with the rule's name as each one's code.
+pub fn lint(
+    statements: &[Option<Stmt>],
+    warnings: Vec<Diagnostic>,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        functions: vec![],
+        found: vec![],
+    };
+    for stmt in statements.iter().flatten() {
+        linter.lint_stmt(stmt);
+    }
+
+    let mut found: Vec<(&str, Diagnostic)> = warnings
+        .into_iter()
+        .filter_map(|warning| rule_of(&warning).map(|rule| (rule, warning)))
+        .chain(linter.found)
+        .collect();
+    found.sort_by_key(|(_, diagnostic)| diagnostic.span.as_ref().map(|span| span.start));
+
+    found
+        .into_iter()
+        .filter_map(|(rule, mut diagnostic)| {
+            diagnostic.kind = match config.level(rule) {
+                Level::Allow => return None,
+                Level::Warn => DiagnosticKind::Warning,
+                Level::Deny => DiagnosticKind::Error,
+            };
+            diagnostic.code = Some(rule);
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    // How many statements each function being linted has so far, innermost
+    // last
+    functions: Vec<usize>,
+    found: Vec<(&'static str, Diagnostic)>,
+}
+
+impl Linter<'_> {
+    fn lint_stmt(&mut self, stmt: &Stmt) {
+        // Blocks only group statements
+        if !matches!(stmt, Stmt::Block { .. }) {
+            if let Some(count) = self.functions.last_mut() {
+                *count += 1;
+            }
+        }
+
+        stmt.accept(self)
+    }
+
+    fn lint_block(&mut self, statements: &[Option<Box<Stmt>>]) {
+        for stmt in statements.iter().flatten() {
+            self.lint_stmt(stmt);
+        }
+    }
+
+    fn report(&mut self, rule: &'static str, token: &Token, message: &str) {
+        self.found.push((rule, Diagnostic::warning(token, message)));
+    }
+
+    // Reports the whole of an expression or statement
+    fn report_in(&mut self, rule: &'static str, span: Span, message: &str) {
+        self.found
+            .push((rule, Diagnostic::warning_in(span, message)));
+    }
+
+    // A body is empty if it's `{}`
+    fn check_body(&mut self, body: &Stmt, what: &str) {
+        if let Stmt::Block { statements, span } = body {
+            if statements.is_empty() {
+                self.report_in("empty-block", *span, &format!("Empty {}.", what));
+            }
+        }
+    }
+
+    // Whether `condition` folds to a literal, see `optimizer::optimize_expr`
+    fn check_condition(&mut self, condition: &Expr) {
+        if let Expr::Literal { value, .. } = optimizer::optimize_expr(condition.clone()) {
+            let message: String = format!("Condition is always {}.", optimizer::is_truthy(&value));
+            self.report_in("constant-condition", condition.span(), &message);
+        }
+    }
+}
+
+impl StmtVisitor<()> for Linter<'_> {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) {
+        self.lint_block(statements);
+    }
+
+    fn visit_break(&mut self, _keyword: &Token) {}
+
+    fn visit_class(&mut self, _name: &Token, _superclass: Option<&Expr>, methods: &[Box<Stmt>]) {
+        for method in methods {
+            method.accept(self);
+        }
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token) {}
+
+    fn visit_expression(&mut self, _expression: &Expr) {}
+
+    fn visit_function(&mut self, name: &Token, _params: &[Token], body: &[Option<Box<Stmt>>]) {
+        // A nested function's statements only count towards its own length
+        self.functions.push(0);
+        self.lint_block(body);
+        let count: usize = self.functions.pop().unwrap();
+
+        let max: usize = self.config.max_function_statements;
+        if count > max {
+            let message: String = format!(
+                "Function has {} statements, more than the {} allowed.",
+                count, max
+            );
+            self.report("long-function", name, &message);
+        }
+    }
+
+    fn visit_if(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) {
+        self.check_condition(condition);
+        self.check_body(then_branch, "'if' branch");
+        self.lint_stmt(then_branch);
+
+        if let Some(else_stmt) = else_branch {
+            self.check_body(else_stmt, "'else' branch");
+            self.lint_stmt(else_stmt);
+        }
+    }
+
+    fn visit_print(&mut self, _keyword: &Token, _expression: &Expr, _stderr: bool) {}
+
+    fn visit_return(&mut self, _keyword: &Token, _value: Option<&Expr>) {}
+
+    fn visit_var(&mut self, _name: &Token, _initializer: Option<&Expr>) {}
+
+    fn visit_while(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        _increment: Option<&Expr>,
+    ) {
+        // `while (true)`, `loop` and `for (;;)` loop forever on purpose
+        let forever: bool = matches!(
+            condition,
+            Expr::Literal {
+                value: Literal::Boolean(true),
+                ..
+            }
+        );
+        if !forever {
+            self.check_condition(condition);
+        }
+        self.check_body(body, "loop body");
+        self.lint_stmt(body);
+    }
+}
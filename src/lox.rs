@@ -1,136 +1,960 @@
 use crate::{
-    error::LoxError,
-    interpreter::Interpreter,
+    ast,
+    capabilities::Capabilities,
+    compiler,
+    convert::{FromLox, IntoArgs, ToLox},
+    coverage::Coverage,
+    debugger::Debugger,
+    diagnostic::{Diagnostic, Sources},
+    doc, highlight,
+    interpreter::{Interpreter, InterruptHandle},
+    js, lint, minify,
+    native::{self, NativeFn},
+    object::Object,
+    optimizer::Optimizer,
     parser::Parser,
+    plugin, pretty,
+    profiler::Profiler,
     resolver::Resolver,
-    scanner::Scanner,
+    scanner::{ScanError, Scanner},
+    state,
     stmt::Stmt,
-    token::{Token, TokenType},
+    symbol::Symbol,
+    sync::{MaybeSend, Rc, RefCell},
+    time::{Instant, SystemTime},
+    timings::Timings,
+    token::Token,
+    util,
 };
-use anyhow::{anyhow, Result};
-use rustyline::error::ReadlineError;
-use std::{cell::RefCell, fs, process, rc::Rc};
+use anyhow::Result;
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+// The REPL's, there's no terminal in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::repl,
+    anyhow::anyhow,
+    rustyline::{error::ReadlineError, history::DefaultHistory, Config, Editor},
+    std::{
+        mem, process,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex, OnceLock,
+        },
+    },
+};
+
+// The script path that stands for stdin, e.g. `echo 'print 1;' | rustlox -`
+pub const STDIN: &str = "-";
+
+const PRELUDE: &str = include_str!("prelude.lox");
+
+// Where `--coverage` writes its report
+const LCOV_PATH: &str = "lcov.info";
+
+// See `rc_path`
+const RC_FILE: &str = ".loxrc";
+const RC_VAR: &str = "LOXRC";
 
-static mut HAD_ERROR: bool = false;
-static mut HAD_RUNTIME_ERROR: bool = false;
+// How often `rustlox watch` checks whether the script changed
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+// A process has one Ctrl-C handler, installed by the first REPL to run and
+// shared by the ones after it. It interrupts the code of the REPL running,
+// see `run_prompt`.
+#[cfg(not(target_arch = "wasm32"))]
+static CTRL_C_HANDLER: OnceLock<Result<(), String>> = OnceLock::new();
+#[cfg(not(target_arch = "wasm32"))]
+static REPL_INTERRUPT: Mutex<Option<InterruptHandle>> = Mutex::new(None);
+// Set when Ctrl-C is pressed again before the REPL's code stopped
+#[cfg(not(target_arch = "wasm32"))]
+static REPL_ABANDONED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Default)]
 pub struct Lox {
     interpreter: Rc<RefCell<Interpreter>>,
+    // Where the next source starts, see `Token::offset`
+    next_offset: usize,
+    // Everything compiled so far, to show the lines diagnostics point at
+    pub sources: Sources,
+    // Run the `optimizer` pass before resolving
+    pub optimize: bool,
+    // Run programs with the closure backend, see `compiler`
+    pub closures: bool,
+    // Leave warnings out of `compile`'s results
+    pub hide_warnings: bool,
+    // Report diagnostics as JSON lines on stderr, see `Diagnostic::to_json`
+    pub json_diagnostics: bool,
+    // `--strict`, undefined globals are compile errors, see `Resolver::strict`
+    pub strict: bool,
+    // Set by `--time`, printed once the script or the REPL ends
+    timings: Option<Timings>,
+    // `--save-state FILE`, where the globals are saved once the script or
+    // the REPL ends, see `state::save`
+    pub save_state: Option<String>,
+    // Where the next source comes from, for `Sources`
+    source_name: String,
+    // Set once `set_stdout` or `set_stderr` replaced a terminal, whose
+    // diagnostics aren't colored then
+    output_captured: bool,
 }
 
+// With the `sync` feature, a `Lox` can be moved to another thread
+#[cfg(feature = "sync")]
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<Lox>;
+};
+
 impl Lox {
     pub fn new() -> Self {
         Lox {
             interpreter: Rc::new(RefCell::new(Interpreter::new())),
+            next_offset: 0,
+            sources: Sources::default(),
+            optimize: false,
+            closures: false,
+            hide_warnings: false,
+            json_diagnostics: false,
+            strict: false,
+            timings: None,
+            save_state: None,
+            source_name: String::new(),
+            output_captured: false,
         }
     }
 
-    pub fn run_file(&mut self, path: String) -> Result<()> {
-        let program: String = fs::read_to_string(path)?;
-        self.run(program);
+    // Also the most the script may raise it to, see `setMaxCallDepth`
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        let mut interpreter = self.interpreter.borrow_mut();
+        interpreter.max_call_depth = depth;
+        interpreter.call_depth_cap = depth;
+    }
 
-        unsafe {
-            if HAD_ERROR {
-                process::exit(65);
-            }
-            if HAD_RUNTIME_ERROR {
-                process::exit(70);
+    // Applies to every script or REPL line run from now on, see `Budget`
+    pub fn set_statement_limit(&mut self, max_statements: Option<u64>) {
+        self.interpreter.borrow_mut().budget.max_statements = max_statements;
+    }
+
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.interpreter.borrow_mut().budget.time_limit = limit;
+    }
+
+    // In bytes, of the values this interpreter's program holds, see `memory`
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.borrow_mut().budget.max_memory = limit;
+    }
+
+    // See `Profiler`, the report is printed once the script or the REPL ends
+    pub fn start_profiling(&mut self) {
+        self.interpreter.borrow_mut().profiler = Some(Profiler::default());
+    }
+
+    // See `Coverage`, only the script's statements are counted
+    pub fn start_coverage(&mut self) {
+        self.interpreter.borrow_mut().coverage = Some(Coverage::default());
+    }
+
+    // Logs every statement and call to stderr as it runs, with the values
+    // involved, e.g. `line 7: a = 3`
+    pub fn start_tracing(&mut self) {
+        self.interpreter.borrow_mut().trace = true;
+    }
+
+    // See `Debugger`, only the script at `path`, run next, is paused in
+    pub fn start_debugging(&mut self, path: &str) -> Result<()> {
+        let source: String = fs::read_to_string(path)?;
+        let mut interpreter = self.interpreter.borrow_mut();
+        let _ = writeln!(
+            interpreter.stdout,
+            "Paused at the start of the script, type 'help' for the commands."
+        );
+        interpreter.debugger = Some(Debugger::new(&source, self.next_offset));
+        Ok(())
+    }
+
+    // See `Timings`, only what's compiled and run from now on is timed
+    pub fn start_timing(&mut self) {
+        self.timings = Some(Timings::default());
+    }
+
+    // Prints a summary to stderr and writes the details to `lcov.info`
+    fn report_coverage(&self, path: &str) -> Result<()> {
+        let mut interpreter = self.interpreter.borrow_mut();
+        if let Some(coverage) = interpreter.coverage.as_ref() {
+            // Nothing to report if the script didn't compile
+            if !coverage.is_empty() {
+                let (summary, lcov) = (coverage.summary(), coverage.lcov(path));
+                let _ = writeln!(interpreter.stderr, "{}", summary);
+                fs::write(LCOV_PATH, lcov)?;
             }
         }
 
         Ok(())
     }
 
-    pub fn run_prompt(&mut self) -> Result<()> {
-        let mut rl = rustyline::DefaultEditor::new()?;
+    // To stderr, so it doesn't mix with the script's output
+    fn print_reports(&self) {
+        let mut interpreter = self.interpreter.borrow_mut();
+        if let Some(report) = interpreter.profiler.as_ref().map(Profiler::report) {
+            let _ = write!(interpreter.stderr, "{}", report);
+        }
+        if let Some(timings) = self.timings.as_ref() {
+            let _ = write!(interpreter.stderr, "{}", timings.report());
+        }
+    }
+
+    // Where the scripts' `print`s and the diagnostics go from now on, rather
+    // than stdout, e.g. a buffer a host program (or a test) reads
+    pub fn set_stdout(&mut self, output: impl Write + MaybeSend + 'static) {
+        self.interpreter.borrow_mut().stdout = Box::new(output);
+        self.output_captured = true;
+    }
+
+    // Same for stderr: `eprint`s, warnings and the reports of `--profile`,
+    // `--time` and `--coverage`
+    pub fn set_stderr(&mut self, output: impl Write + MaybeSend + 'static) {
+        self.interpreter.borrow_mut().stderr = Box::new(output);
+        self.output_captured = true;
+    }
+
+    // Where `readLine()` and `readAll()` read from from now on, rather than
+    // stdin, e.g. a `Cursor` over canned input
+    pub fn set_stdin(&mut self, input: impl BufRead + MaybeSend + 'static) {
+        self.interpreter.borrow_mut().stdin = Some(Box::new(input));
+    }
+
+    // The interpreter running everything, for what `Lox` doesn't wrap, e.g.
+    // `lox.interpreter().borrow_mut().register_class::<T>(name)`
+    pub fn interpreter(&self) -> Rc<RefCell<Interpreter>> {
+        self.interpreter.clone()
+    }
+
+    // Seeds the global `name`, e.g. with the host's configuration, before a
+    // script runs: `lox.set_global("retries", 3)`. Like `define_native`,
+    // it's a known global under `--strict`.
+    pub fn set_global(&mut self, name: &str, value: impl ToLox) {
+        self.interpreter
+            .borrow_mut()
+            .set_global(name, value.to_lox());
+    }
+
+    // Reads back the global `name`, e.g. a result the script left there.
+    // Fails if it's not defined, or doesn't convert to `R`.
+    pub fn get_global<R: FromLox>(&self, name: &str) -> Result<R, Diagnostic> {
+        let value: Option<Object> = self.interpreter.borrow().get_global(name);
+        value
+            .ok_or_else(|| native::error(format!("Undefined variable '{}'.", name)))
+            .and_then(|value| R::from_lox(&value))
+            .map_err(Diagnostic::runtime)
+    }
+
+    // See `Interpreter::interrupt_handle`
+    pub fn interrupt_handle(&mut self) -> InterruptHandle {
+        self.interpreter.borrow_mut().interrupt_handle()
+    }
+
+    // What the natives may do from now on, see `capabilities`
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.interpreter.borrow_mut().capabilities = capabilities;
+    }
+
+    // See `Interpreter::define_native`. Defined before a script compiles,
+    // it's a known global under `--strict`.
+    pub fn define_native(&mut self, name: &str, arity: usize, body: impl NativeFn) {
+        self.interpreter
+            .borrow_mut()
+            .define_native(name, arity, body);
+    }
+
+    // See `Interpreter::define_async_native`
+    #[cfg(feature = "async")]
+    pub fn define_async_native<F, Fut>(&mut self, name: &str, arity: usize, body: F)
+    where
+        F: Fn(Vec<Object>) -> Fut + MaybeSend + 'static,
+        Fut: std::future::Future<Output = Result<Object, crate::error::LoxError>> + 'static,
+    {
+        self.interpreter
+            .borrow_mut()
+            .define_async_native(name, arity, body);
+    }
+
+    // `run_source` for scripts calling async natives, on a multi-threaded
+    // tokio runtime, see `async_native`. The script holds on to one of the
+    // runtime's worker threads until it's done, async natives included.
+    #[cfg(feature = "async")]
+    pub async fn run_async(&mut self, source: &str) -> Result<Object, Vec<Diagnostic>> {
+        use tokio::runtime::{Handle, RuntimeFlavor};
+
+        // `block_in_place` panics anywhere else
+        let runtime: Handle = match Handle::try_current() {
+            Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => runtime,
+            _ => {
+                return Err(vec![Diagnostic::runtime(native::error(
+                    "run_async() needs a multi-threaded tokio runtime.".to_owned(),
+                ))])
+            }
+        };
+
+        self.interpreter.borrow_mut().runtime = Some(runtime);
+        let result = tokio::task::block_in_place(|| self.run_source(source));
+        self.interpreter.borrow_mut().runtime = None;
+        result
+    }
+
+    // Calls the global function `name` the code run so far defined, e.g.
+    // `let total: f64 = lox.call("add", (1, 2))?;`. The arguments and the
+    // result are converted by `IntoArgs` and `FromLox`. Budgeted like a
+    // script of its own.
+    pub fn call<R: FromLox>(
+        &mut self,
+        name: &str,
+        arguments: impl IntoArgs,
+    ) -> Result<R, Diagnostic> {
+        let mut interpreter = self.interpreter.borrow_mut();
+        interpreter.budget.start();
+
+        interpreter
+            .call_function(name, &arguments.into_args())
+            .and_then(|value| R::from_lox(&value))
+            .map_err(Diagnostic::runtime)
+    }
+
+    // See `plugin::load`
+    pub fn load_plugin(&mut self, path: &str) -> Result<(), String> {
+        plugin::load(&mut self.interpreter.borrow_mut(), path)
+    }
+
+    // `--load-state FILE`, see `state::load`
+    pub fn load_state(&mut self, path: &str) -> Result<(), String> {
+        state::load(&mut self.interpreter.borrow_mut(), path)
+    }
+
+    // Saves the globals where `--save-state` asked to, if it did
+    fn save_state(&self) {
+        let Some(path) = self.save_state.as_deref() else {
+            return;
+        };
+        let mut interpreter = self.interpreter.borrow_mut();
+        match state::save(&interpreter, path) {
+            Ok(skipped) => {
+                for name in skipped {
+                    let _ = writeln!(interpreter.stderr, "Global '{}' wasn't saved, it holds a function, a class, a non-finite number or itself.", name);
+                }
+            }
+            Err(message) => {
+                let _ = writeln!(interpreter.stderr, "{}", message);
+            }
+        }
+    }
+
+    // Helpers written in Lox itself, see `prelude.lox`
+    pub fn run_prelude(&mut self) {
+        self.source_name = "<prelude>".to_owned();
+        let _ = self.run(PRELUDE.to_owned(), false);
+    }
+
+    // Runs the user's own helpers, see `rc_path`, after the prelude and
+    // before any of the user's input. Fails with the exit code for its
+    // error, once that's reported along with where the file is.
+    pub fn run_rc(&mut self) -> Result<(), i32> {
+        let Some(path) = rc_path() else {
+            return Ok(());
+        };
+        let path: String = path.to_string_lossy().into_owned();
+        // A missing file is fine, unless `LOXRC` asked for it
+        let source: String = match self.read_source(&path) {
+            Ok(source) => source,
+            Err(error)
+                if error.kind() == io::ErrorKind::NotFound && env::var_os(RC_VAR).is_none() =>
+            {
+                return Ok(())
+            }
+            Err(error) => {
+                let mut interpreter = self.interpreter.borrow_mut();
+                let _ = writeln!(interpreter.stderr, "Can't read {}: {}.", path, error);
+                return Err(66);
+            }
+        };
+
+        self.run(source, false).map(|_| ()).inspect_err(|_| {
+            let _ = writeln!(
+                self.interpreter.borrow_mut().stderr,
+                "Failed to load {}.",
+                path
+            );
+        })
+    }
+
+    // Runs the script at `path`, see `run_script`
+    pub fn run_file(&mut self, path: String, args: Vec<String>) -> Result<i32> {
+        let program: String = self.read_source(&path)?;
+        self.run_script(&path, program, args)
+    }
+
+    // `rustlox -e code`, run like a script whose path is `-e`
+    pub fn run_code(&mut self, code: String, args: Vec<String>) -> Result<i32> {
+        self.source_name = "-e".to_owned();
+        self.run_script("-e", code, args)
+    }
+
+    // Compiles and runs `source` in this interpreter, on top of what ran
+    // before. Returns the value of a top-level `return` (nil without one),
+    // or the diagnostics that stopped it. Nothing is printed, and warnings
+    // are dropped unless there's an error too (`compile` returns them).
+    pub fn run_source(&mut self, source: &str) -> Result<Object, Vec<Diagnostic>> {
+        self.source_name = "<source>".to_owned();
+        let program: Program = self.compile(source.to_owned(), true)?;
+        let ret: Option<Object> = self.execute(program).map_err(|error| vec![error])?;
+        Ok(ret.unwrap_or(Object::None))
+    }
+
+    // Exposes the script's own path and its command-line arguments
+    fn define_script_globals(&mut self, path: &str, args: Vec<String>) {
+        let globals = self.interpreter.borrow().globals.clone();
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("scriptPath"), Object::String(path.into()));
+        globals.borrow_mut().define(
+            Symbol::intern("args"),
+            native::new_list(
+                args.into_iter()
+                    .map(|arg| Object::String(arg.into()))
+                    .collect(),
+            ),
+        );
+    }
+
+    // Returns the script's exit code: 0 once it ran to the end, that of its
+    // error, or the number it returned at the top level
+    fn run_script(&mut self, path: &str, program: String, args: Vec<String>) -> Result<i32> {
+        self.define_script_globals(path, args);
+
+        let ret: Result<Option<Object>, i32> = self.run(program, true);
+        // Whatever the script got to, even if it failed
+        self.save_state();
+        self.print_reports();
+        self.report_coverage(path)?;
+
+        let ret: Option<Object> = match ret {
+            Ok(ret) => ret,
+            Err(code) => return Ok(code),
+        };
+
+        // A top-level `return` sets the exit code of the script
+        match ret {
+            // What a process can exit with everywhere
+            Some(Object::Number(code)) if code.fract() == 0.0 && (0.0..=255.0).contains(&code) => {
+                Ok(code as i32)
+            }
+            Some(Object::Number(code)) => {
+                let mut interpreter = self.interpreter.borrow_mut();
+                let _ = writeln!(
+                    interpreter.stderr,
+                    "Exit code must be an integer from 0 to 255, got {}.",
+                    util::format_number(code)
+                );
+                Ok(70)
+            }
+            Some(Object::None) | None => Ok(0),
+            Some(_) => {
+                let mut interpreter = self.interpreter.borrow_mut();
+                let _ = writeln!(interpreter.stderr, "Exit code must be a number.");
+                Ok(70)
+            }
+        }
+    }
+
+    // `rustlox watch`: runs the script at `path`, then again every time it
+    // changes, in the same interpreter. Errors don't stop the watch, the
+    // fixed script runs next.
+    pub fn watch(&mut self, path: &str, args: Vec<String>) -> Result<()> {
+        self.define_script_globals(path, args);
+        self.interpreter.borrow_mut().hot_reload = true;
+
+        // The globals the script declared with `var` so far
+        let mut declared: HashSet<Symbol> = HashSet::new();
+        let mut modified: Option<SystemTime> = None;
+
+        loop {
+            let time: Option<SystemTime> = fs::metadata(path).and_then(|m| m.modified()).ok();
+            if time.is_some() && time != modified {
+                if modified.is_some() {
+                    let mut interpreter = self.interpreter.borrow_mut();
+                    let _ = writeln!(interpreter.stderr, "[{} changed, reloading]", path);
+                }
+                modified = time;
+                if let Ok(source) = self.read_source(path) {
+                    self.reload(source, &mut declared);
+                }
+            }
+
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+
+    // Runs the script again, keeping the state of the last run: a global
+    // already declared keeps its value (its `var` is skipped), functions
+    // are redefined and classes updated in place (see
+    // `Interpreter::declare_class`). Values holding on to an old function,
+    // e.g. a callback stored in a list, still call the old one.
+    fn reload(&mut self, source: String, declared: &mut HashSet<Symbol>) {
+        let mut program: Program = match self.compile(source, true) {
+            Ok(program) => program,
+            Err(diagnostics) => return self.report(&diagnostics),
+        };
+        self.report(&program.warnings);
+
+        program.statements.retain(|stmt| match stmt {
+            Some(Stmt::Var { name, .. }) => declared.insert(name.symbol),
+            _ => true,
+        });
+
+        if let Err(diagnostic) = self.execute(program) {
+            self.report(&[diagnostic]);
+        }
+    }
+
+    // Returns the exit code: 130 when Ctrl-C was pressed twice to leave
+    // code that didn't stop, 0 otherwise
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_prompt(&mut self) -> Result<i32> {
+        self.source_name = "<repl>".to_owned();
+        // A pasted block is inserted into the line whole, newlines included,
+        // so it runs as one input rather than line by line (an `else` on its
+        // own line would otherwise be cut off from its `if`)
+        let config: Config = Config::builder().bracketed_paste(true).build();
+        let mut rl: Editor<repl::Helper, DefaultHistory> = Editor::with_config(config)?;
+        let color: bool = !no_color() && io::stdout().is_terminal();
+        rl.set_helper(Some(repl::Helper::new(color)));
+
+        // While reading a line, rustyline owns the terminal and handles Ctrl-C
+        // itself. While code runs, Ctrl-C sets the interrupt flag instead of
+        // killing the REPL. Pressing it again before anything picked the flag
+        // up leaves the REPL once the code returns.
+        self.interpreter.borrow_mut().repl = true;
+        install_ctrl_c_handler()?;
+        *REPL_INTERRUPT.lock().unwrap() = Some(self.interrupt_handle());
+        REPL_ABANDONED.store(false, Ordering::SeqCst);
+        let mut res: Result<i32> = Ok(0);
+
+        // The lines of an input that isn't complete yet, see
+        // `Scanner::is_incomplete`
+        let mut input = String::new();
 
         loop {
-            match rl.readline("\n>> ") {
-                Ok(line) => self.run(line),
+            let prompt: &str = if input.is_empty() { "\n>> " } else { ".. " };
+            match rl.readline(prompt) {
+                Ok(line) => {
+                    // An empty line runs an incomplete input anyway, to get
+                    // out of a stray `(`
+                    let empty: bool = line.trim().is_empty();
+                    input.push_str(&line);
+                    input.push('\n');
+                    if Scanner::is_incomplete(&input) && !empty {
+                        continue;
+                    }
+
+                    let _ = self.run(mem::take(&mut input), false);
+                    if REPL_ABANDONED.swap(false, Ordering::SeqCst) {
+                        res = Ok(130);
+                        break;
+                    }
+                }
+                // Ctrl-C in the middle of an input drops it
+                Err(ReadlineError::Interrupted) if !input.is_empty() => input.clear(),
                 Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
-                    println!("Kill signal received. Exiting...");
+                    let mut interpreter = self.interpreter.borrow_mut();
+                    let _ = writeln!(interpreter.stdout, "Kill signal received. Exiting...");
+                    break;
+                }
+                Err(err) => {
+                    res = Err(anyhow!("Error: {err:?}"));
                     break;
                 }
-                Err(err) => return Err(anyhow!("Error: {err:?}")),
             };
 
-            unsafe {
-                HAD_ERROR = false;
-                HAD_RUNTIME_ERROR = false;
-            }
+            self.interpreter
+                .borrow()
+                .interrupt
+                .store(false, Ordering::SeqCst);
         }
 
+        *REPL_INTERRUPT.lock().unwrap() = None;
+        let code: i32 = res?;
+        self.save_state();
+        self.print_reports();
+        Ok(code)
+    }
+
+    // Prints the syntax tree of the script at `path` as JSON, see
+    // `ast::to_json`. This and the other printers below return whether the
+    // script compiled, its diagnostics are printed otherwise.
+    pub fn emit_ast_json(&mut self, path: String) -> Result<bool> {
+        self.print_ast(path, ast::to_json)
+    }
+
+    // Prints the syntax tree of the script at `path`, see `ast::print_program`
+    pub fn emit_ast(&mut self, path: String) -> Result<bool> {
+        self.print_ast(path, ast::print_program)
+    }
+
+    // Prints the script at `path` back as Lox, see `pretty::print_program`
+    pub fn emit_source(&mut self, path: String) -> Result<bool> {
+        self.print_ast(path, pretty::print_program)
+    }
+
+    // Prints the documentation of the script at `path`, see `doc::to_markdown`
+    pub fn doc(&mut self, path: &str) -> Result<bool> {
+        let title: String = file_name(path);
+        self.print_ast(path.to_owned(), |statements| {
+            doc::to_markdown(&title, statements)
+        })
+    }
+
+    // Prints the script at `path` as a colored HTML page, see
+    // `highlight::to_html`. It doesn't have to compile.
+    pub fn highlight(&mut self, path: &str) -> Result<()> {
+        let source: String = self.read_source(path)?;
+        println!("{}", highlight::to_html(&file_name(path), &source));
         Ok(())
     }
 
-    pub fn run(&mut self, source: String) {
-        let mut scanner: Scanner = Scanner::new(source);
-        let tokens: Vec<Token> = scanner.scan_tokens().unwrap().clone();
+    fn print_ast(
+        &mut self,
+        path: String,
+        print: impl Fn(&[Option<Stmt>]) -> String,
+    ) -> Result<bool> {
+        let source: String = self.read_source(&path)?;
+
+        match self.parse(source) {
+            Ok(statements) => println!("{}", print(&statements)),
+            Err(diagnostics) => {
+                self.report(&diagnostics);
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Prints the script at `path` on a single line, see `minify::minify`
+    pub fn minify(&mut self, path: &str, rename: bool) -> Result<bool> {
+        let source: String = self.read_source(path)?;
+
+        match self.compile(source.clone(), true) {
+            Ok(program) => self.report(&program.warnings),
+            Err(diagnostics) => {
+                self.report(&diagnostics);
+                return Ok(false);
+            }
+        }
+
+        println!("{}", minify::minify(&source, rename));
+        Ok(true)
+    }
+
+    // Prints the script at `path` as JavaScript, see `js::to_js`. The prelude
+    // is transpiled along with it if `prelude` is set.
+    pub fn emit_js(&mut self, path: &str, prelude: bool) -> Result<bool> {
+        let source: String = self.read_source(path)?;
+        // Defined before the prelude runs, so only the natives
+        let natives: Vec<Symbol> = self
+            .interpreter
+            .borrow()
+            .globals
+            .borrow()
+            .bindings()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        let mut statements: Vec<Option<Stmt>> = match prelude {
+            true => self.compile(PRELUDE.to_owned(), false).unwrap().statements,
+            false => vec![],
+        };
+        match self.compile(source, true) {
+            Ok(program) => {
+                self.report(&program.warnings);
+                statements.extend(program.statements);
+            }
+            Err(diagnostics) => {
+                self.report(&diagnostics);
+                return Ok(false);
+            }
+        }
+
+        println!("{}", js::to_js(path, &natives, &statements));
+        Ok(true)
+    }
+
+    // Scans, parses and resolves the script at `path` without running it,
+    // printing every diagnostic. Returns whether it had no errors.
+    pub fn check(&mut self, path: &str) -> Result<bool> {
+        let source: String = self.read_source(path)?;
+
+        match self.compile(source, true) {
+            Ok(program) => {
+                self.report(&program.warnings);
+                Ok(true)
+            }
+            Err(diagnostics) => {
+                self.report(&diagnostics);
+                Ok(false)
+            }
+        }
+    }
+
+    // Compiles the script at `path` and lints it, printing what `config`
+    // doesn't allow. Returns whether there was no error or denied lint.
+    pub fn lint(&mut self, path: &str, config: &lint::Config) -> Result<bool> {
+        let source: String = self.read_source(path)?;
+
+        // The optimizer would fold away the conditions to lint, and the
+        // warnings are what's linted
+        self.optimize = false;
+        self.hide_warnings = false;
+        let diagnostics: Vec<Diagnostic> = match self.compile(source, true) {
+            Ok(program) => lint::lint(&program.statements, program.warnings, config),
+            Err(diagnostics) => diagnostics,
+        };
+
+        self.report(&diagnostics);
+        Ok(!diagnostics.iter().any(Diagnostic::is_error))
+    }
 
-        let mut parser: Parser = Parser::new(tokens);
-        let statements: Vec<Option<Stmt>> = parser.parse();
+    // Scans and parses `source`, failing with every error found
+    pub fn parse(&mut self, source: String) -> Result<Vec<Option<Stmt>>, Vec<Diagnostic>> {
+        let base: usize = self.next_offset;
+        // +1 so the next source's first token doesn't share this one's EOF offset
+        self.next_offset += source.len() + 1;
+        self.sources.add(base, &self.source_name, source.clone());
 
-        unsafe {
-            if HAD_ERROR {
-                return;
+        let scanner = Scanner::new(source, base);
+        let (statements, diagnostics): (Vec<Option<Stmt>>, Vec<Diagnostic>) =
+            match self.timings.as_mut() {
+                // The parser pulls tokens as it goes, scan them all up front
+                // to time the two apart
+                Some(timings) => {
+                    let start = Instant::now();
+                    let tokens: Vec<Result<Token, ScanError>> = scanner.collect();
+                    timings.scan += start.elapsed();
+
+                    let start = Instant::now();
+                    let mut parser = Parser::new(tokens.into_iter());
+                    let statements: Vec<Option<Stmt>> = parser.parse();
+                    timings.parse += start.elapsed();
+                    (statements, parser.diagnostics)
+                }
+                None => {
+                    let mut parser: Parser = Parser::new(scanner);
+                    (parser.parse(), parser.diagnostics)
+                }
+            };
+
+        match diagnostics.is_empty() {
+            true => Ok(statements),
+            false => Err(diagnostics),
+        }
+    }
+
+    // Scans, parses and resolves `source`. Fails with every diagnostic found
+    // (warnings included) if there was any error. `is_script` allows a
+    // top-level `return`.
+    pub fn compile(&mut self, source: String, is_script: bool) -> Result<Program, Vec<Diagnostic>> {
+        let mut statements: Vec<Option<Stmt>> = self.parse(source)?;
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+
+        if self.optimize {
+            let start = Instant::now();
+            let mut optimizer = Optimizer::default();
+            statements = optimizer.optimize(statements);
+            diagnostics.append(&mut optimizer.diagnostics);
+            if let Some(timings) = self.timings.as_mut() {
+                timings.optimize += start.elapsed();
             }
         }
 
+        let start = Instant::now();
+
         // Resolver does a static analysis. If it doesn't throw an error, then
         // the syntax is clean and the interpreter can run confidently.
-        let mut resolver = Resolver::new(self.interpreter.clone());
+        let mut resolver = Resolver::new(is_script);
+        if self.strict {
+            let globals = self.interpreter.borrow().globals.clone();
+            let mut names: Vec<Symbol> = globals
+                .borrow()
+                .bindings()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            // Defined by `run_script`, which `check` doesn't go through
+            if is_script {
+                names.extend([Symbol::intern("scriptPath"), Symbol::intern("args")]);
+            }
+            resolver.strict(names);
+        }
         // Vec<Option<Stmt>> -> Vec<Option<Box<Stmt>>>
         resolver.resolve_stmt_list(
             &statements
                 .iter()
-                .map(|x| match x {
-                    Some(stmt) => Some(Box::new(stmt.clone())),
-                    None => None,
-                })
-                .collect(),
+                .map(|x| x.as_ref().map(|stmt| Box::new(stmt.clone())))
+                .collect::<Vec<Option<Box<Stmt>>>>(),
         );
+        diagnostics.append(&mut resolver.diagnostics);
+        self.interpreter.borrow_mut().locals.extend(resolver.locals);
+        if let Some(timings) = self.timings.as_mut() {
+            timings.resolve += start.elapsed();
+        }
+        if self.hide_warnings {
+            diagnostics.retain(Diagnostic::is_error);
+        }
 
-        unsafe {
-            if HAD_ERROR {
-                return;
-            }
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(diagnostics);
         }
 
-        self.interpreter.borrow_mut().interpret(statements);
+        Ok(Program {
+            statements,
+            warnings: diagnostics,
+        })
     }
 
-    pub fn error(line: usize, message: &str) {
-        Lox::report(line, "", message);
-    }
+    // Returns the value of a top-level `return`, which ends the program early
+    pub fn execute(&mut self, program: Program) -> Result<Option<Object>, Diagnostic> {
+        let mut interpreter = self.interpreter.borrow_mut();
+        if let Some(coverage) = interpreter.coverage.as_mut() {
+            coverage.add(&program.statements);
+        }
+
+        // Tracing, coverage and the debugger need the tree-walker
+        let walk_tree: bool =
+            interpreter.trace || interpreter.coverage.is_some() || interpreter.debugger.is_some();
+        let start = Instant::now();
+        let res = if self.closures && !walk_tree {
+            let statements = compiler::compile(&program.statements, &interpreter.locals);
+            interpreter.interpret_compiled(&statements)
+        } else {
+            interpreter.interpret(program.statements)
+        };
 
-    pub fn parse_error(token: &Token, message: &str) {
-        match token.token_type {
-            TokenType::Eof => Lox::report(token.line, "at end", message),
-            _ => Lox::report(token.line, &format!("at '{}'", token.lexeme), message),
+        if let Some(timings) = self.timings.as_mut() {
+            timings.interpret += start.elapsed();
+            timings.peak_statements = timings.peak_statements.max(interpreter.budget.statements());
         }
+        res.map_err(Diagnostic::runtime)
     }
 
-    pub fn runtime_error(error: LoxError) {
-        match error {
-            LoxError::RuntimeError { message, token } => {
-                match token {
-                    Some(token) => println!("{}\n[line {}]", message, token.line),
-                    None => println!("{}", message),
-                }
-                unsafe {
-                    HAD_RUNTIME_ERROR = true;
-                }
+    // Compiles and executes `source`, printing any diagnostic. Fails with the
+    // exit code for the error, if any.
+    fn run(&mut self, source: String, is_script: bool) -> Result<Option<Object>, i32> {
+        let program: Program = self.compile(source, is_script).map_err(|diagnostics| {
+            self.report(&diagnostics);
+            65
+        })?;
+        self.report(&program.warnings);
+
+        self.execute(program).map_err(|diagnostic| {
+            self.report(&[diagnostic]);
+            70
+        })
+    }
+
+    // A diagnostic as `report` prints it when the output isn't a terminal
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        diagnostic.render(&self.sources, false)
+    }
+
+    // Errors go to stdout, warnings to stderr so they don't mix with the
+    // output. Colored when printed to a terminal, unless `NO_COLOR` is set.
+    // As JSON, everything goes to stderr.
+    pub fn report(&self, diagnostics: &[Diagnostic]) {
+        let no_color: bool = no_color() || self.output_captured;
+        let mut interpreter = self.interpreter.borrow_mut();
+
+        for diagnostic in diagnostics {
+            if self.json_diagnostics {
+                let _ = writeln!(interpreter.stderr, "{}", diagnostic.to_json(&self.sources));
+                continue;
             }
-            _ => unreachable!(),
+
+            let stderr: bool = !diagnostic.is_error();
+            let color: bool = !no_color
+                && match stderr {
+                    true => io::stderr().is_terminal(),
+                    false => io::stdout().is_terminal(),
+                };
+            let text: String = diagnostic.render(&self.sources, color);
+            let _ = writeln!(interpreter.output(stderr), "{}", text);
         }
     }
 
-    pub fn report(line: usize, loc: &str, message: &str) {
-        println!("[Line {line}] Error {loc}: {message}");
+    // The contents of the script at `path`, named after it in `sources`
+    fn read_source(&mut self, path: &str) -> io::Result<String> {
+        self.source_name = match path {
+            STDIN => "<stdin>".to_owned(),
+            _ => path.to_owned(),
+        };
+        read_source(path)
+    }
+}
 
-        unsafe {
-            HAD_ERROR = true;
-        }
+// The file `run_rc` runs: the one `LOXRC` names, `~/.loxrc` otherwise
+fn rc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(RC_VAR) {
+        return Some(PathBuf::from(path));
     }
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(Path::new(&home).join(RC_FILE))
+}
+
+// The last part of `path`, to title its documentation or HTML page
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(path.to_owned(), |name| name.to_string_lossy().into_owned())
+}
+
+// See `CTRL_C_HANDLER`. With no REPL running, Ctrl-C ends the process with
+// the status it would have without a handler.
+#[cfg(not(target_arch = "wasm32"))]
+fn install_ctrl_c_handler() -> Result<()> {
+    let installed = CTRL_C_HANDLER.get_or_init(|| {
+        ctrlc::set_handler(|| match REPL_INTERRUPT.lock().unwrap().as_ref() {
+            Some(interrupt) => {
+                if interrupt.interrupt() {
+                    REPL_ABANDONED.store(true, Ordering::SeqCst);
+                }
+            }
+            None => process::exit(130),
+        })
+        .map_err(|err| err.to_string())
+    });
+    installed.clone().map_err(|err| anyhow!(err))
+}
+
+// Whether `NO_COLOR` asks for output without colors
+fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+// The contents of the script at `path`, or all of stdin if `path` is `-`
+fn read_source(path: &str) -> io::Result<String> {
+    match path {
+        STDIN => io::read_to_string(io::stdin()),
+        _ => fs::read_to_string(path),
+    }
+}
+
+// The output of `Lox::compile`, ready to be executed
+pub struct Program {
+    statements: Vec<Option<Stmt>>,
+    pub warnings: Vec<Diagnostic>,
 }
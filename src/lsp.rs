@@ -0,0 +1,371 @@
+// `rustlox lsp`: a Language Server Protocol server over stdin and stdout,
+// so editors can show diagnostics, jump to definitions and list the symbols
+// of a Lox file.
+//
+// Every open document is scanned, parsed and resolved again whenever it
+// changes, nothing runs. Positions are counted in characters, which matches
+// the editor's UTF-16 units for everything outside the astral planes.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticKind},
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    stmt::Stmt,
+    token::{Span, Token, TokenType},
+};
+
+// See `Document::symbols`, from the protocol's `SymbolKind`
+const CLASS: u32 = 5;
+const METHOD: u32 = 6;
+const FUNCTION: u32 = 12;
+const VARIABLE: u32 = 13;
+
+// The error code of requests the server doesn't implement
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// An open file, compiled as of its last change
+struct Document {
+    // Its text, to turn the offsets of spans into positions
+    source: String,
+    statements: Vec<Option<Stmt>>,
+    diagnostics: Vec<Diagnostic>,
+    // See `Resolver::definitions`
+    definitions: HashMap<usize, Token>,
+    // Every identifier token, in source order, to find the one under the
+    // cursor
+    identifiers: Vec<Token>,
+}
+
+impl Document {
+    fn new(source: &str) -> Self {
+        let mut parser: Parser = Parser::new(Scanner::new(source.to_owned(), 0));
+        let statements: Vec<Option<Stmt>> = parser.parse();
+        let mut diagnostics: Vec<Diagnostic> = parser.diagnostics;
+
+        // What parsed is still resolved for its definitions, but resolving a
+        // program that didn't parse reports errors caused by the ones already
+        // there
+        let mut resolver = Resolver::new(true);
+        resolver.resolve_stmt_list(
+            &statements
+                .iter()
+                .map(|x| x.as_ref().map(|stmt| Box::new(stmt.clone())))
+                .collect::<Vec<Option<Box<Stmt>>>>(),
+        );
+        if diagnostics.is_empty() {
+            diagnostics.append(&mut resolver.diagnostics);
+        }
+        let definitions: HashMap<usize, Token> = resolver.definitions;
+
+        let (tokens, _) = Scanner::new(source.to_owned(), 0).scan_tokens();
+        let identifiers: Vec<Token> = tokens
+            .into_iter()
+            .filter(|token| token.token_type == TokenType::Identifier)
+            .collect();
+
+        Document {
+            source: source.to_owned(),
+            statements,
+            diagnostics,
+            definitions,
+            identifiers,
+        }
+    }
+
+    // The identifier at `line` and `column`, both starting at 1
+    fn identifier_at(&self, line: usize, column: usize) -> Option<&Token> {
+        self.identifiers.iter().find(|token| {
+            token.line == line
+                && token.column <= column
+                && column <= token.column + token.lexeme.chars().count()
+        })
+    }
+
+    // Where the variable `name` refers to is declared: a local as found by
+    // the resolver, or else the last top-level declaration of that name
+    fn definition(&self, name: &Token) -> Option<&Token> {
+        if let Some(token) = self.definitions.get(&name.offset) {
+            return Some(token);
+        }
+
+        self.statements
+            .iter()
+            .rev()
+            .flatten()
+            .filter_map(|stmt| match stmt {
+                Stmt::Var { name, .. } | Stmt::Function { name, .. } | Stmt::Class { name, .. } => {
+                    Some(name)
+                }
+                _ => None,
+            })
+            .find(|declared| declared.symbol == name.symbol)
+    }
+
+    // The top-level classes (with their methods), functions and variables
+    fn symbols(&self) -> Vec<Value> {
+        self.statements
+            .iter()
+            .flatten()
+            .filter_map(|stmt| {
+                let symbol = |name: &Token, kind: u32, children: Vec<Value>| {
+                    self.symbol(name, kind, stmt.span(), children)
+                };
+                match stmt {
+                    Stmt::Class { name, methods, .. } => {
+                        let methods: Vec<Value> = methods
+                            .iter()
+                            .filter_map(|method| {
+                                let name: &Token = method.token()?;
+                                Some(self.symbol(name, METHOD, method.span(), vec![]))
+                            })
+                            .collect();
+                        Some(symbol(name, CLASS, methods))
+                    }
+                    Stmt::Function { name, .. } => Some(symbol(name, FUNCTION, vec![])),
+                    Stmt::Var { name, .. } => Some(symbol(name, VARIABLE, vec![])),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    // A `DocumentSymbol`: the whole declaration, `span`, selecting its name
+    fn symbol(&self, name: &Token, kind: u32, span: Span, children: Vec<Value>) -> Value {
+        json!({
+            "name": name.lexeme,
+            "kind": kind,
+            "range": self.range(span.range()),
+            "selectionRange": range(name.line, name.column, name.lexeme.chars().count()),
+            "children": children,
+        })
+    }
+
+    // The `Range` of the offsets `span`, which may cover several lines
+    fn range(&self, span: Range<usize>) -> Value {
+        json!({
+            "start": self.position(span.start),
+            "end": self.position(span.end),
+        })
+    }
+
+    // The `Position` of `offset`, counting lines and characters from 0
+    fn position(&self, offset: usize) -> Value {
+        let before: &str = self.source.get(..offset).unwrap_or(&self.source);
+        let line_start: usize = before.rfind('\n').map_or(0, |i| i + 1);
+        json!({
+            "line": before.matches('\n').count(),
+            "character": before[line_start..].chars().count(),
+        })
+    }
+
+    fn to_lsp(&self, diagnostic: &Diagnostic) -> Value {
+        // Error, Warning
+        let severity: u32 = match diagnostic.kind {
+            DiagnosticKind::Warning => 2,
+            _ => 1,
+        };
+        let range: Value = match &diagnostic.span {
+            Some(span) => self.range(span.clone()),
+            None => range(
+                diagnostic.line.unwrap_or(1),
+                diagnostic.column.unwrap_or(1),
+                1,
+            ),
+        };
+
+        json!({
+            "range": range,
+            "severity": severity,
+            "source": "rustlox",
+            "message": diagnostic.message,
+        })
+    }
+}
+
+// A `Range` on `line` from `column` (both starting at 1), `len` characters
+// long. The protocol counts from 0.
+fn range(line: usize, column: usize, len: usize) -> Value {
+    let line: usize = line.saturating_sub(1);
+    let column: usize = column.saturating_sub(1);
+    json!({
+        "start": { "line": line, "character": column },
+        "end": { "line": line, "character": column + len },
+    })
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    // The result of a request, `Ok(None)` for notifications
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Option<Value>, i64> {
+        let uri: &str = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match method {
+            "initialize" => Ok(Some(json!({
+                "capabilities": {
+                    // Full text on every change
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "rustlox" },
+            }))),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Some(Value::Null))
+            }
+            "textDocument/didOpen" => {
+                let text: &str = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.open(uri, text);
+                Ok(None)
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|changes| changes.last()) {
+                    self.open(uri, text["text"].as_str().unwrap_or_default());
+                }
+                Ok(None)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                Ok(None)
+            }
+            "textDocument/definition" => {
+                let line: usize = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+                let column: usize = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+
+                let definition: Option<&Token> = self.documents.get(uri).and_then(|document| {
+                    let name: &Token = document.identifier_at(line + 1, column + 1)?;
+                    document.definition(name)
+                });
+                Ok(Some(match definition {
+                    Some(token) => json!({
+                        "uri": uri,
+                        "range": range(token.line, token.column, token.lexeme.chars().count()),
+                    }),
+                    None => Value::Null,
+                }))
+            }
+            "textDocument/documentSymbol" => Ok(Some(match self.documents.get(uri) {
+                Some(document) => Value::Array(document.symbols()),
+                None => Value::Null,
+            })),
+            // Only answered if it's a request, notifications like
+            // `initialized` are ignored
+            _ => Err(METHOD_NOT_FOUND),
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        self.documents.insert(uri.to_owned(), Document::new(text));
+    }
+
+    // The diagnostics of the document at `uri`, sent after every change
+    fn publish(&self, uri: &str) -> Value {
+        let diagnostics: Vec<Value> = self
+            .documents
+            .get(uri)
+            .map(|document| {
+                document
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| document.to_lsp(diagnostic))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+}
+
+// Answers messages from stdin until the client says `exit` (or the input
+// ends). Returns the exit code, 1 if it didn't ask to `shutdown` first.
+pub fn serve() -> Result<i32> {
+    let mut server = Server::default();
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+
+    while let Some(message) = read_message(&mut input)? {
+        let method: &str = message["method"].as_str().unwrap_or_default();
+        let params: &Value = &message["params"];
+
+        if method == "exit" {
+            // Exiting without a `shutdown` first is an error, see the spec
+            return Ok(if server.shutdown { 0 } else { 1 });
+        }
+
+        let result = server.handle(method, params);
+        // Responses to client requests carry their id, notifications don't
+        if let Some(id) = message.get("id") {
+            let response: Value = match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(code) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": format!("Unknown method '{}'.", method) },
+                }),
+            };
+            write_message(&mut output, &response)?;
+        }
+
+        if method.starts_with("textDocument/did") && method != "textDocument/didClose" {
+            let uri: &str = params["textDocument"]["uri"].as_str().unwrap_or_default();
+            write_message(&mut output, &server.publish(uri))?;
+        }
+    }
+
+    Ok(0)
+}
+
+// A message is a `Content-Length` header, a blank line and that many bytes
+// of JSON. `None` once the input ends.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length: Option<usize> = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line: &str = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse()?);
+            }
+        }
+    }
+
+    let length: usize = length.ok_or_else(|| anyhow!("Expect a Content-Length header."))?;
+    let mut body: Vec<u8> = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<()> {
+    let body: String = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
@@ -1,37 +1,171 @@
 use anyhow::Result;
-use lox::Lox;
-
-use std::{cmp::Ordering, env, process};
-
-pub mod ast;
-pub mod callable;
-pub mod class;
-pub mod environment;
-pub mod error;
-pub mod expr;
-pub mod interpreter;
-pub mod lox;
-pub mod object;
-pub mod parser;
-pub mod resolver;
-pub mod scanner;
-pub mod stmt;
-pub mod token;
-pub mod util;
+use cli::{AstFormat, Command, Options, USAGE};
+use rustlox::{
+    capabilities::Capabilities,
+    lox::{Lox, STDIN},
+    lsp,
+};
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+    process, thread,
+};
+
+mod cli;
+
+// Lox calls grow the stack as they need (see `callable::grow_stack`), but
+// parsing, resolving and printing deeply nested code and values still
+// recurse on the main thread's
+const STACK_SIZE: usize = 1 << 30;
 
 fn main() -> Result<()> {
-    let mut lox: Lox = Lox::new();
-    let args: Vec<String> = env::args().collect();
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)?
+        .join()
+        .unwrap_or_else(|_| process::exit(70))
+}
 
-    // The first element of `args` is always the exec. path
-    match args.len().cmp(&2) {
-        Ordering::Greater => {
-            println!("Usage: `rustlox [script]`");
+fn run() -> Result<()> {
+    // The first element of `args` is always the exec. path.
+    let args: Vec<String> = env::args().collect();
+    let options: Options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
             process::exit(64);
         }
-        Ordering::Equal => lox.run_file(args[1].clone())?,
-        _ => lox.run_prompt()?,
     };
 
-    Ok(())
+    let mut lox: Lox = Lox::new();
+    lox.optimize = options.optimize;
+    lox.closures = options.closures;
+    lox.hide_warnings = options.hide_warnings;
+    lox.strict = options.strict;
+    lox.json_diagnostics = options.json_diagnostics;
+    if let Some(depth) = options.max_call_depth {
+        lox.set_max_call_depth(depth);
+    }
+    if options.sandbox {
+        lox.set_capabilities(Capabilities::none());
+    }
+    // Before anything compiles, so `--strict` knows their functions
+    for path in &options.plugins {
+        if let Err(message) = lox.load_plugin(path) {
+            eprintln!("{}", message);
+            process::exit(66);
+        }
+    }
+
+    // The commands that only compile, whether the script did
+    let compiled: Option<bool> = match &options.command {
+        // Only parses the script, nothing runs
+        Command::Ast {
+            path,
+            format: AstFormat::Tree,
+        } => Some(lox.emit_ast(path.clone())?),
+        Command::Ast {
+            path,
+            format: AstFormat::Json,
+        } => Some(lox.emit_ast_json(path.clone())?),
+        Command::Ast {
+            path,
+            format: AstFormat::Source,
+        } => Some(lox.emit_source(path.clone())?),
+        // Only compiles the script, reporting its errors and warnings. In
+        // strict mode the prelude's functions are known globals too.
+        Command::Check { path } => {
+            if options.strict && options.prelude {
+                lox.run_prelude();
+            }
+            Some(lox.check(path)?)
+        }
+        // Only compiles the script, along with the prelude unless it's off
+        Command::EmitJs { path } => Some(lox.emit_js(path, options.prelude)?),
+        Command::Minify { path, rename } => Some(lox.minify(path, *rename)?),
+        Command::Doc { path } => Some(lox.doc(path)?),
+        Command::Highlight { path } => return lox.highlight(path),
+        // Only compiles the documents an editor sends
+        Command::Lsp => process::exit(lsp::serve()?),
+        Command::Lint { path, config } => {
+            if options.strict && options.prelude {
+                lox.run_prelude();
+            }
+            Some(lox.lint(path, config)?)
+        }
+        _ => None,
+    };
+    match compiled {
+        Some(true) => return Ok(()),
+        Some(false) => process::exit(65),
+        None => (),
+    }
+
+    if options.prelude {
+        lox.run_prelude();
+    }
+    // The REPL always runs it, a script (or piped input) only if asked to
+    let repl: bool = matches!(options.command, Command::Prompt) && io::stdin().is_terminal();
+    if repl || options.rc {
+        if let Err(code) = lox.run_rc() {
+            if !repl {
+                process::exit(code);
+            }
+        }
+    }
+    // After the rc file, whose classes the saved instances may need
+    if let Some(path) = &options.load_state {
+        if let Err(message) = lox.load_state(path) {
+            eprintln!("{}", message);
+            process::exit(66);
+        }
+    }
+    lox.save_state = options.save_state;
+
+    // Only the user's code counts against the budget
+    lox.set_statement_limit(options.max_statements);
+    lox.set_time_limit(options.time_limit);
+    lox.set_memory_limit(options.max_memory);
+    if options.profile {
+        lox.start_profiling();
+    }
+    if options.coverage {
+        lox.start_coverage();
+    }
+    if options.trace {
+        lox.start_tracing();
+    }
+    if options.time {
+        lox.start_timing();
+    }
+
+    let code: i32 = match options.command {
+        // Piped in, e.g. `echo 'print 1;' | rustlox`, the input is the script
+        Command::Prompt if !io::stdin().is_terminal() => lox.run_file(STDIN.to_owned(), vec![])?,
+        Command::Prompt => lox.run_prompt()?,
+        Command::Script { path, args } => lox.run_file(path, args)?,
+        Command::Eval { code, args } => lox.run_code(code, args)?,
+        Command::Watch { path, args } => return lox.watch(&path, args),
+        // Runs the script under the debugger, see `Debugger`
+        Command::Debug { path, args } => {
+            lox.start_debugging(&path)?;
+            lox.run_file(path, args)?
+        }
+        Command::Ast { .. }
+        | Command::Check { .. }
+        | Command::Lint { .. }
+        | Command::EmitJs { .. }
+        | Command::Minify { .. }
+        | Command::Doc { .. }
+        | Command::Highlight { .. }
+        | Command::Lsp => {
+            unreachable!()
+        }
+    };
+    match code {
+        0 => Ok(()),
+        code => process::exit(code),
+    }
 }
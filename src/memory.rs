@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use crate::{
+    callable::LoxCallable,
+    environment::Environment,
+    object::Object,
+    symbol::Symbol,
+    sync::{Rc, RefCell},
+    token::Literal,
+};
+
+// Approximates the memory a program uses, for `--max-memory`. Each
+// interpreter keeps its own count, see `Interpreter::charge`: it adds up the
+// size of the values it makes (strings, lists, maps, instances, environments)
+// and, once that gets close to the limit, measures what's actually still
+// reachable from its environments with `measure`.
+//
+// Sizes are estimates from the values' capacities, not what the allocator
+// hands out. Values shared by several ones count once, and neither the parsed
+// program nor what the host or natives allocate for themselves counts.
+
+// An `Rc`'s counts and a `RefCell`'s flag
+const HEADER: usize = 3 * size_of::<usize>();
+
+// What making `value` allocated, not counting the values it holds
+pub fn size_of_value(value: &Object) -> usize {
+    match value {
+        Object::String(string) => HEADER + string.len(),
+        Object::Bytes(bytes) => HEADER + size_of::<Vec<u8>>() + bytes.capacity(),
+        Object::List(list) => HEADER + list.borrow().capacity() * size_of::<Object>(),
+        Object::Map(map) => {
+            HEADER + map.borrow().capacity() * (size_of::<Literal>() + size_of::<Object>())
+        }
+        Object::Instance(instance) => {
+            HEADER + instance.borrow().fields().capacity() * size_of::<(Symbol, Object)>()
+        }
+        Object::Class(class) => {
+            HEADER + class.borrow().methods.capacity() * size_of::<(Symbol, LoxCallable)>()
+        }
+        // Numbers, booleans and nil are held inline, callables share their
+        // declaration with the program
+        _ => 0,
+    }
+}
+
+// What an environment takes, not counting the values it holds
+pub fn size_of_environment(environment: &Environment) -> usize {
+    HEADER + size_of::<Environment>() + environment.capacity() * size_of::<(Symbol, Object)>()
+}
+
+// The bytes taken by everything reachable from `roots`, counting what's
+// reachable several ways once. Walks with explicit stacks rather than
+// recursion, as values can nest deeper than the Rust stack allows.
+pub fn measure<'a>(roots: impl IntoIterator<Item = &'a Rc<RefCell<Environment>>>) -> usize {
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut environments: Vec<Rc<RefCell<Environment>>> = roots.into_iter().cloned().collect();
+    let mut values: Vec<Object> = vec![];
+    let mut total: usize = 0;
+
+    loop {
+        if let Some(value) = values.pop() {
+            if let Some(address) = address(&value) {
+                if !seen.insert(address) {
+                    continue;
+                }
+            }
+            total += size_of_value(&value);
+
+            match &value {
+                Object::List(list) => values.extend(list.borrow().iter().cloned()),
+                Object::Map(map) => values.extend(map.borrow().values().cloned()),
+                Object::Instance(instance) => {
+                    let instance = instance.borrow();
+                    values.extend(instance.fields().values().cloned());
+                    values.push(Object::Class(instance.class()));
+                }
+                Object::Class(class) => {
+                    let class = class.borrow();
+                    values.push(class.superclass.clone());
+                    values.extend(class.methods.values().cloned().map(Object::Callable));
+                }
+                Object::Callable(LoxCallable::User { closure, .. }) => {
+                    environments.push(closure.clone())
+                }
+                Object::Callable(LoxCallable::Method { receiver, .. }) => {
+                    values.push(receiver.as_ref().clone())
+                }
+                _ => {}
+            }
+        } else if let Some(environment) = environments.pop() {
+            if !seen.insert(Rc::as_ptr(&environment) as *const () as usize) {
+                continue;
+            }
+
+            let environment = environment.borrow();
+            total += size_of_environment(&environment);
+            values.extend(environment.objects().cloned());
+            environments.extend(environment.enclosing.clone());
+        } else {
+            return total;
+        }
+    }
+}
+
+// Where a shared value lives, to count it once
+fn address(value: &Object) -> Option<usize> {
+    match value {
+        Object::String(string) => Some(Rc::as_ptr(string) as *const () as usize),
+        Object::Bytes(bytes) => Some(Rc::as_ptr(bytes) as *const () as usize),
+        Object::List(list) => Some(Rc::as_ptr(list) as *const () as usize),
+        Object::Map(map) => Some(Rc::as_ptr(map) as *const () as usize),
+        Object::Instance(instance) => Some(Rc::as_ptr(instance) as *const () as usize),
+        Object::Class(class) => Some(Rc::as_ptr(class) as *const () as usize),
+        _ => None,
+    }
+}
@@ -0,0 +1,113 @@
+// `rustlox minify`: the script on a single line, without comments and with
+// only the spaces that keep two tokens apart. With `--rename`, local
+// variables and parameters also get the shortest names that clash with
+// nothing else in the script. Functions and classes keep their names, which
+// show when they're printed.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    stmt::Stmt,
+    token::{Token, TokenType},
+};
+
+// `source` must compile, see `Lox::minify`
+pub fn minify(source: &str, rename: bool) -> String {
+    let (tokens, _) = Scanner::new(source.to_owned(), 0).scan_tokens();
+    let names: HashMap<usize, String> = match rename {
+        true => short_names(source, &tokens),
+        false => HashMap::new(),
+    };
+
+    let mut res = String::new();
+    for token in tokens
+        .iter()
+        .filter(|token| token.token_type != TokenType::Eof)
+    {
+        let lexeme: &str = names.get(&token.offset).unwrap_or(&token.lexeme);
+        if needs_space(&res, lexeme) {
+            res.push(' ');
+        }
+        res.push_str(lexeme);
+    }
+
+    res
+}
+
+// Whether `next` would run into the end of `res`, e.g. two words, `=` after
+// `<`, or a `/` after `/`, which starts a comment
+fn needs_space(res: &str, next: &str) -> bool {
+    let (Some(last), Some(first)) = (res.chars().last(), next.chars().next()) else {
+        return false;
+    };
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    (word(last) && (word(first) || first == '"'))
+        || (matches!(last, '!' | '=' | '<' | '>') && first == '=')
+        || (last == '/' && matches!(first, '/' | '*'))
+}
+
+// The new name of every local variable's token (its declaration and each
+// use), keyed by offset, as found by the resolver
+fn short_names(source: &str, tokens: &[Token]) -> HashMap<usize, String> {
+    let mut parser: Parser = Parser::new(Scanner::new(source.to_owned(), 0));
+    let statements: Vec<Option<Box<Stmt>>> = parser
+        .parse()
+        .into_iter()
+        .map(|stmt| stmt.map(Box::new))
+        .collect();
+    let mut resolver = Resolver::new(true);
+    resolver.resolve_stmt_list(&statements);
+
+    // Names are only taken from what the script doesn't use already, so a
+    // renamed local never hides (or is hidden by) anything
+    let used: HashSet<&str> = tokens
+        .iter()
+        .filter(|token| token.token_type == TokenType::Identifier)
+        .map(|token| token.lexeme.as_str())
+        .collect();
+    let mut fresh = (0..)
+        .map(short_name)
+        .filter(|name| Scanner::text2token(name) == TokenType::Identifier)
+        .filter(|name| !used.contains(name.as_str()));
+
+    // Functions and classes are the declarations right after `fn` or `class`
+    let named: HashSet<usize> = tokens
+        .windows(2)
+        .filter(|pair| matches!(pair[0].token_type, TokenType::Fn | TokenType::Class))
+        .map(|pair| pair[1].offset)
+        .collect();
+
+    let mut names: HashMap<usize, String> = HashMap::new();
+    for declaration in &resolver.declarations {
+        if !named.contains(&declaration.offset) {
+            names.insert(declaration.offset, fresh.next().unwrap());
+        }
+    }
+    for (offset, declaration) in &resolver.definitions {
+        if let Some(name) = names.get(&declaration.offset).cloned() {
+            names.insert(*offset, name);
+        }
+    }
+
+    names
+}
+
+// `a` to `z`, then `A` to `Z`, then `aa`, `ab`, ...
+fn short_name(mut n: usize) -> String {
+    const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let mut res: Vec<u8> = vec![LETTERS[n % LETTERS.len()]];
+    n /= LETTERS.len();
+    while n > 0 {
+        n -= 1;
+        res.push(LETTERS[n % LETTERS.len()]);
+        n /= LETTERS.len();
+    }
+
+    res.reverse();
+    String::from_utf8(res).unwrap()
+}
@@ -0,0 +1,1429 @@
+use hmac::{Hmac, Mac};
+use indexmap::IndexMap;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    f64::consts,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    process::{Command, ExitStatus, Stdio},
+    sync::{atomic::Ordering, OnceLock},
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{
+    callable::LoxCallable,
+    capabilities::Capability,
+    class::{LoxClass, LoxInstance},
+    convert::{FromLox, ToLox},
+    debugger,
+    error::LoxError,
+    format,
+    interpreter::{self, Interpreter},
+    object::{Freezable, Object},
+    plugin, state,
+    symbol::Symbol,
+    sync::{MaybeSend, Rc, RefCell},
+    time::Instant,
+    token::Literal,
+    util::{self, Fnv},
+};
+
+// A global native function, called with already evaluated arguments. The
+// interpreter gives access to runtime state (e.g. the PRNG) and to calling
+// back into Lox code. Any closure is one, so a host can register natives
+// holding its own state, see `Interpreter::define_native`.
+pub trait NativeFn:
+    Fn(&mut Interpreter, &[Object]) -> Result<Object, LoxError> + MaybeSend + 'static
+{
+}
+
+impl<F> NativeFn for F where
+    F: Fn(&mut Interpreter, &[Object]) -> Result<Object, LoxError> + MaybeSend + 'static
+{
+}
+
+impl fmt::Debug for dyn NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+// The natives below are plain functions
+pub type Builtin = fn(&mut Interpreter, &[Object]) -> Result<Object, LoxError>;
+
+// A built-in method: the interpreter (for calling back into Lox code),
+// the receiver, and the call arguments.
+pub type NativeMethod = fn(&mut Interpreter, &Object, &[Object]) -> Result<Object, LoxError>;
+
+// The names of every method `find_method` knows, for suggestions
+pub const METHOD_NAMES: &[&str] = &[
+    "abs",
+    "ceil",
+    "contains",
+    "decode",
+    "endsWith",
+    "filter",
+    "floor",
+    "has",
+    "insert",
+    "join",
+    "keys",
+    "length",
+    "lower",
+    "map",
+    "merge",
+    "pop",
+    "push",
+    "reduce",
+    "remove",
+    "replace",
+    "round",
+    "size",
+    "slice",
+    "sort",
+    "split",
+    "startsWith",
+    "toFixed",
+    "toList",
+    "trim",
+    "upper",
+    "values",
+];
+
+// Method tables of the primitive types. Returns the method bound to
+// `receiver`, or `None` if the type has no method called `name`.
+pub fn find_method(receiver: &Object, name: &str) -> Option<LoxCallable> {
+    let (arity, body): (usize, NativeMethod) = match (receiver, name) {
+        // String
+        (Object::String(_), "length") => (0, |_, this, _| {
+            Ok(Object::Number(as_string(this).chars().count() as f64))
+        }),
+        (Object::String(_), "upper") => (0, |_, this, _| {
+            Ok(Object::String(as_string(this).to_uppercase().into()))
+        }),
+        (Object::String(_), "lower") => (0, |_, this, _| {
+            Ok(Object::String(as_string(this).to_lowercase().into()))
+        }),
+        (Object::String(_), "trim") => (0, |_, this, _| {
+            Ok(Object::String(as_string(this).trim().into()))
+        }),
+        (Object::String(_), "split") => (1, |_, this, args| {
+            let separator: &str = string_arg(args, 0)?;
+            Ok(new_list(
+                as_string(this)
+                    .split(separator)
+                    .map(|x| Object::String(x.into()))
+                    .collect(),
+            ))
+        }),
+        (Object::String(_), "contains") => (1, |_, this, args| {
+            Ok(Object::Boolean(
+                as_string(this).contains(string_arg(args, 0)?),
+            ))
+        }),
+        (Object::String(_), "startsWith") => (1, |_, this, args| {
+            Ok(Object::Boolean(
+                as_string(this).starts_with(string_arg(args, 0)?),
+            ))
+        }),
+        (Object::String(_), "endsWith") => (1, |_, this, args| {
+            Ok(Object::Boolean(
+                as_string(this).ends_with(string_arg(args, 0)?),
+            ))
+        }),
+        (Object::String(_), "replace") => (2, |_, this, args| {
+            Ok(Object::String(
+                as_string(this)
+                    .replace(string_arg(args, 0)?, string_arg(args, 1)?)
+                    .into(),
+            ))
+        }),
+        // Bytes
+        (Object::Bytes(_), "length") => (0, |_, this, _| {
+            Ok(Object::Number(as_bytes(this).len() as f64))
+        }),
+        // `bytes.slice(start, end)`, `end` is exclusive
+        (Object::Bytes(_), "slice") => (2, |_, this, args| {
+            let bytes: &[u8] = as_bytes(this);
+            let start: usize = index_arg(args, 0, bytes.len() + 1)?;
+            let end: usize = index_arg(args, 1, bytes.len() + 1)?;
+            if start > end {
+                return Err(error(format!(
+                    "Slice start {} is after its end {}.",
+                    start, end
+                )));
+            }
+            Ok(Object::Bytes(Rc::new(bytes[start..end].to_vec())))
+        }),
+        (Object::Bytes(_), "decode") => (1, |_, this, args| {
+            Ok(Object::String(
+                decode(as_bytes(this), string_arg(args, 0)?)?.into(),
+            ))
+        }),
+        (Object::Bytes(_), "toList") => (0, |_, this, _| {
+            Ok(new_list(
+                as_bytes(this)
+                    .iter()
+                    .map(|byte| Object::Number(*byte as f64))
+                    .collect(),
+            ))
+        }),
+        // Number
+        (Object::Number(_), "floor") => {
+            (0, |_, this, _| Ok(Object::Number(as_number(this).floor())))
+        }
+        (Object::Number(_), "ceil") => (0, |_, this, _| Ok(Object::Number(as_number(this).ceil()))),
+        (Object::Number(_), "round") => {
+            (0, |_, this, _| Ok(Object::Number(as_number(this).round())))
+        }
+        (Object::Number(_), "abs") => (0, |_, this, _| Ok(Object::Number(as_number(this).abs()))),
+        // `(2 / 3).toFixed(2)` is "0.67"
+        (Object::Number(_), "toFixed") => (1, |_, this, args| {
+            let digits: usize = match integer_arg(args, 0)? {
+                digits @ 0..=100 => digits as usize,
+                digits => {
+                    return Err(error(format!(
+                        "toFixed() takes 0 to 100 digits, got {}.",
+                        digits
+                    )))
+                }
+            };
+            Ok(Object::String(
+                util::format_fixed(as_number(this), digits).into(),
+            ))
+        }),
+        // List
+        (Object::List(_), "length") => (0, |_, this, _| {
+            Ok(Object::Number(as_list(this).borrow().len() as f64))
+        }),
+        (Object::List(_), "map") => (1, |interpreter, this, args| {
+            // Copy the elements first, the callback is free to mutate the list
+            let elements: Vec<Object> = as_list(this).borrow().clone();
+            let mut res: Vec<Object> = vec![];
+
+            for element in elements {
+                res.push(interpreter.call_value(args[0].clone(), &[element])?);
+            }
+
+            Ok(new_list(res))
+        }),
+        (Object::List(_), "filter") => (1, list_filter),
+        // One string built in a single pass, cheaper than concatenating the
+        // elements one by one with `+`. Elements that aren't strings are
+        // converted like `str` does.
+        (Object::List(_), "join") => (1, |_, this, args| {
+            let separator: &str = string_arg(args, 0)?;
+            let mut res: String = String::new();
+            for (i, element) in as_list(this).borrow().iter().enumerate() {
+                if i > 0 {
+                    res.push_str(separator);
+                }
+                match element {
+                    Object::String(val) => res.push_str(val),
+                    element => res.push_str(&interpreter::stringify(element)),
+                }
+            }
+            Ok(Object::String(res.into()))
+        }),
+        (Object::List(_), "reduce") => (2, list_reduce),
+        (Object::List(_), "sort") => (1, list_sort),
+        (Object::List(_), "push") => (1, |_, this, args| {
+            as_list(this).borrow_mut().get_mut()?.push(args[0].clone());
+            Ok(Object::None)
+        }),
+        (Object::List(_), "pop") => (0, |_, this, _| {
+            match as_list(this).borrow_mut().get_mut()?.pop() {
+                Some(val) => Ok(val),
+                None => Err(error("Can't pop from an empty list.".to_owned())),
+            }
+        }),
+        (Object::List(_), "insert") => (2, |_, this, args| {
+            let mut list = as_list(this).borrow_mut();
+            let list = list.get_mut()?;
+            // Inserting right after the last element is allowed
+            let i: usize = index_arg(args, 0, list.len() + 1)?;
+            list.insert(i, args[1].clone());
+            Ok(Object::None)
+        }),
+        (Object::List(_), "remove") => (1, |_, this, args| {
+            let mut list = as_list(this).borrow_mut();
+            let list = list.get_mut()?;
+            let i: usize = index_arg(args, 0, list.len())?;
+            Ok(list.remove(i))
+        }),
+        // Map
+        (Object::Map(_), "size") => (0, |_, this, _| {
+            Ok(Object::Number(as_map(this).borrow().len() as f64))
+        }),
+        (Object::Map(_), "keys") => (0, |_, this, _| {
+            Ok(new_list(
+                as_map(this).borrow().keys().map(from_key).collect(),
+            ))
+        }),
+        (Object::Map(_), "values") => (0, |_, this, _| {
+            Ok(new_list(as_map(this).borrow().values().cloned().collect()))
+        }),
+        (Object::Map(_), "has") => (1, |_, this, args| {
+            Ok(Object::Boolean(
+                as_map(this).borrow().contains_key(&to_key(&args[0])?),
+            ))
+        }),
+        // Returns the removed value, `nil` if there was none
+        (Object::Map(_), "remove") => (1, |_, this, args| {
+            let removed: Option<Object> = as_map(this)
+                .borrow_mut()
+                .get_mut()?
+                .shift_remove(&to_key(&args[0])?);
+            Ok(removed.unwrap_or(Object::None))
+        }),
+        // A new map, entries of `other` win over existing ones
+        (Object::Map(_), "merge") => (1, |_, this, args| {
+            let other = match &args[0] {
+                Object::Map(other) => other.borrow().clone(),
+                _ => return Err(error("Argument 1 must be a map.".to_owned())),
+            };
+
+            let mut res: IndexMap<Literal, Object> = as_map(this).borrow().clone();
+            res.extend(other);
+            Ok(new_map(res))
+        }),
+        _ => return None,
+    };
+
+    Some(LoxCallable::Method {
+        name: name.to_owned(),
+        arity,
+        receiver: Box::new(receiver.clone()),
+        body,
+    })
+}
+
+fn list_filter(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let elements: Vec<Object> = as_list(this).borrow().clone();
+    let mut res: Vec<Object> = vec![];
+
+    for element in elements {
+        let keep: Object =
+            interpreter.call_value(arguments[0].clone(), std::slice::from_ref(&element))?;
+        if interpreter::is_truthy(&keep) {
+            res.push(element);
+        }
+    }
+
+    Ok(new_list(res))
+}
+
+// `xs.reduce(f, initial)` calls `f(accumulator, element)` for each element
+fn list_reduce(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let elements: Vec<Object> = as_list(this).borrow().clone();
+    let mut acc: Object = arguments[1].clone();
+
+    for element in elements {
+        acc = interpreter.call_value(arguments[0].clone(), &[acc, element])?;
+    }
+
+    Ok(acc)
+}
+
+// `xs.sort(comparator)` sorts in place. The comparator returns a negative
+// number, zero, or a positive number, like `a - b`. With `nil` instead of a
+// comparator, numbers and strings are sorted in their natural order.
+fn list_sort(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let comparator: Object = arguments[0].clone();
+    let elements: Vec<Object> = as_list(this).borrow().clone();
+
+    let sorted: Vec<Object> = merge_sort(elements, &mut |a, b| match &comparator {
+        Object::None => match (a, b) {
+            (Object::Number(a), Object::Number(b)) => Ok(a <= b),
+            (Object::String(a), Object::String(b)) => Ok(a <= b),
+            _ => Err(error(
+                "Only lists of numbers or strings can be sorted without a comparator.".to_owned(),
+            )),
+        },
+        _ => match interpreter.call_value(comparator.clone(), &[a.clone(), b.clone()])? {
+            Object::Number(val) => Ok(val <= 0.0),
+            _ => Err(error("Comparator must return a number.".to_owned())),
+        },
+    })?;
+
+    *as_list(this).borrow_mut().get_mut()? = sorted;
+    Ok(Object::None)
+}
+
+// Stable merge sort with a fallible `a <= b`. `slice::sort_by` can't stop on
+// errors and may panic if a user comparator isn't a total order.
+fn merge_sort(
+    mut items: Vec<Object>,
+    less_equal: &mut dyn FnMut(&Object, &Object) -> Result<bool, LoxError>,
+) -> Result<Vec<Object>, LoxError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right: Vec<Object> = items.split_off(items.len() / 2);
+    let left: Vec<Object> = merge_sort(items, less_equal)?;
+    let right: Vec<Object> = merge_sort(right, less_equal)?;
+
+    let mut res: Vec<Object> = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if less_equal(a, b)? {
+            res.push(left.next().unwrap());
+        } else {
+            res.push(right.next().unwrap());
+        }
+    }
+    res.extend(left);
+    res.extend(right);
+
+    Ok(res)
+}
+
+// There are no static members, so modules are instances of an otherwise
+// empty class, with the natives and constants stored as fields.
+fn module(name: &str, members: Vec<(&str, Object)>) -> Object {
+    let instance = LoxInstance::new(LoxClass::new(name.to_owned(), Object::None, HashMap::new()));
+
+    for (member, value) in members {
+        instance.borrow_mut().define(Symbol::intern(member), value);
+    }
+    instance.borrow_mut().freeze();
+
+    Object::Instance(instance)
+}
+
+pub fn native(arity: usize, body: impl NativeFn) -> Object {
+    Object::Callable(LoxCallable::Native {
+        arity,
+        variadic: false,
+        body: Rc::new(body),
+    })
+}
+
+// Takes `min_arity` or more arguments
+pub fn variadic(min_arity: usize, body: impl NativeFn) -> Object {
+    Object::Callable(LoxCallable::Native {
+        arity: min_arity,
+        variadic: true,
+        body: Rc::new(body),
+    })
+}
+
+pub fn math_module() -> Object {
+    module(
+        "Math",
+        vec![
+            ("PI", Object::Number(consts::PI)),
+            ("E", Object::Number(consts::E)),
+            ("sqrt", native(1, |_, args| unary(args, f64::sqrt))),
+            ("abs", native(1, |_, args| unary(args, f64::abs))),
+            ("floor", native(1, |_, args| unary(args, f64::floor))),
+            ("ceil", native(1, |_, args| unary(args, f64::ceil))),
+            ("round", native(1, |_, args| unary(args, f64::round))),
+            ("sin", native(1, |_, args| unary(args, f64::sin))),
+            ("cos", native(1, |_, args| unary(args, f64::cos))),
+            ("tan", native(1, |_, args| unary(args, f64::tan))),
+            ("log", native(1, |_, args| unary(args, f64::ln))),
+            ("exp", native(1, |_, args| unary(args, f64::exp))),
+            ("pow", native(2, |_, args| binary(args, f64::powf))),
+            ("min", native(2, |_, args| binary(args, f64::min))),
+            ("max", native(2, |_, args| binary(args, f64::max))),
+        ],
+    )
+}
+
+fn unary(arguments: &[Object], f: fn(f64) -> f64) -> Result<Object, LoxError> {
+    Ok(Object::Number(f(number_arg(arguments, 0)?)))
+}
+
+fn binary(arguments: &[Object], f: fn(f64, f64) -> f64) -> Result<Object, LoxError> {
+    Ok(Object::Number(f(
+        number_arg(arguments, 0)?,
+        number_arg(arguments, 1)?,
+    )))
+}
+
+pub fn random(interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::Number(interpreter.rng.next_f64()))
+}
+
+// Both bounds are inclusive
+pub fn random_int(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let lo: i64 = integer_arg(arguments, 0)?;
+    let hi: i64 = integer_arg(arguments, 1)?;
+
+    if lo > hi {
+        return Err(error(format!(
+            "randomInt() lower bound {} is greater than upper bound {}.",
+            lo, hi
+        )));
+    }
+
+    // Past 2^53 numbers can't tell neighbouring integers apart anyway
+    let span: u64 = match hi.abs_diff(lo).checked_add(1) {
+        Some(span) if span <= 1 << 53 => span,
+        _ => {
+            return Err(error(
+                "randomInt() range is too large, it can span at most 2^53 integers.".to_owned(),
+            ))
+        }
+    };
+    Ok(Object::Number(
+        lo.wrapping_add(interpreter.rng.below(span) as i64) as f64,
+    ))
+}
+
+pub fn random_seed(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    interpreter.rng.seed(integer_arg(arguments, 0)? as u64);
+    Ok(Object::None)
+}
+
+// Fisher-Yates, in place
+pub fn shuffle(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let list = match &arguments[0] {
+        Object::List(list) => list,
+        _ => return Err(error("Argument 1 must be a list.".to_owned())),
+    };
+    let mut list = list.borrow_mut();
+    let list = list.get_mut()?;
+
+    for i in (1..list.len()).rev() {
+        let j: usize = interpreter.rng.below(i as u64 + 1) as usize;
+        list.swap(i, j);
+    }
+
+    Ok(Object::None)
+}
+
+// `nil` if the string isn't a number
+pub fn num(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    match string_arg(arguments, 0)?.trim().parse::<f64>() {
+        Ok(val) => Ok(Object::Number(val)),
+        Err(_) => Ok(Object::None),
+    }
+}
+
+pub fn str(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(interpreter::stringify(&arguments[0]).into()))
+}
+
+// 0 for an empty list
+pub fn sum(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let numbers: Vec<f64> = numbers_arg(arguments, 0)?;
+    // `Iterator::sum` starts at -0
+    Ok(Object::Number(numbers.iter().fold(0.0, |acc, x| acc + x)))
+}
+
+pub fn min(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    aggregate(arguments, "min", |xs| {
+        xs.iter().copied().fold(f64::INFINITY, f64::min)
+    })
+}
+
+pub fn max(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    aggregate(arguments, "max", |xs| {
+        xs.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+    })
+}
+
+pub fn avg(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    aggregate(arguments, "avg", |xs| {
+        xs.iter().fold(0.0, |acc, x| acc + x) / xs.len() as f64
+    })
+}
+
+// Aggregates that are undefined for an empty list
+fn aggregate(arguments: &[Object], name: &str, f: fn(&[f64]) -> f64) -> Result<Object, LoxError> {
+    let numbers: Vec<f64> = numbers_arg(arguments, 0)?;
+    if numbers.is_empty() {
+        return Err(error(format!("Can't take the {} of an empty list.", name)));
+    }
+
+    Ok(Object::Number(f(&numbers)))
+}
+
+// `nil` if the string isn't an integer in the given base
+pub fn parse_int(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let text: &str = string_arg(arguments, 0)?;
+    let base: i64 = integer_arg(arguments, 1)?;
+
+    if !(2..=36).contains(&base) {
+        return Err(error(format!(
+            "parseInt() base must be between 2 and 36, got {}.",
+            base
+        )));
+    }
+
+    match i64::from_str_radix(text.trim(), base as u32) {
+        Ok(val) => Ok(Object::Number(val as f64)),
+        Err(_) => Ok(Object::None),
+    }
+}
+
+// Reads stdin up to EOF, e.g. for `cat data.txt | lox filter.lox`. Refused
+// in the REPL, where it would swallow the rest of the session's input.
+pub fn read_all(interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    if interpreter.repl {
+        return Err(error(
+            "readAll() is only available when running a script.".to_owned(),
+        ));
+    }
+
+    let mut text = String::new();
+    match interpreter.read_input(|input| input.read_to_string(&mut text)) {
+        Ok(_) => Ok(Object::String(text.into())),
+        Err(err) => Err(error(format!("Failed to read from stdin: {}.", err))),
+    }
+}
+
+// `jsonParse(text)`, see `convert` for what JSON values become
+pub fn json_parse(
+    _interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    match serde_json::from_str::<serde_json::Value>(string_arg(arguments, 0)?) {
+        Ok(value) => Ok(value.to_lox()),
+        Err(err) => Err(error(format!("Invalid JSON: {}.", err))),
+    }
+}
+
+// `jsonStringify(value)`, on one line
+pub fn json_stringify(
+    _interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let value = serde_json::Value::from_lox(&arguments[0])?;
+    Ok(Object::String(value.to_string().into()))
+}
+
+// `encode(text, encoding)` gives the bytes of a string
+pub fn encode(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let text: &str = string_arg(arguments, 0)?;
+
+    let bytes: Vec<u8> = match string_arg(arguments, 1)? {
+        "utf-8" | "utf8" => text.as_bytes().to_vec(),
+        encoding @ ("latin1" | "ascii") => {
+            let max: char = if encoding == "ascii" {
+                '\x7f'
+            } else {
+                '\u{ff}'
+            };
+            match text.chars().find(|c| *c > max) {
+                Some(c) => return Err(error(format!("Can't encode '{}' as {}.", c, encoding))),
+                None => text.chars().map(|c| c as u8).collect(),
+            }
+        }
+        encoding => return Err(unknown_encoding(encoding)),
+    };
+
+    Ok(Object::Bytes(Rc::new(bytes)))
+}
+
+fn decode(bytes: &[u8], encoding: &str) -> Result<String, LoxError> {
+    match encoding {
+        "utf-8" | "utf8" => match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => Ok(text),
+            Err(err) => Err(error(format!("Invalid UTF-8: {}.", err))),
+        },
+        "latin1" => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        "ascii" => match bytes.iter().find(|byte| !byte.is_ascii()) {
+            Some(byte) => Err(error(format!("Byte {} is not ASCII.", byte))),
+            None => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        },
+        _ => Err(unknown_encoding(encoding)),
+    }
+}
+
+fn unknown_encoding(encoding: &str) -> LoxError {
+    error(format!(
+        "Unknown encoding '{}', expected 'utf-8', 'latin1' or 'ascii'.",
+        encoding
+    ))
+}
+
+pub fn read_file_bytes(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::FsRead, "readFileBytes")?;
+    let path: &str = string_arg(arguments, 0)?;
+
+    match fs::read(path) {
+        Ok(bytes) => Ok(Object::Bytes(Rc::new(bytes))),
+        Err(err) => Err(error(format!("Can't read '{}': {}.", path, err))),
+    }
+}
+
+pub fn write_file_bytes(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::FsWrite, "writeFileBytes")?;
+    let path: &str = string_arg(arguments, 0)?;
+    let bytes = match &arguments[1] {
+        Object::Bytes(bytes) => bytes,
+        _ => return Err(error("Argument 2 must be bytes.".to_owned())),
+    };
+
+    match fs::write(path, &bytes[..]) {
+        Ok(()) => Ok(Object::None),
+        Err(err) => Err(error(format!("Can't write '{}': {}.", path, err))),
+    }
+}
+
+// The Unicode scalar value of a one-character string
+pub fn ord(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let text: &str = string_arg(arguments, 0)?;
+    let mut chars = text.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Object::Number(c as u32 as f64)),
+        _ => Err(error(format!(
+            "ord() expects a single character, got '{}'.",
+            text
+        ))),
+    }
+}
+
+pub fn chr(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let code: i64 = integer_arg(arguments, 0)?;
+
+    match u32::try_from(code).ok().and_then(char::from_u32) {
+        Some(c) => Ok(Object::String(c.to_string().into())),
+        None => Err(error(format!("{} is not a valid code point.", code))),
+    }
+}
+
+pub fn code_points(
+    _interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let text: &str = string_arg(arguments, 0)?;
+    Ok(new_list(
+        text.chars()
+            .map(|c| Object::Number(c as u32 as f64))
+            .collect(),
+    ))
+}
+
+// Reads one line from stdin, without the line terminator. `nil` on EOF.
+// Rustyline only holds the terminal in raw mode while it's waiting for REPL
+// input, so by the time code runs stdin is a plain line-buffered stream.
+pub fn read_line(interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    let mut line = String::new();
+
+    match interpreter.read_input(|input| input.read_line(&mut line)) {
+        Ok(0) => Ok(Object::None),
+        Ok(_) => {
+            let trimmed: &str = line.trim_end_matches(['\n', '\r']);
+            Ok(Object::String(trimmed.into()))
+        }
+        Err(err) => Err(error(format!("Failed to read from stdin: {}.", err))),
+    }
+}
+
+// `nil` if the variable isn't set (or isn't valid unicode)
+pub fn getenv(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter.capabilities.check(Capability::Env, "getenv")?;
+    match env::var(string_arg(arguments, 0)?) {
+        Ok(val) => Ok(Object::String(val.into())),
+        Err(_) => Ok(Object::None),
+    }
+}
+
+pub fn setenv(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter.capabilities.check(Capability::Env, "setenv")?;
+    let name: &str = string_arg(arguments, 0)?;
+    let value: &str = string_arg(arguments, 1)?;
+
+    if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+        return Err(error(format!("Invalid environment variable '{}'.", name)));
+    }
+
+    env::set_var(name, value);
+    Ok(Object::None)
+}
+
+// "linux", "macos", "windows", ...
+pub fn os_name(_interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(env::consts::OS.into()))
+}
+
+pub fn cpu_count(
+    _interpreter: &mut Interpreter,
+    _arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let count: usize = thread::available_parallelism().map_or(1, |n| n.get());
+    Ok(Object::Number(count as f64))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn hostname(_interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(
+        gethostname::gethostname()
+            .to_string_lossy()
+            .into_owned()
+            .into(),
+    ))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn hostname(_interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Err(error(
+        "hostname() isn't available in the browser.".to_owned(),
+    ))
+}
+
+pub fn cwd(interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter.capabilities.check(Capability::Env, "cwd")?;
+    match env::current_dir() {
+        Ok(path) => Ok(Object::String(path.to_string_lossy().into_owned().into())),
+        Err(err) => Err(error(format!("Can't get working directory: {}.", err))),
+    }
+}
+
+pub fn chdir(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter.capabilities.check(Capability::Env, "chdir")?;
+    let path: &str = string_arg(arguments, 0)?;
+
+    match env::set_current_dir(path) {
+        Ok(()) => Ok(Object::None),
+        Err(err) => Err(error(format!(
+            "Can't change directory to '{}': {}.",
+            path, err
+        ))),
+    }
+}
+
+// `exec(cmd, args)` runs a program to completion, capturing its output:
+// `{"stdout": ..., "stderr": ..., "status": ...}`. The status is `nil` if
+// the process was killed by a signal.
+pub fn exec(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::Process, "exec")?;
+    let output = command(arguments)?
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| spawn_error(arguments, err))?;
+
+    let mut res: IndexMap<Literal, Object> = IndexMap::new();
+    for (key, value) in [
+        (
+            "stdout",
+            Object::String(String::from_utf8_lossy(&output.stdout).into_owned().into()),
+        ),
+        (
+            "stderr",
+            Object::String(String::from_utf8_lossy(&output.stderr).into_owned().into()),
+        ),
+        ("status", exit_status(output.status)),
+    ] {
+        res.insert(Literal::String(key.into()), value);
+    }
+
+    Ok(new_map(res))
+}
+
+// `execStream(cmd, args, f)` calls `f(line)` for each line the program
+// writes to stdout as it arrives, and returns its exit status. Stderr is
+// passed through.
+pub fn exec_stream(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::Process, "execStream")?;
+    let mut child = command(arguments)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| spawn_error(arguments, err))?;
+
+    let stdout = child.stdout.take().unwrap();
+    for line in BufReader::new(stdout).lines() {
+        let line: String = match line {
+            Ok(line) => line,
+            Err(err) => {
+                let _ = child.kill();
+                return Err(error(format!("Failed to read process output: {}.", err)));
+            }
+        };
+
+        if let Err(err) =
+            interpreter.call_value(arguments[2].clone(), &[Object::String(line.into())])
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+    }
+
+    match child.wait() {
+        Ok(status) => Ok(exit_status(status)),
+        Err(err) => Err(error(format!("Failed to wait for process: {}.", err))),
+    }
+}
+
+fn command(arguments: &[Object]) -> Result<Command, LoxError> {
+    let mut command = Command::new(string_arg(arguments, 0)?);
+
+    match &arguments[1] {
+        Object::List(args) => {
+            for arg in args.borrow().iter() {
+                match arg {
+                    Object::String(arg) => command.arg(&**arg),
+                    _ => return Err(error("Process arguments must be strings.".to_owned())),
+                };
+            }
+        }
+        _ => return Err(error("Argument 2 must be a list.".to_owned())),
+    }
+
+    Ok(command)
+}
+
+fn spawn_error(arguments: &[Object], err: io::Error) -> LoxError {
+    error(format!(
+        "Failed to run '{}': {}.",
+        interpreter::stringify(&arguments[0]),
+        err
+    ))
+}
+
+fn exit_status(status: ExitStatus) -> Object {
+    match status.code() {
+        Some(code) => Object::Number(code as f64),
+        None => Object::None,
+    }
+}
+
+// Sleeps in short slices so that Ctrl-C in the REPL can cut it short
+pub fn sleep(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    // The browser's thread can't block
+    if cfg!(target_arch = "wasm32") {
+        return Err(error("sleep() isn't available in the browser.".to_owned()));
+    }
+    let ms: f64 = number_arg(arguments, 0)?;
+    // Negative, NaN, infinite, or past what `Instant` can count to
+    let deadline: Option<Instant> = Duration::try_from_secs_f64(ms / 1000.0)
+        .ok()
+        .and_then(|duration| Instant::now().checked_add(duration));
+    let Some(deadline) = deadline else {
+        return Err(error(format!(
+            "Can't sleep for {} ms.",
+            util::format_number(ms)
+        )));
+    };
+
+    loop {
+        if interpreter.interrupt.swap(false, Ordering::SeqCst) {
+            return Err(error("Interrupted.".to_owned()));
+        }
+        // Fails once the script's time is up, like any other statement
+        interpreter.budget.check()?;
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(Object::None);
+        }
+
+        // Wakes up when either the sleep or the script's time is over
+        let wake: Instant = match interpreter.budget.deadline() {
+            Some(timeout) => deadline.min(timeout),
+            None => deadline,
+        };
+        thread::sleep(
+            wake.saturating_duration_since(now)
+                .min(Duration::from_millis(10)),
+        );
+    }
+}
+
+// Pauses the script where it's called and evaluates the expressions typed
+// in there (e.g. `total`, or `total = 0` to change it), until an empty line
+// or the end of input resumes it. See `debugger::evaluate`.
+pub fn inspect(interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    let _ = writeln!(
+        interpreter.stdout,
+        "Paused by inspect(), enter an expression to evaluate it, or an empty line to resume."
+    );
+
+    loop {
+        let _ = write!(interpreter.stdout, "(inspect) ");
+        let _ = interpreter.stdout.flush();
+
+        let mut input: String = String::new();
+        if interpreter
+            .read_input(|stdin| stdin.read_line(&mut input))
+            .unwrap_or(0)
+            == 0
+        {
+            let _ = writeln!(interpreter.stdout);
+            break;
+        }
+        let expr: &str = input.trim();
+        if expr.is_empty() {
+            break;
+        }
+
+        let value: String = debugger::evaluate(interpreter, expr);
+        let _ = writeln!(interpreter.stdout, "{}", value);
+    }
+
+    Ok(Object::None)
+}
+
+// `loadPlugin("lib.so")`, see `plugin::load`
+pub fn load_plugin(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::Process, "loadPlugin")?;
+    plugin::load(interpreter, string_arg(arguments, 0)?).map_err(error)?;
+    Ok(Object::None)
+}
+
+// `saveState(path)`, see `state::save`. Returns the names of the globals it
+// couldn't save.
+pub fn save_state(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::FsWrite, "saveState")?;
+    let skipped: Vec<String> =
+        state::save(interpreter, string_arg(arguments, 0)?).map_err(error)?;
+    Ok(new_list(
+        skipped
+            .into_iter()
+            .map(|name| Object::String(name.into()))
+            .collect(),
+    ))
+}
+
+pub fn load_state(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    interpreter
+        .capabilities
+        .check(Capability::FsRead, "loadState")?;
+    state::load(interpreter, string_arg(arguments, 0)?).map_err(error)?;
+    Ok(Object::None)
+}
+
+pub fn set_max_call_depth(
+    interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    match integer_arg(arguments, 0)? {
+        depth if depth < 1 => Err(error(format!(
+            "Max call depth must be positive, got {}.",
+            depth
+        ))),
+        depth if depth as u64 > interpreter.call_depth_cap as u64 => Err(error(format!(
+            "Max call depth can't be raised above {}.",
+            interpreter.call_depth_cap
+        ))),
+        depth => {
+            interpreter.max_call_depth = depth as usize;
+            Ok(Object::None)
+        }
+    }
+}
+
+// Nanoseconds on a monotonic clock, unaffected by wall-clock adjustments.
+// Counted from the first call so the value stays exact as an f64.
+fn monotonic_nanos() -> f64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as f64
+}
+
+pub fn monotonic_clock(
+    _interpreter: &mut Interpreter,
+    _arguments: &[Object],
+) -> Result<Object, LoxError> {
+    Ok(Object::Number(monotonic_nanos()))
+}
+
+// `var sw = stopwatch(); ...; sw.elapsed()` gives the seconds since creation
+pub fn stopwatch(
+    _interpreter: &mut Interpreter,
+    _arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let elapsed = LoxCallable::Method {
+        name: "elapsed".to_owned(),
+        arity: 0,
+        receiver: Box::new(Object::Number(monotonic_nanos())),
+        body: |_, start, _| Ok(Object::Number((monotonic_nanos() - as_number(start)) / 1e9)),
+    };
+
+    Ok(module(
+        "Stopwatch",
+        vec![("elapsed", Object::Callable(elapsed))],
+    ))
+}
+
+// `format("x = {}, y = {:.2}", x, y)`, see the `format` module
+pub fn format(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(
+        format::format(string_arg(arguments, 0)?, &arguments[1..])?.into(),
+    ))
+}
+
+// Like `format`, but prints the result. No newline is added.
+pub fn printf(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    let text: String = format::format(string_arg(arguments, 0)?, &arguments[1..])?;
+    let _ = write!(interpreter.stdout, "{}", text);
+    let _ = interpreter.stdout.flush();
+    Ok(Object::None)
+}
+
+// Aborts the script with a runtime error, e.g. for unreachable branches.
+// TODO: throw a catchable `Error` instance once exceptions exist.
+pub fn panic(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    Err(error(interpreter::stringify(&arguments[0])))
+}
+
+// Random, from the OS rather than `rng`, so `randomSeed` doesn't repeat IDs
+pub fn uuid(_interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(Uuid::new_v4().to_string().into()))
+}
+
+// Time-ordered: IDs generated later sort after earlier ones
+pub fn uuid_v7(_interpreter: &mut Interpreter, _arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::String(Uuid::now_v7().to_string().into()))
+}
+
+// Hex digests of the UTF-8 bytes of a string
+pub fn sha256(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    digest::<Sha256>(arguments)
+}
+
+pub fn sha1(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    digest::<Sha1>(arguments)
+}
+
+pub fn md5(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    digest::<Md5>(arguments)
+}
+
+// `hmacSha256(key, message)`
+pub fn hmac_sha256(
+    _interpreter: &mut Interpreter,
+    arguments: &[Object],
+) -> Result<Object, LoxError> {
+    let key: &str = string_arg(arguments, 0)?;
+    let message: &str = string_arg(arguments, 1)?;
+
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    Ok(Object::String(hex(&mac.finalize().into_bytes()).into()))
+}
+
+fn digest<D: Digest>(arguments: &[Object]) -> Result<Object, LoxError> {
+    let text: &str = string_arg(arguments, 0)?;
+    Ok(Object::String(hex(&D::digest(text.as_bytes())).into()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// A stable hash, truncated to 53 bits so it is exact as a Lox number
+pub fn hash(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(Object::Number(
+        (hash_value(&arguments[0], &mut HashSet::new())? & ((1 << 53) - 1)) as f64,
+    ))
+}
+
+// Equal values hash equally: lists hash in order, maps independently of it.
+// `enclosing` holds the address of each list and map `obj` is nested in, so
+// one containing itself fails rather than recursing forever.
+fn hash_value(obj: &Object, enclosing: &mut HashSet<usize>) -> Result<u64, LoxError> {
+    let address: Option<usize> = match obj {
+        Object::List(list) => Some(Rc::as_ptr(list) as usize),
+        Object::Map(map) => Some(Rc::as_ptr(map) as usize),
+        _ => None,
+    };
+    if let Some(address) = address {
+        if !enclosing.insert(address) {
+            return Err(error(
+                "Can't hash a list or map that contains itself.".to_owned(),
+            ));
+        }
+    }
+
+    let mut hasher = Fnv::default();
+
+    match obj {
+        Object::List(list) => {
+            "list".hash(&mut hasher);
+            for element in list.borrow().iter() {
+                hash_value(element, enclosing)?.hash(&mut hasher);
+            }
+        }
+        Object::Map(map) => {
+            "map".hash(&mut hasher);
+            let mut entries: u64 = 0;
+            for (key, value) in map.borrow().iter() {
+                let mut entry = Fnv::default();
+                key.hash(&mut entry);
+                hash_value(value, enclosing)?.hash(&mut entry);
+                entries = entries.wrapping_add(entry.finish());
+            }
+            entries.hash(&mut hasher);
+        }
+        _ => match to_key(obj) {
+            Ok(key) => key.hash(&mut hasher),
+            Err(_) => {
+                return Err(error(
+                    "Only strings, numbers, booleans, nil, lists and maps can be hashed."
+                        .to_owned(),
+                ))
+            }
+        },
+    }
+
+    if let Some(address) = address {
+        enclosing.remove(&address);
+    }
+    Ok(hasher.finish())
+}
+
+// Deep copy of lists, maps and instances. Instances keep their class.
+pub fn clone(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    Ok(deep_clone(&arguments[0], &mut HashMap::new()))
+}
+
+// `copies` maps the address of each container copied so far to its copy,
+// so shared references (and cycles) are shared in the copy as well.
+fn deep_clone(obj: &Object, copies: &mut HashMap<usize, Object>) -> Object {
+    let address: usize = match obj {
+        Object::List(list) => Rc::as_ptr(list) as usize,
+        Object::Map(map) => Rc::as_ptr(map) as usize,
+        Object::Instance(instance) => Rc::as_ptr(instance) as usize,
+        _ => return obj.clone(),
+    };
+
+    if let Some(copy) = copies.get(&address) {
+        return copy.clone();
+    }
+
+    match obj {
+        Object::List(list) => {
+            let copy = Rc::new(RefCell::new(Freezable::new(vec![])));
+            copies.insert(address, Object::List(copy.clone()));
+
+            let elements: Vec<Object> = list
+                .borrow()
+                .iter()
+                .map(|element| deep_clone(element, copies))
+                .collect();
+            *copy.borrow_mut().get_mut().unwrap() = elements;
+
+            Object::List(copy)
+        }
+        Object::Map(map) => {
+            let copy = Rc::new(RefCell::new(Freezable::new(IndexMap::new())));
+            copies.insert(address, Object::Map(copy.clone()));
+
+            let entries: IndexMap<Literal, Object> = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| (key.clone(), deep_clone(value, copies)))
+                .collect();
+            *copy.borrow_mut().get_mut().unwrap() = entries;
+
+            Object::Map(copy)
+        }
+        Object::Instance(instance) => {
+            let copy = LoxInstance::new(instance.borrow().class());
+            copies.insert(address, Object::Instance(copy.clone()));
+
+            let fields: Vec<(Symbol, Object)> = instance
+                .borrow()
+                .fields()
+                .iter()
+                .map(|(name, value)| (*name, deep_clone(value, copies)))
+                .collect();
+            for (name, value) in fields {
+                copy.borrow_mut().define(name, value);
+            }
+
+            Object::Instance(copy)
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Makes a list, map or instance read-only and returns it. Shallow: the
+// values inside stay as mutable as they were.
+pub fn freeze(_interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, LoxError> {
+    match &arguments[0] {
+        Object::List(list) => list.borrow_mut().freeze(),
+        Object::Map(map) => map.borrow_mut().freeze(),
+        Object::Instance(instance) => instance.borrow_mut().freeze(),
+        _ => {
+            return Err(error(
+                "Only lists, maps and instances can be frozen.".to_owned(),
+            ))
+        }
+    }
+
+    Ok(arguments[0].clone())
+}
+
+// Only primitives that can be hashed reliably can be map keys
+pub fn to_key(obj: &Object) -> Result<Literal, LoxError> {
+    match obj {
+        Object::String(val) => Ok(Literal::String(val.clone())),
+        // -0 and 0 are equal but hash differently
+        Object::Number(val) if *val == 0.0 => Ok(Literal::Number(0.0)),
+        Object::Number(val) => Ok(Literal::Number(*val)),
+        Object::Boolean(val) => Ok(Literal::Boolean(*val)),
+        Object::None => Ok(Literal::None),
+        _ => Err(error(
+            "Map keys must be strings, numbers, booleans, or nil.".to_owned(),
+        )),
+    }
+}
+
+pub fn from_key(key: &Literal) -> Object {
+    match key {
+        Literal::String(val) => Object::String(val.clone()),
+        Literal::Bytes(val) => Object::Bytes(Rc::new(val.clone())),
+        Literal::Number(val) => Object::Number(*val),
+        Literal::Boolean(val) => Object::Boolean(*val),
+        Literal::None => Object::None,
+    }
+}
+
+pub fn new_list(elements: Vec<Object>) -> Object {
+    Object::List(Rc::new(RefCell::new(Freezable::new(elements))))
+}
+
+pub fn new_map(entries: IndexMap<Literal, Object>) -> Object {
+    Object::Map(Rc::new(RefCell::new(Freezable::new(entries))))
+}
+
+pub fn error(message: String) -> LoxError {
+    LoxError::RuntimeError {
+        message,
+        token: None,
+    }
+}
+
+fn number_arg(arguments: &[Object], i: usize) -> Result<f64, LoxError> {
+    match &arguments[i] {
+        Object::Number(val) => Ok(*val),
+        _ => Err(error(format!("Argument {} must be a number.", i + 1))),
+    }
+}
+
+fn integer_arg(arguments: &[Object], i: usize) -> Result<i64, LoxError> {
+    match number_arg(arguments, i)? {
+        val if val.fract() == 0.0 => Ok(val as i64),
+        _ => Err(error(format!("Argument {} must be an integer.", i + 1))),
+    }
+}
+
+// An integer argument usable as an index into something of length `len`
+fn index_arg(arguments: &[Object], i: usize, len: usize) -> Result<usize, LoxError> {
+    match integer_arg(arguments, i)? {
+        val if val >= 0 && (val as usize) < len => Ok(val as usize),
+        val => Err(error(format!("Index {} out of bounds.", val))),
+    }
+}
+
+fn numbers_arg(arguments: &[Object], i: usize) -> Result<Vec<f64>, LoxError> {
+    let list = match &arguments[i] {
+        Object::List(list) => list.borrow(),
+        _ => return Err(error(format!("Argument {} must be a list.", i + 1))),
+    };
+
+    list.iter()
+        .enumerate()
+        .map(|(j, element)| match element {
+            Object::Number(val) => Ok(*val),
+            _ => Err(error(format!(
+                "Element {} of argument {} must be a number, got {}.",
+                j,
+                i + 1,
+                interpreter::stringify(element)
+            ))),
+        })
+        .collect()
+}
+
+fn string_arg(arguments: &[Object], i: usize) -> Result<&str, LoxError> {
+    match &arguments[i] {
+        Object::String(val) => Ok(val),
+        _ => Err(error(format!("Argument {} must be a string.", i + 1))),
+    }
+}
+
+// The receivers below are guaranteed by the patterns in `find_method`.
+
+fn as_string(obj: &Object) -> &str {
+    match obj {
+        Object::String(val) => val,
+        _ => unreachable!(),
+    }
+}
+
+fn as_number(obj: &Object) -> f64 {
+    match obj {
+        Object::Number(val) => *val,
+        _ => unreachable!(),
+    }
+}
+
+fn as_bytes(obj: &Object) -> &[u8] {
+    match obj {
+        Object::Bytes(val) => val,
+        _ => unreachable!(),
+    }
+}
+
+fn as_list(obj: &Object) -> &Rc<RefCell<Freezable<Vec<Object>>>> {
+    match obj {
+        Object::List(val) => val,
+        _ => unreachable!(),
+    }
+}
+
+fn as_map(obj: &Object) -> &Rc<RefCell<Freezable<IndexMap<Literal, Object>>>> {
+    match obj {
+        Object::Map(val) => val,
+        _ => unreachable!(),
+    }
+}
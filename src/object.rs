@@ -1,17 +1,69 @@
-use std::{cell::RefCell, rc::Rc};
+use indexmap::IndexMap;
+use std::ops::Deref;
 
 use crate::{
     callable::LoxCallable,
     class::{LoxClass, LoxInstance},
+    error::LoxError,
+    foreign::Foreign,
+    sync::{Rc, RefCell},
+    token::Literal,
 };
 
 #[derive(strum_macros::Display, Clone, Debug)]
 pub enum Object {
-    String(String),
+    String(Rc<str>),
+    // Immutable, so shared freely
+    Bytes(Rc<Vec<u8>>),
     Number(f64),
     Boolean(bool),
     Callable(LoxCallable),
     Class(Rc<RefCell<LoxClass>>),
     Instance(Rc<RefCell<LoxInstance>>),
+    // A Rust value, see `foreign`
+    Foreign(Foreign),
+    List(Rc<RefCell<Freezable<Vec<Object>>>>),
+    // Keys are restricted to hashable primitives, see `native::to_key`.
+    // Insertion ordered, so iteration and printing are deterministic.
+    Map(Rc<RefCell<Freezable<IndexMap<Literal, Object>>>>),
     None,
 }
+
+// A container that `freeze()` can make read-only. Reads go through `Deref`,
+// writes have to go through `get_mut`, which fails once frozen.
+#[derive(Debug)]
+pub struct Freezable<T> {
+    value: T,
+    frozen: bool,
+}
+
+impl<T> Freezable<T> {
+    pub fn new(value: T) -> Self {
+        Freezable {
+            value,
+            frozen: false,
+        }
+    }
+
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn get_mut(&mut self) -> Result<&mut T, LoxError> {
+        match self.frozen {
+            true => Err(LoxError::RuntimeError {
+                message: "Can't modify a frozen value.".to_owned(),
+                token: None,
+            }),
+            false => Ok(&mut self.value),
+        }
+    }
+}
+
+impl<T> Deref for Freezable<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
@@ -0,0 +1,490 @@
+// An optional pass between parsing and resolution (`--opt`), which folds
+// constant expressions and drops code that can never run.
+//
+// Only rewrites that can't change what a program does are made. Anything
+// that would raise a runtime error (e.g. `1 + "a"`) is left untouched, so
+// the interpreter still reports it, at the same line.
+
+use std::mem;
+
+use crate::{
+    diagnostic::Diagnostic,
+    expr::Expr,
+    resolver,
+    stmt::Stmt,
+    token::{Literal, Span, Token, TokenType},
+};
+
+#[derive(Default)]
+pub struct Optimizer {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Optimizer {
+    pub fn optimize(&mut self, statements: Vec<Option<Stmt>>) -> Vec<Option<Stmt>> {
+        self.optimize_block(
+            statements
+                .into_iter()
+                .map(|stmt| stmt.map(Box::new))
+                .collect(),
+        )
+        .into_iter()
+        .map(|stmt| stmt.map(|stmt| *stmt))
+        .collect()
+    }
+
+    // Also drops the statements following a `return`, `break` or `continue`.
+    // They are gone before the resolver would warn about them, so warn here.
+    fn optimize_block(&mut self, statements: Vec<Option<Box<Stmt>>>) -> Vec<Option<Box<Stmt>>> {
+        let mut res: Vec<Option<Box<Stmt>>> = vec![];
+        let mut statements = statements.into_iter().flatten().peekable();
+
+        while let Some(stmt) = statements.next() {
+            let stmt: Stmt = self.optimize_stmt(*stmt);
+            let exit: Option<Token> = stmt.exit().cloned();
+            res.push(Some(Box::new(stmt)));
+
+            if let Some(keyword) = exit {
+                if statements.peek().is_some() {
+                    self.diagnostics
+                        .push(Diagnostic::warning(&keyword, resolver::UNREACHABLE));
+                }
+                break;
+            }
+        }
+
+        res
+    }
+
+    fn optimize_stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Block { statements, span } => Stmt::Block {
+                statements: self.optimize_block(statements),
+                span,
+            },
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                span,
+            } => Stmt::Class {
+                span,
+                name,
+                superclass,
+                methods: methods
+                    .into_iter()
+                    .map(|method| Box::new(self.optimize_stmt(*method)))
+                    .collect(),
+            },
+            Stmt::Expression { expression, span } => Stmt::Expression {
+                expression: optimize_expr(expression),
+                span,
+            },
+            Stmt::Function {
+                name,
+                params,
+                body,
+                span,
+            } => Stmt::Function {
+                span,
+                name,
+                params,
+                body: self.optimize_block(body),
+            },
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                let condition: Expr = optimize_expr(condition);
+                let then_branch: Stmt = self.optimize_stmt(*then_branch);
+                let else_branch: Option<Stmt> = (*else_branch).map(|stmt| self.optimize_stmt(stmt));
+
+                match &condition {
+                    Expr::Literal { value, .. } if is_truthy(value) => then_branch,
+                    Expr::Literal { .. } => else_branch.unwrap_or_else(|| empty(span)),
+                    _ => Stmt::If {
+                        span,
+                        keyword,
+                        condition,
+                        then_branch: Box::new(then_branch),
+                        else_branch: Box::new(else_branch),
+                    },
+                }
+            }
+            Stmt::Print {
+                keyword,
+                expression,
+                stderr,
+                span,
+            } => Stmt::Print {
+                span,
+                keyword,
+                expression: optimize_expr(expression),
+                stderr,
+            },
+            Stmt::Return {
+                keyword,
+                value,
+                span,
+            } => Stmt::Return {
+                span,
+                keyword,
+                value: value.map(optimize_expr),
+            },
+            Stmt::Var {
+                name,
+                initializer,
+                span,
+            } => Stmt::Var {
+                span,
+                name,
+                initializer: initializer.map(optimize_expr),
+            },
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+                increment,
+                span,
+            } => {
+                let condition: Expr = optimize_expr(condition);
+
+                match &condition {
+                    Expr::Literal { value, .. } if !is_truthy(value) => empty(span),
+                    _ => Stmt::While {
+                        span,
+                        keyword,
+                        condition,
+                        body: Box::new(self.optimize_stmt(*body)),
+                        increment: increment.map(optimize_expr),
+                    },
+                }
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => stmt,
+        }
+    }
+}
+
+pub fn optimize_expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::Assign {
+            id,
+            name,
+            value,
+            span,
+        } => Expr::Assign {
+            span,
+            id,
+            name,
+            value: optimize_box(value),
+        },
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            span,
+        } => {
+            let left: Expr = optimize_expr(*left);
+            let right: Expr = optimize_expr(*right);
+
+            if let (Expr::Literal { value: left, .. }, Expr::Literal { value: right, .. }) =
+                (&left, &right)
+            {
+                if let Some(value) = fold_binary(&operator, left, right) {
+                    return Expr::Literal { value, span };
+                }
+            }
+
+            Expr::Binary {
+                span,
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+            span,
+        } => Expr::Call {
+            span,
+            callee: optimize_box(callee),
+            paren,
+            arguments: arguments.into_iter().map(optimize_box).collect(),
+        },
+        Expr::Get {
+            id,
+            object,
+            name,
+            span,
+        } => Expr::Get {
+            span,
+            id,
+            object: optimize_box(object),
+            name,
+        },
+        Expr::Grouping { expression, span } => match optimize_expr(*expression) {
+            Expr::Literal { value, .. } => Expr::Literal { value, span },
+            expression => Expr::Grouping {
+                span,
+                expression: Box::new(expression),
+            },
+        },
+        Expr::Index {
+            object,
+            bracket,
+            index,
+            span,
+        } => Expr::Index {
+            span,
+            object: optimize_box(object),
+            bracket,
+            index: optimize_box(index),
+        },
+        Expr::IndexSet {
+            object,
+            bracket,
+            index,
+            value,
+            span,
+        } => Expr::IndexSet {
+            span,
+            object: optimize_box(object),
+            bracket,
+            index: optimize_box(index),
+            value: optimize_box(value),
+        },
+        Expr::List {
+            bracket,
+            elements,
+            span,
+        } => Expr::List {
+            span,
+            bracket,
+            elements: elements.into_iter().map(optimize_box).collect(),
+        },
+        Expr::Map {
+            brace,
+            entries,
+            span,
+        } => Expr::Map {
+            span,
+            brace,
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| (optimize_box(key), optimize_box(value)))
+                .collect(),
+        },
+        Expr::Logical {
+            left,
+            operator,
+            right,
+            span,
+        } => {
+            let left: Expr = optimize_expr(*left);
+            let right: Expr = optimize_expr(*right);
+
+            // `and` gives the left operand if it's falsey, `or` if it's truthy
+            match &left {
+                Expr::Literal { value, .. } => match (&operator.token_type, is_truthy(value)) {
+                    (TokenType::Or, true) | (TokenType::And, false) => left,
+                    _ => right,
+                },
+                _ => Expr::Logical {
+                    span,
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                },
+            }
+        }
+        Expr::Set {
+            object,
+            name,
+            value,
+            span,
+        } => Expr::Set {
+            span,
+            object: optimize_box(object),
+            name,
+            value: optimize_box(value),
+        },
+        Expr::Unary {
+            operator,
+            right,
+            span,
+        } => fold_unary(operator, optimize_expr(*right), span),
+        Expr::Literal { .. } | Expr::Super { .. } | Expr::This { .. } | Expr::Variable { .. } => {
+            expr
+        }
+    }
+}
+
+// Optimizes a boxed child expression in place, reusing its allocation
+fn optimize_box(mut expr: Box<Expr>) -> Box<Expr> {
+    let placeholder = Expr::Literal {
+        value: Literal::None,
+        span: Span::default(),
+    };
+    *expr = optimize_expr(mem::replace(&mut *expr, placeholder));
+    expr
+}
+
+// Mirrors `Expr::Binary` in the interpreter, `None` where it would fail
+fn fold_binary(operator: &Token, left: &Literal, right: &Literal) -> Option<Literal> {
+    let value: Literal = match (&operator.token_type, left, right) {
+        (TokenType::EqualEqual, left, right) => Literal::Boolean(left == right),
+        (TokenType::BangEqual, left, right) => Literal::Boolean(left != right),
+        (TokenType::Plus, Literal::String(left), Literal::String(right)) => {
+            Literal::String(format!("{}{}", left, right).into())
+        }
+        (TokenType::Plus, Literal::Bytes(left), Literal::Bytes(right)) => {
+            Literal::Bytes([&left[..], &right[..]].concat())
+        }
+        (token_type, Literal::Number(left), Literal::Number(right)) => match token_type {
+            TokenType::Plus => Literal::Number(left + right),
+            TokenType::Minus => Literal::Number(left - right),
+            TokenType::Star => Literal::Number(left * right),
+            TokenType::Slash => Literal::Number(left / right),
+            TokenType::Greater => Literal::Boolean(left > right),
+            TokenType::GreaterEqual => Literal::Boolean(left >= right),
+            TokenType::Less => Literal::Boolean(left < right),
+            TokenType::LessEqual => Literal::Boolean(left <= right),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+// `span` is the unary expression's, which a folded result stands for
+fn fold_unary(operator: Token, right: Expr, span: Span) -> Expr {
+    match (&operator.token_type, right) {
+        (
+            TokenType::Bang,
+            Expr::Literal {
+                value: Literal::Boolean(value),
+                ..
+            },
+        ) => Expr::Literal {
+            value: Literal::Boolean(!value),
+            span,
+        },
+        (
+            TokenType::Minus,
+            Expr::Literal {
+                value: Literal::Number(value),
+                ..
+            },
+        ) => Expr::Literal {
+            value: Literal::Number(-value),
+            span,
+        },
+        // `!(a == b)` is `a != b` and vice versa, equality never fails
+        (
+            TokenType::Bang,
+            Expr::Binary {
+                left,
+                operator: inner,
+                right,
+                ..
+            },
+        ) if matches!(
+            inner.token_type,
+            TokenType::EqualEqual | TokenType::BangEqual
+        ) =>
+        {
+            let (token_type, lexeme) = match inner.token_type {
+                TokenType::EqualEqual => (TokenType::BangEqual, "!="),
+                _ => (TokenType::EqualEqual, "=="),
+            };
+
+            Expr::Binary {
+                span,
+                left,
+                operator: Token::new(
+                    token_type,
+                    lexeme.to_owned(),
+                    Literal::None,
+                    inner.line,
+                    inner.column,
+                    inner.offset,
+                ),
+                right,
+            }
+        }
+        // A double negation only cancels out if the operand is known to have
+        // the right type, otherwise the inner negation would have failed
+        (
+            TokenType::Bang,
+            Expr::Unary {
+                operator: inner,
+                right,
+                ..
+            },
+        ) if inner.token_type == TokenType::Bang && is_boolean(&right) => *right,
+        (
+            TokenType::Minus,
+            Expr::Unary {
+                operator: inner,
+                right,
+                ..
+            },
+        ) if inner.token_type == TokenType::Minus && is_number(&right) => *right,
+        (_, right) => Expr::Unary {
+            span,
+            operator,
+            right: Box::new(right),
+        },
+    }
+}
+
+// Whether `expr` can only evaluate to a boolean (or fail)
+fn is_boolean(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { value, .. } => matches!(value, Literal::Boolean(_)),
+        Expr::Unary { operator, .. } => operator.token_type == TokenType::Bang,
+        Expr::Binary { operator, .. } => matches!(
+            operator.token_type,
+            TokenType::EqualEqual
+                | TokenType::BangEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+        ),
+        Expr::Grouping { expression, .. } => is_boolean(expression),
+        _ => false,
+    }
+}
+
+// Whether `expr` can only evaluate to a number (or fail)
+fn is_number(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { value, .. } => matches!(value, Literal::Number(_)),
+        Expr::Unary { operator, .. } => operator.token_type == TokenType::Minus,
+        Expr::Binary { operator, .. } => matches!(
+            operator.token_type,
+            TokenType::Minus | TokenType::Star | TokenType::Slash
+        ),
+        Expr::Grouping { expression, .. } => is_number(expression),
+        _ => false,
+    }
+}
+
+// Same as `interpreter::is_truthy`, for literals
+pub fn is_truthy(value: &Literal) -> bool {
+    !matches!(value, Literal::None | Literal::Boolean(false))
+}
+
+fn empty(span: Span) -> Stmt {
+    Stmt::Block {
+        statements: vec![],
+        span,
+    }
+}
@@ -1,19 +1,40 @@
+use std::mem;
+
 use crate::{
+    diagnostic::{Diagnostic, MAX_ERRORS, TOO_MANY_ERRORS},
     error::LoxError,
-    expr::Expr,
-    lox::Lox,
+    expr::{Expr, ExprId},
+    scanner::{ScanError, Scanner},
     stmt::Stmt,
-    token::{Literal, Token, TokenType},
+    sync::Rc,
+    token::{Literal, Span, Token, TokenType},
 };
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+// Pulls tokens from the scanner as it goes, one token of lookahead is enough.
+// Scan errors are reported along with parse errors, in source order.
+pub struct Parser<I: Iterator<Item = Result<Token, ScanError>> = Scanner> {
+    tokens: I,
+    current: Token,
+    previous: Token,
+    // How many blocks and class bodies we're in, see `synchronize`
+    depth: usize,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+impl<I: Iterator<Item = Result<Token, ScanError>>> Parser<I> {
+    // `tokens` has to end with `Eof`, as the `Scanner`'s do
+    pub fn new(tokens: I) -> Self {
+        let eof = Token::new(TokenType::Eof, "".to_owned(), Literal::None, 0, 0, 0);
+        let mut parser = Parser {
+            tokens,
+            current: eof.clone(),
+            previous: eof,
+            depth: 0,
+            diagnostics: vec![],
+        };
+
+        parser.current = parser.next_token();
+        parser
     }
 
     // program -> statement* EOF ;
@@ -43,7 +64,7 @@ impl Parser {
             return match self.function("function".to_string()) {
                 Ok(stmt) => Some(stmt),
                 Err(_) => {
-                    self.synchronize();
+                    self.skip_function();
                     None
                 }
             };
@@ -70,24 +91,34 @@ impl Parser {
 
     // classDecl -> "class" ( "<" IDENTIFIER )? "{" function* "}" ;
     fn class_declaration(&mut self) -> Result<Stmt, LoxError> {
-        let name: Token = self.consume(TokenType::Identifier, "Expect class name.")?;
+        let start: Span = Span::of(self.previous());
+        let keyword_doc: Option<Rc<str>> = self.previous().doc.clone();
+        let mut name: Token = self.consume(TokenType::Identifier, "Expect class name.")?;
+        // A doc comment is scanned onto the keyword, see `Scanner::add_doc`
+        name.doc = name.doc.or(keyword_doc);
 
-        let superclass: Option<Expr>;
-        if self.is_match_advance(&[TokenType::Less]) {
+        let superclass: Option<Expr> = if self.is_match_advance(&[TokenType::Less]) {
             let _ = self.consume(TokenType::Identifier, "Expect superclass name.");
-            superclass = Some(Expr::Variable {
+            Some(Expr::Variable {
+                id: ExprId::fresh(),
                 name: self.previous().clone(),
-            });
+                span: Span::of(self.previous()),
+            })
         } else {
-            superclass = None;
-        }
+            None
+        };
 
         self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
 
         let mut methods: Vec<Box<Stmt>> = vec![];
+        self.depth += 1;
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            methods.push(Box::new(self.function("method".to_owned())?));
+            match self.function("method".to_owned()) {
+                Ok(method) => methods.push(Box::new(method)),
+                Err(_) => self.skip_function(),
+            }
         }
+        self.depth -= 1;
 
         let _ = self.consume(TokenType::RightBrace, "Expect '}' after class body.");
 
@@ -95,12 +126,22 @@ impl Parser {
             name,
             superclass,
             methods,
+            span: self.span_from(start),
         })
     }
 
     // function -> IDENTIFIER "(" parameters? ")" block ;
     fn function(&mut self, kind: String) -> Result<Stmt, LoxError> {
-        let name: Token = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
+        // Methods have no `fn` to carry their doc comment, or start at
+        let (keyword_doc, keyword): (Option<Rc<str>>, Option<Span>) =
+            match self.previous().token_type {
+                TokenType::Fn => (self.previous().doc.clone(), Some(Span::of(self.previous()))),
+                _ => (None, None),
+            };
+        let mut name: Token =
+            self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
+        name.doc = name.doc.or(keyword_doc);
+        let start: Span = keyword.unwrap_or(Span::of(&name));
         self.consume(
             TokenType::LeftParen,
             &format!("Expect '(' after {} name.", kind),
@@ -111,7 +152,7 @@ impl Parser {
         if !self.check(&TokenType::RightParen) {
             loop {
                 if params.len() >= 255 {
-                    Self::error(self.peek(), "Can't have more than 255 parameters.");
+                    self.error(&self.peek().clone(), "Can't have more than 255 parameters.");
                 }
 
                 params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
@@ -131,20 +172,23 @@ impl Parser {
             Ok(vec) => {
                 // Vec<Option<Stmt>> -> Vec<Option<Block<Stmt>>>
                 vec.iter()
-                    .map(|x| match x {
-                        Some(val) => Some(Box::new(val.clone())),
-                        None => None,
-                    })
+                    .map(|x| x.as_ref().map(|val| Box::new(val.clone())))
                     .collect()
             }
             Err(err) => return Err(err),
         };
 
-        Ok(Stmt::Function { name, params, body })
+        Ok(Stmt::Function {
+            name,
+            params,
+            body,
+            span: self.span_from(start),
+        })
     }
 
     // varDecl -> "var" IDENTIFIER ( "=" expression )? ";" ;
     fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let start: Span = Span::of(self.previous());
         let name: Token = self.consume(TokenType::Identifier, "Expect variable name.")?;
 
         let initializer: Option<Expr> = if self.is_match_advance(&[TokenType::Equal]) {
@@ -158,11 +202,24 @@ impl Parser {
             "Expect ';' after variable declaration.",
         )?;
 
-        Ok(Stmt::Var { name, initializer })
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            span: self.span_from(start),
+        })
     }
 
-    // statement -> exprStmt | forStmt | ifStmt | printStmt | whileStmt | block ;
+    // statement -> exprStmt | forStmt | ifStmt | printStmt | whileStmt | loopStmt
+    //              | breakStmt | continueStmt | block ;
     fn statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        if self.is_match_advance(&[TokenType::Break]) {
+            return self.break_statement();
+        }
+
+        if self.is_match_advance(&[TokenType::Continue]) {
+            return self.continue_statement();
+        }
+
         if self.is_match_advance(&[TokenType::For]) {
             return self.for_statement();
         }
@@ -171,7 +228,11 @@ impl Parser {
             return self.if_statement();
         }
 
-        if self.is_match_advance(&[TokenType::Print]) {
+        if self.is_match_advance(&[TokenType::Loop]) {
+            return self.loop_statement();
+        }
+
+        if self.is_match_advance(&[TokenType::Print, TokenType::Eprint]) {
             return self.print_statement();
         }
 
@@ -184,19 +245,18 @@ impl Parser {
         }
 
         if self.is_match_advance(&[TokenType::LeftBrace]) {
+            let start: Span = Span::of(self.previous());
             return Ok(Some(Stmt::Block {
                 statements: match self.block() {
                     Ok(vec) => {
                         // Vec<Option<Stmt>> -> Vec<Option<Box<Stmt>>>
                         vec.iter()
-                            .map(|x| match x {
-                                Some(stmt) => Some(Box::new(stmt.clone())),
-                                None => None,
-                            })
+                            .map(|x| x.as_ref().map(|stmt| Box::new(stmt.clone())))
                             .collect()
                     }
                     Err(err) => return Err(err),
                 },
+                span: self.span_from(start),
             }));
         }
 
@@ -205,25 +265,49 @@ impl Parser {
 
     // exprStmt -> expression ";" ;
     fn expression_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let start: Span = Span::of(self.peek());
         let expr: Expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
-        Ok(Some(Stmt::Expression { expression: expr }))
+        Ok(Some(Stmt::Expression {
+            expression: expr,
+            span: self.span_from(start),
+        }))
+    }
+
+    // breakStmt -> "break" ";" ;
+    fn break_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+        Ok(Some(Stmt::Break {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+        }))
+    }
+
+    // continueStmt -> "continue" ";" ;
+    fn continue_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+        Ok(Some(Stmt::Continue {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+        }))
     }
 
     // forStmt -> "for" "(" ( varDecl | exprStmt | ";" )
     //            expression? ";"
     //            expression? ")" statement ";"
     fn for_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
         let _ = self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
 
-        let initializer: Option<Stmt>;
-        if self.is_match_advance(&[TokenType::Semicolon]) {
-            initializer = None;
+        let initializer: Option<Stmt> = if self.is_match_advance(&[TokenType::Semicolon]) {
+            None
         } else if self.is_match_advance(&[TokenType::Var]) {
-            initializer = Some(self.var_declaration()?);
+            Some(self.var_declaration()?)
         } else {
-            initializer = self.expression_statement()?;
-        }
+            self.expression_statement()?
+        };
 
         let mut condition: Option<Expr>;
         if !self.check(&TokenType::Semicolon) {
@@ -233,44 +317,38 @@ impl Parser {
         }
         let _ = self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
 
-        let increment: Option<Expr>;
-        if !self.check(&TokenType::RightParen) {
-            increment = Some(self.expression()?);
+        let increment: Option<Expr> = if !self.check(&TokenType::RightParen) {
+            Some(self.expression()?)
         } else {
-            increment = None;
-        }
+            None
+        };
         let _ = self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
 
+        // The increment is kept on the `While` node instead of being appended
+        // to the body, so that `continue` doesn't skip it.
         let mut body: Option<Stmt> = self.statement()?;
-        if !increment.is_none() {
-            body = Some(Stmt::Block {
-                statements: vec![
-                    Some(Box::new(body.unwrap())),
-                    Some(Box::new(Stmt::Expression {
-                        expression: increment.unwrap(),
-                    })),
-                ],
-            });
-        }
+        let span: Span = self.span_from(Span::of(&keyword));
 
         // If the condition is not specified, set it to `true`
         // i.e. infinite loop
         if condition.is_none() {
             condition = Some(Expr::Literal {
                 value: Literal::Boolean(true),
+                span: Span::of(&keyword),
             });
         }
         body = Some(Stmt::While {
+            keyword,
             condition: condition.unwrap(),
             body: Box::new(body.unwrap()),
+            increment,
+            span,
         });
 
-        if !initializer.is_none() {
+        if let Some(initializer) = initializer {
             body = Some(Stmt::Block {
-                statements: vec![
-                    Some(Box::new(initializer.unwrap())),
-                    Some(Box::new(body.unwrap())),
-                ],
+                statements: vec![Some(Box::new(initializer)), Some(Box::new(body.unwrap()))],
+                span,
             });
         }
 
@@ -280,6 +358,7 @@ impl Parser {
     // ifStmt -> "if" "(" expression ")" statement
     //           ( "else" statement )? ;
     fn if_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
         let _ = self.consume(TokenType::LeftParen, "Expect '(' after 'if' .");
         let condition: Expr = self.expression()?;
         let _ = self.consume(TokenType::RightParen, "Expect ')' after if condition.");
@@ -291,52 +370,91 @@ impl Parser {
         };
 
         Ok(Some(Stmt::If {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
             condition,
             then_branch: Box::new(then_branch),
             else_branch: Box::new(else_branch),
         }))
     }
 
-    // printStmt -> "print" expression ";" ;
+    // loopStmt -> "loop" statement ;
+    fn loop_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
+        let body: Box<Stmt> = Box::new(self.statement()?.unwrap());
+
+        // `loop` is just sugar for `while (true)`
+        Ok(Some(Stmt::While {
+            condition: Expr::Literal {
+                value: Literal::Boolean(true),
+                span: Span::of(&keyword),
+            },
+            body,
+            increment: None,
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+        }))
+    }
+
+    // printStmt -> ( "print" | "eprint" ) expression ";" ;
     fn print_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
+        let stderr: bool = keyword.token_type == TokenType::Eprint;
         let expr: Expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
-        Ok(Some(Stmt::Print { expression: expr }))
+        Ok(Some(Stmt::Print {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+            expression: expr,
+            stderr,
+        }))
     }
 
     // returnStmt -> "return" expression? ";" ;
     fn return_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
         let keyword: Token = self.previous().clone();
 
-        let value: Option<Expr>;
-        if !self.check(&TokenType::Semicolon) {
-            value = Some(self.expression()?);
+        let value: Option<Expr> = if !self.check(&TokenType::Semicolon) {
+            Some(self.expression()?)
         } else {
-            value = None;
-        }
+            None
+        };
 
         let _ = self.consume(TokenType::Semicolon, "Expect ';' after return value.");
 
-        Ok(Some(Stmt::Return { keyword, value }))
+        Ok(Some(Stmt::Return {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+            value,
+        }))
     }
 
     // whileStmt -> "while" "(" expression ")" statement ;
     fn while_statement(&mut self) -> Result<Option<Stmt>, LoxError> {
+        let keyword: Token = self.previous().clone();
         let _ = self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
         let condition: Expr = self.expression()?;
         let _ = self.consume(TokenType::RightParen, "Expect ')' after condition.");
         let body: Box<Stmt> = Box::new(self.statement()?.unwrap());
 
-        Ok(Some(Stmt::While { condition, body }))
+        Ok(Some(Stmt::While {
+            span: self.span_from(Span::of(&keyword)),
+            keyword,
+            condition,
+            body,
+            increment: None,
+        }))
     }
 
     // block -> "{" declaration* "}" ;
     fn block(&mut self) -> Result<Vec<Option<Stmt>>, LoxError> {
         let mut statements: Vec<Option<Stmt>> = vec![];
 
+        self.depth += 1;
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
             statements.push(self.declaration());
         }
+        self.depth -= 1;
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
         Ok(statements)
@@ -347,24 +465,48 @@ impl Parser {
         self.assignment()
     }
 
-    // assignment -> ( call "." )? IDENTIFIER "=" assignment | logic_or ;
+    // assignment -> ( call "." )? IDENTIFIER "=" assignment
+    //               | call "[" expression "]" "=" assignment | logic_or ;
     fn assignment(&mut self) -> Result<Expr, LoxError> {
         let expr: Expr = self.or()?;
 
         if self.is_match_advance(&[TokenType::Equal]) {
             let equals: Token = self.previous().to_owned();
             let value: Box<Expr> = Box::new(self.assignment()?);
+            let span: Span = expr.span().to(value.span());
 
             match expr {
-                Expr::Variable { name } => return Ok(Expr::Assign { name, value }),
-                Expr::Get { object, name } => {
+                Expr::Variable { id, name, .. } => {
+                    return Ok(Expr::Assign {
+                        id,
+                        name,
+                        value,
+                        span,
+                    })
+                }
+                Expr::Get { object, name, .. } => {
                     return Ok(Expr::Set {
                         object,
                         name,
                         value,
+                        span,
                     })
                 }
-                _ => return Err(Self::error(&equals, "Invalid assignment target.")),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                    ..
+                } => {
+                    return Ok(Expr::IndexSet {
+                        object,
+                        bracket,
+                        index,
+                        value,
+                        span,
+                    })
+                }
+                _ => return Err(self.error(&equals, "Invalid assignment target.")),
             }
         }
 
@@ -379,6 +521,7 @@ impl Parser {
             let operator = self.previous().clone();
             let right: Expr = self.and()?;
             expr = Expr::Logical {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -397,6 +540,7 @@ impl Parser {
             let operator: Token = self.previous().clone();
             let right: Expr = self.equality()?;
             expr = Expr::Logical {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -415,6 +559,7 @@ impl Parser {
             let right: Expr = self.comparison()?;
 
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -438,6 +583,7 @@ impl Parser {
             let right: Expr = self.term()?;
 
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -456,6 +602,7 @@ impl Parser {
             let right: Expr = self.factor()?;
 
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -474,6 +621,7 @@ impl Parser {
             let right: Expr = self.unary()?;
 
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -490,6 +638,7 @@ impl Parser {
             let expr: Expr = self.unary()?;
 
             return Ok(Expr::Unary {
+                span: Span::of(&operator).to(expr.span()),
                 operator,
                 right: Box::new(expr),
             });
@@ -498,7 +647,7 @@ impl Parser {
         self.call()
     }
 
-    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
     fn call(&mut self) -> Result<Expr, LoxError> {
         let mut expr: Expr = self.primary()?;
 
@@ -509,9 +658,21 @@ impl Parser {
                 let name: Token =
                     self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
                 expr = Expr::Get {
+                    id: ExprId::fresh(),
+                    span: expr.span().to(Span::of(&name)),
                     object: Box::new(expr),
                     name,
                 }
+            } else if self.is_match_advance(&[TokenType::LeftBracket]) {
+                let index: Expr = self.expression()?;
+                let bracket: Token =
+                    self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index {
+                    span: expr.span().to(Span::of(&bracket)),
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                }
             } else {
                 break;
             }
@@ -527,7 +688,7 @@ impl Parser {
         if !self.check(&TokenType::RightParen) {
             loop {
                 if arguments.len() >= 255 {
-                    Self::error(self.peek(), "Can't have more than 255 arguments.");
+                    self.error(&self.peek().clone(), "Can't have more than 255 arguments.");
                 }
 
                 arguments.push(Box::new(self.expression()?));
@@ -541,6 +702,7 @@ impl Parser {
         let paren: Token = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
 
         Ok(Expr::Call {
+            span: callee.span().to(Span::of(&paren)),
             callee: Box::new(callee),
             paren,
             arguments,
@@ -548,38 +710,97 @@ impl Parser {
     }
 
     // primary -> "true" | "false" | "nil" | "this"
-    //            | NUMBER | STRING | IDENTIFIER | "(" expression ")"
+    //            | NUMBER | STRING | BYTES | IDENTIFIER | "(" expression ")"
+    //            | "[" ( expression ( "," expression )* )? "]"
+    //            | "{" ( expression ":" expression ( "," expression ":" expression )* )? "}"
     //            | "super" "." IDENTIFIER ;
     fn primary(&mut self) -> Result<Expr, LoxError> {
-        if self.is_match_advance(&[TokenType::Number, TokenType::String]) {
+        if self.is_match_advance(&[TokenType::Number, TokenType::String, TokenType::Bytes]) {
             return Ok(Expr::Literal {
                 value: self.previous().literal.clone(),
+                span: Span::of(self.previous()),
             });
         }
 
         if self.is_match_advance(&[TokenType::True]) {
             return Ok(Expr::Literal {
                 value: Literal::Boolean(true),
+                span: Span::of(self.previous()),
             });
         }
 
         if self.is_match_advance(&[TokenType::False]) {
             return Ok(Expr::Literal {
                 value: Literal::Boolean(false),
+                span: Span::of(self.previous()),
             });
         }
 
         if self.is_match_advance(&[TokenType::Nil]) {
             return Ok(Expr::Literal {
                 value: Literal::None,
+                span: Span::of(self.previous()),
             });
         }
 
         if self.is_match_advance(&[TokenType::LeftParen]) {
+            let start: Span = Span::of(self.previous());
             let expr: Expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
             return Ok(Expr::Grouping {
                 expression: Box::new(expr),
+                span: self.span_from(start),
+            });
+        }
+
+        if self.is_match_advance(&[TokenType::LeftBracket]) {
+            let start: Span = Span::of(self.previous());
+            let mut elements: Vec<Box<Expr>> = vec![];
+
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(Box::new(self.expression()?));
+
+                    if !self.is_match_advance(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            let bracket: Token =
+                self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(Expr::List {
+                bracket,
+                elements,
+                span: self.span_from(start),
+            });
+        }
+
+        // A `{` in statement position is a block, so map literals only appear
+        // where an expression is expected
+        if self.is_match_advance(&[TokenType::LeftBrace]) {
+            let start: Span = Span::of(self.previous());
+            let mut entries: Vec<(Box<Expr>, Box<Expr>)> = vec![];
+
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let key: Expr = self.expression()?;
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    let value: Expr = self.expression()?;
+                    entries.push((Box::new(key), Box::new(value)));
+
+                    if !self.is_match_advance(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            let brace: Token =
+                self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map {
+                brace,
+                entries,
+                span: self.span_from(start),
             });
         }
 
@@ -588,22 +809,31 @@ impl Parser {
             let _ = self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
             let method: Token =
                 self.consume(TokenType::Identifier, "Expect superclass method name.")?;
-            return Ok(Expr::Super { keyword, method });
+            return Ok(Expr::Super {
+                id: ExprId::fresh(),
+                span: Span::of(&keyword).to(Span::of(&method)),
+                keyword,
+                method,
+            });
         }
 
         if self.is_match_advance(&[TokenType::This]) {
             return Ok(Expr::This {
+                id: ExprId::fresh(),
                 keyword: self.previous().clone(),
+                span: Span::of(self.previous()),
             });
         }
 
         if self.is_match_advance(&[TokenType::Identifier]) {
             return Ok(Expr::Variable {
+                id: ExprId::fresh(),
                 name: self.previous().to_owned(),
+                span: Span::of(self.previous()),
             });
         }
 
-        Err(Self::error(self.peek(), "Expect expression."))
+        Err(self.error(&self.peek().clone(), "Expect expression."))
     }
 
     // ------------------------------ Utility functions --------------------------------
@@ -629,22 +859,40 @@ impl Parser {
 
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
-            self.current += 1;
+            let next: Token = self.next_token();
+            self.previous = mem::replace(&mut self.current, next);
         }
 
         self.previous()
     }
 
+    // The next token from the scanner, reporting the errors before it
+    fn next_token(&mut self) -> Token {
+        loop {
+            match self.tokens.next() {
+                Some(Ok(token)) => return token,
+                Some(Err(error)) => self.report(error.into()),
+                // Only if `tokens` didn't end with `Eof`
+                None => return self.current.clone(),
+            }
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
     fn peek(&self) -> &Token {
-        self.tokens.get(self.current).unwrap()
+        &self.current
     }
 
     fn previous(&self) -> &Token {
-        self.tokens.get(self.current - 1).unwrap()
+        &self.previous
+    }
+
+    // From `start` to the end of the last token consumed
+    fn span_from(&self, start: Span) -> Span {
+        start.to(Span::of(self.previous()))
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, LoxError> {
@@ -652,15 +900,36 @@ impl Parser {
             return Ok(self.advance().clone());
         }
 
-        Err(Self::error(self.peek(), message))
+        Err(self.error(&self.peek().clone(), message))
     }
 
-    fn error(token: &Token, message: &str) -> LoxError {
-        Lox::parse_error(token, message);
+    fn error(&mut self, token: &Token, message: &str) -> LoxError {
+        self.report(Diagnostic::error_at(token, message));
         LoxError::ParseError {}
     }
 
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if self.diagnostics.len() < MAX_ERRORS {
+            self.diagnostics.push(diagnostic);
+        } else if self.diagnostics.len() == MAX_ERRORS {
+            self.diagnostics.push(Diagnostic {
+                message: TOO_MANY_ERRORS.to_owned(),
+                ..diagnostic
+            });
+
+            // Skip to the end, which stops every loop parsing statements
+            while !self.is_at_end() {
+                self.advance();
+            }
+        }
+    }
+
     fn synchronize(&mut self) {
+        // A `}` ends the enclosing block or class body, which parses it
+        if self.depth > 0 && self.check(&TokenType::RightBrace) {
+            return;
+        }
+
         // Consume everything until the end of the statement.
         // At the end, `self.current` is at the beginning of a new statement,
         // and we can continue parsing.
@@ -672,14 +941,40 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                TokenType::Class
+                TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Eprint
                 | TokenType::For
                 | TokenType::Fn
                 | TokenType::If
+                | TokenType::Loop
                 | TokenType::Print
                 | TokenType::Return
                 | TokenType::Var
                 | TokenType::While => return,
+                TokenType::RightBrace if self.depth > 0 => return,
+                _ => (),
+            }
+
+            self.advance();
+        }
+    }
+
+    // Skips the rest of a function or method that failed to parse, up to the
+    // closing `}` of its body. Stops before a `}` closing anything else.
+    fn skip_function(&mut self) {
+        let mut braces: usize = 0;
+
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::LeftBrace => braces += 1,
+                TokenType::RightBrace if braces == 0 => return,
+                TokenType::RightBrace if braces == 1 => {
+                    self.advance();
+                    return;
+                }
+                TokenType::RightBrace => braces -= 1,
                 _ => (),
             }
 
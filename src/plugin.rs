@@ -0,0 +1,187 @@
+// Native functions from shared libraries, loaded with `--plugin lib.so` or
+// `loadPlugin("lib.so")`. The boundary is a C ABI, so a plugin can be
+// written in any language and doesn't depend on how `Object` is laid out:
+//
+//     bool rustlox_plugin_init(const Registrar *registrar);
+//
+// is called once the library is loaded, and calls `registrar->define` for
+// every function it adds (as globals). A function receives its arguments as
+// `LoxValue`s and writes its result to `*result`. Returning false fails the
+// call with the string in `*result` as the message. Strings the plugin
+// returns are copied right away, they only have to outlive the call.
+//
+// Only nil, booleans, numbers and strings cross the boundary.
+
+use std::{ffi::c_void, slice};
+
+use crate::{
+    error::LoxError,
+    interpreter::{stringify, Interpreter},
+    native,
+    object::Object,
+};
+
+// The loader's, there are no shared libraries to load in a browser
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::{callable::LoxCallable, symbol::Symbol},
+    libloading::{Library, Symbol as LibSymbol},
+};
+
+// Bumped whenever the types below change, a plugin can check it
+pub const ABI_VERSION: u32 = 1;
+
+#[cfg(not(target_arch = "wasm32"))]
+const INIT: &[u8] = b"rustlox_plugin_init";
+
+pub const NIL: u8 = 0;
+pub const BOOL: u8 = 1;
+pub const NUMBER: u8 = 2;
+pub const STRING: u8 = 3;
+
+#[repr(C)]
+pub struct LoxValue {
+    // One of `NIL`, `BOOL`, `NUMBER` or `STRING`
+    pub tag: u8,
+    // `NUMBER`'s value, and `BOOL`'s as 0 or 1
+    pub number: f64,
+    // `STRING`'s bytes, UTF-8 and not NUL-terminated
+    pub string: *const u8,
+    pub len: usize,
+}
+
+pub type PluginFn =
+    unsafe extern "C" fn(args: *const LoxValue, argc: usize, result: *mut LoxValue) -> bool;
+
+#[repr(C)]
+pub struct Registrar {
+    pub version: u32,
+    // Passed back to `define`
+    pub context: *mut c_void,
+    pub define: unsafe extern "C" fn(
+        context: *mut c_void,
+        name: *const u8,
+        len: usize,
+        arity: usize,
+        function: PluginFn,
+    ),
+}
+
+// Loads the library at `path` and defines its functions as globals. The
+// library stays loaded as long as the interpreter.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(interpreter: &mut Interpreter, path: &str) -> Result<(), String> {
+    let fail = |error: &dyn std::fmt::Display| format!("Can't load plugin '{}': {}.", path, error);
+
+    // Running the library's initializers is as safe as the plugin is
+    let library: Library = unsafe { Library::new(path) }.map_err(|error| fail(&error))?;
+    let mut functions: Vec<(String, usize, PluginFn)> = vec![];
+    let registrar = Registrar {
+        version: ABI_VERSION,
+        context: &mut functions as *mut _ as *mut c_void,
+        define,
+    };
+
+    let ok: bool = unsafe {
+        let init: LibSymbol<unsafe extern "C" fn(*const Registrar) -> bool> =
+            library.get(INIT).map_err(|error| fail(&error))?;
+        init(&registrar)
+    };
+    if !ok {
+        return Err(fail(&"its initialization failed"));
+    }
+
+    for (name, arity, function) in functions {
+        let callable = LoxCallable::Plugin {
+            name: name.clone(),
+            arity,
+            function,
+        };
+        interpreter
+            .globals
+            .borrow_mut()
+            .define(Symbol::intern(&name), Object::Callable(callable));
+    }
+    interpreter.plugins.push(library);
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(_interpreter: &mut Interpreter, path: &str) -> Result<(), String> {
+    Err(format!(
+        "Can't load plugin '{}': plugins need a native build.",
+        path
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe extern "C" fn define(
+    context: *mut c_void,
+    name: *const u8,
+    len: usize,
+    arity: usize,
+    function: PluginFn,
+) {
+    let functions = &mut *(context as *mut Vec<(String, usize, PluginFn)>);
+    let name: String = String::from_utf8_lossy(slice::from_raw_parts(name, len)).into_owned();
+    functions.push((name, arity, function));
+}
+
+pub fn call(name: &str, function: PluginFn, arguments: &[Object]) -> Result<Object, LoxError> {
+    // They point into `arguments`, which outlive the call
+    let args: Vec<LoxValue> = arguments
+        .iter()
+        .map(|argument| to_value(name, argument))
+        .collect::<Result<_, _>>()?;
+
+    let mut result: LoxValue = to_value(name, &Object::None)?;
+    let ok: bool = unsafe { function(args.as_ptr(), args.len(), &mut result) };
+    let value: Object = unsafe { from_value(&result) };
+
+    match ok {
+        true => Ok(value),
+        false => Err(native::error(stringify(&value))),
+    }
+}
+
+fn to_value(name: &str, argument: &Object) -> Result<LoxValue, LoxError> {
+    value(argument).ok_or_else(|| {
+        native::error(format!(
+            "Plugin function '{}' only takes nil, booleans, numbers and strings.",
+            name
+        ))
+    })
+}
+
+// `None` for the values that don't cross the boundary. A string's bytes are
+// borrowed from `object`.
+pub fn value(object: &Object) -> Option<LoxValue> {
+    let (tag, number, string): (u8, f64, &str) = match object {
+        Object::None => (NIL, 0.0, ""),
+        Object::Boolean(value) => (BOOL, f64::from(u8::from(*value)), ""),
+        Object::Number(value) => (NUMBER, *value, ""),
+        Object::String(value) => (STRING, 0.0, value),
+        _ => return None,
+    };
+
+    Some(LoxValue {
+        tag,
+        number,
+        string: string.as_ptr(),
+        len: string.len(),
+    })
+}
+
+// A `STRING`'s `string` must point to `len` readable bytes
+pub(crate) unsafe fn from_value(value: &LoxValue) -> Object {
+    match value.tag {
+        BOOL => Object::Boolean(value.number != 0.0),
+        NUMBER => Object::Number(value.number),
+        STRING if !value.string.is_null() => {
+            let bytes: &[u8] = slice::from_raw_parts(value.string, value.len);
+            Object::String(String::from_utf8_lossy(bytes).into())
+        }
+        _ => Object::None,
+    }
+}
@@ -0,0 +1,496 @@
+// Prints a syntax tree back as Lox source, for `--emit-source` and the tools
+// that rewrite scripts. The source parses to the same tree: parentheses are
+// only added where the tree couldn't have been parsed without them (e.g. one
+// the optimizer built), and `for` and `loop` are told apart from `while` by
+// their keyword. Only doc comments are in the tree, other comments and blank
+// lines are lost.
+
+use crate::{
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    token::{Literal, Token, TokenType},
+    util::escape_bytes,
+    visitor::{ExprVisitor, StmtVisitor},
+};
+
+// How tightly an expression binds, see `precedence`
+const ASSIGNMENT: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const EQUALITY: u8 = 4;
+const COMPARISON: u8 = 5;
+const TERM: u8 = 6;
+const FACTOR: u8 = 7;
+const UNARY: u8 = 8;
+const CALL: u8 = 9;
+const PRIMARY: u8 = 10;
+
+pub fn print(expr: &Expr) -> String {
+    expr.accept(&mut Printer::default())
+}
+
+// The whole program, one statement per line, with a blank line around
+// functions and classes
+pub fn print_program(statements: &[Option<Stmt>]) -> String {
+    let mut printer = Printer::default();
+    let statements: Vec<&Stmt> = statements.iter().flatten().collect();
+    printer.lines(&statements).trim_start().to_owned()
+}
+
+// The precedence of the grammar rule that parses `expr`
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Assign { .. } | Expr::Set { .. } | Expr::IndexSet { .. } => ASSIGNMENT,
+        Expr::Logical { operator, .. } => match operator.token_type {
+            TokenType::Or => OR,
+            _ => AND,
+        },
+        Expr::Binary { operator, .. } => binary_precedence(&operator.token_type),
+        Expr::Unary { .. } => UNARY,
+        // Printed with a minus sign, or as a division
+        Expr::Literal {
+            value: Literal::Number(val),
+            ..
+        } if val.is_sign_negative() || !val.is_finite() => UNARY,
+        Expr::Call { .. } | Expr::Get { .. } | Expr::Index { .. } => CALL,
+        _ => PRIMARY,
+    }
+}
+
+fn binary_precedence(operator: &TokenType) -> u8 {
+    match operator {
+        TokenType::EqualEqual | TokenType::BangEqual => EQUALITY,
+        TokenType::Plus | TokenType::Minus => TERM,
+        TokenType::Star | TokenType::Slash => FACTOR,
+        _ => COMPARISON,
+    }
+}
+
+// Whether `expr` would be printed starting with `{`, which starts a block
+// at the beginning of a statement
+fn starts_with_map(expr: &Expr) -> bool {
+    match expr {
+        Expr::Map { .. } => true,
+        Expr::Binary { left, .. } | Expr::Logical { left, .. } => starts_with_map(left),
+        Expr::Call { callee, .. } => starts_with_map(callee),
+        Expr::Get { object, .. }
+        | Expr::Set { object, .. }
+        | Expr::Index { object, .. }
+        | Expr::IndexSet { object, .. } => starts_with_map(object),
+        _ => false,
+    }
+}
+
+// Whether an `else` after `stmt` would be taken for that of an `if` nested
+// at its end
+fn dangles(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::If { else_branch, .. } => match &**else_branch {
+            Some(else_stmt) => dangles(else_stmt),
+            None => true,
+        },
+        Stmt::While { body, .. } => dangles(body),
+        _ => false,
+    }
+}
+
+fn is_declaration(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Function { .. } | Stmt::Class { .. })
+}
+
+#[derive(Default)]
+struct Printer {
+    // How deep the statement being printed is nested
+    depth: usize,
+    // Printing a class's methods, which have no `fn`
+    in_class: bool,
+}
+
+impl Printer {
+    // `statements` one level deeper than the current statement, each on a
+    // line of its own
+    fn lines(&mut self, statements: &[&Stmt]) -> String {
+        let mut res = String::new();
+
+        for (i, stmt) in statements.iter().enumerate() {
+            if i > 0 && (is_declaration(stmt) || is_declaration(statements[i - 1])) {
+                res.push('\n');
+            }
+            res.push('\n');
+            res.push_str(&"  ".repeat(self.depth));
+            res.push_str(&stmt.accept(self));
+        }
+
+        res
+    }
+
+    fn block(&mut self, statements: &[&Stmt]) -> String {
+        if statements.is_empty() {
+            return "{}".to_owned();
+        }
+
+        self.depth += 1;
+        let lines: String = self.lines(statements);
+        self.depth -= 1;
+        format!("{{{}\n{}}}", lines, "  ".repeat(self.depth))
+    }
+
+    // The `///` comment of a function or class, on the lines before it
+    fn doc(&self, name: &Token) -> String {
+        let Some(doc) = &name.doc else {
+            return String::new();
+        };
+
+        doc.lines()
+            .map(|line| match line.is_empty() {
+                true => format!("///\n{}", "  ".repeat(self.depth)),
+                false => format!("/// {}\n{}", line, "  ".repeat(self.depth)),
+            })
+            .collect()
+    }
+
+    // `expr`, in parentheses if it binds less tightly than `min`
+    fn operand(&mut self, expr: &Expr, min: u8) -> String {
+        match precedence(expr) < min {
+            true => format!("({})", expr.accept(self)),
+            false => expr.accept(self),
+        }
+    }
+
+    fn arguments(&mut self, exprs: &[Box<Expr>]) -> String {
+        exprs
+            .iter()
+            .map(|expr| self.operand(expr, ASSIGNMENT))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // A left-associative binary expression
+    fn binary(&mut self, left: &Expr, operator: &Token, right: &Expr, precedence: u8) -> String {
+        format!(
+            "{} {} {}",
+            self.operand(left, precedence),
+            operator.lexeme,
+            self.operand(right, precedence + 1)
+        )
+    }
+
+    // `for (initializer; condition; increment) body`, `initializer` with its
+    // `;` or empty
+    fn for_loop(
+        &mut self,
+        initializer: &str,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> String {
+        let mut header: String = match initializer {
+            "" => ";".to_owned(),
+            initializer => initializer.to_owned(),
+        };
+        // Left out when it's the `true` the parser fills in
+        if !matches!(
+            condition,
+            Expr::Literal {
+                value: Literal::Boolean(true),
+                ..
+            }
+        ) {
+            header += &format!(" {}", self.operand(condition, ASSIGNMENT));
+        }
+        header.push(';');
+        if let Some(increment) = increment {
+            header += &format!(" {}", self.operand(increment, ASSIGNMENT));
+        }
+
+        format!("for ({}) {}", header, body.accept(self))
+    }
+}
+
+impl ExprVisitor<String> for Printer {
+    fn visit_assign(&mut self, _id: ExprId, name: &Token, value: &Expr) -> String {
+        format!("{} = {}", name.lexeme, self.operand(value, ASSIGNMENT))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        let precedence: u8 = binary_precedence(&operator.token_type);
+        self.binary(left, operator, right, precedence)
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Box<Expr>]) -> String {
+        format!(
+            "{}({})",
+            self.operand(callee, CALL),
+            self.arguments(arguments)
+        )
+    }
+
+    fn visit_get(&mut self, _id: ExprId, object: &Expr, name: &Token) -> String {
+        format!("{}.{}", self.operand(object, CALL), name.lexeme)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> String {
+        format!("({})", expression.accept(self))
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+        format!(
+            "{}[{}]",
+            self.operand(object, CALL),
+            self.operand(index, ASSIGNMENT)
+        )
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> String {
+        format!(
+            "{}[{}] = {}",
+            self.operand(object, CALL),
+            self.operand(index, ASSIGNMENT),
+            self.operand(value, ASSIGNMENT)
+        )
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Box<Expr>]) -> String {
+        format!("[{}]", self.arguments(elements))
+    }
+
+    // Lox strings have no escapes, a `"` in one can't be printed back
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        match value {
+            Literal::None => "nil".to_owned(),
+            Literal::String(val) => format!("\"{}\"", val),
+            Literal::Bytes(val) => escape_bytes(val),
+            Literal::Boolean(val) => val.to_string(),
+            Literal::Number(val) if val.is_nan() => "0 / 0".to_owned(),
+            Literal::Number(val) if val.is_infinite() => match val.is_sign_negative() {
+                true => "-1 / 0".to_owned(),
+                false => "1 / 0".to_owned(),
+            },
+            Literal::Number(val) => val.to_string(),
+        }
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Box<Expr>, Box<Expr>)]) -> String {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}: {}",
+                    self.operand(key, ASSIGNMENT),
+                    self.operand(value, ASSIGNMENT)
+                )
+            })
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        let precedence: u8 = match operator.token_type {
+            TokenType::Or => OR,
+            _ => AND,
+        };
+        self.binary(left, operator, right, precedence)
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> String {
+        format!(
+            "{}.{} = {}",
+            self.operand(object, CALL),
+            name.lexeme,
+            self.operand(value, ASSIGNMENT)
+        )
+    }
+
+    fn visit_super(&mut self, _id: ExprId, _keyword: &Token, method: &Token) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_this(&mut self, _id: ExprId, _keyword: &Token) -> String {
+        "this".to_owned()
+    }
+
+    // `- -x`, not `--x`, in case Lox ever gets a decrement
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
+        let right: String = self.operand(right, UNARY);
+        match operator.token_type == TokenType::Minus && right.starts_with('-') {
+            true => format!("{} {}", operator.lexeme, right),
+            false => format!("{}{}", operator.lexeme, right),
+        }
+    }
+
+    fn visit_variable(&mut self, _id: ExprId, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+}
+
+impl StmtVisitor<String> for Printer {
+    // A `for` loop with an initializer is parsed into a block of the
+    // initializer and the loop, see `Parser::for_statement`
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) -> String {
+        let statements: Vec<&Stmt> = statements.iter().flatten().map(|s| &**s).collect();
+
+        if let [initializer @ (Stmt::Var { .. } | Stmt::Expression { .. }), Stmt::While {
+            keyword,
+            condition,
+            body,
+            increment,
+            ..
+        }] = statements[..]
+        {
+            if keyword.token_type == TokenType::For {
+                let initializer: String = initializer.accept(self);
+                return self.for_loop(&initializer, condition, body, increment.as_ref());
+            }
+        }
+
+        self.block(&statements)
+    }
+
+    fn visit_break(&mut self, _keyword: &Token) -> String {
+        "break;".to_owned()
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Box<Stmt>],
+    ) -> String {
+        let superclass: String = match superclass {
+            Some(superclass) => format!(" < {}", superclass.accept(self)),
+            None => String::new(),
+        };
+
+        let enclosing: bool = self.in_class;
+        self.in_class = true;
+        let methods: Vec<&Stmt> = methods.iter().map(|method| &**method).collect();
+        let body: String = self.block(&methods);
+        self.in_class = enclosing;
+
+        format!(
+            "{}class {}{} {}",
+            self.doc(name),
+            name.lexeme,
+            superclass,
+            body
+        )
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token) -> String {
+        "continue;".to_owned()
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) -> String {
+        match starts_with_map(expression) {
+            true => format!("({});", expression.accept(self)),
+            false => format!("{};", expression.accept(self)),
+        }
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
+    ) -> String {
+        let keyword: &str = if self.in_class { "" } else { "fn " };
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+
+        // Functions nested in a method are declared with `fn` again
+        let enclosing: bool = self.in_class;
+        self.in_class = false;
+        let body: Vec<&Stmt> = body.iter().flatten().map(|s| &**s).collect();
+        let body: String = self.block(&body);
+        self.in_class = enclosing;
+
+        format!(
+            "{}{}{}({}) {}",
+            self.doc(name),
+            keyword,
+            name.lexeme,
+            params.join(", "),
+            body
+        )
+    }
+
+    fn visit_if(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> String {
+        let condition: String = self.operand(condition, ASSIGNMENT);
+        let then_branch: String = match else_branch.is_some() && dangles(then_branch) {
+            true => self.block(&[then_branch]),
+            false => then_branch.accept(self),
+        };
+
+        match else_branch {
+            Some(else_branch) => format!(
+                "if ({}) {} else {}",
+                condition,
+                then_branch,
+                else_branch.accept(self)
+            ),
+            None => format!("if ({}) {}", condition, then_branch),
+        }
+    }
+
+    fn visit_print(&mut self, _keyword: &Token, expression: &Expr, stderr: bool) -> String {
+        let keyword: &str = if stderr { "eprint" } else { "print" };
+        format!("{} {};", keyword, self.operand(expression, ASSIGNMENT))
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> String {
+        match value {
+            Some(value) => format!("return {};", self.operand(value, ASSIGNMENT)),
+            None => "return;".to_owned(),
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> String {
+        match initializer {
+            Some(initializer) => format!(
+                "var {} = {};",
+                name.lexeme,
+                self.operand(initializer, ASSIGNMENT)
+            ),
+            None => format!("var {};", name.lexeme),
+        }
+    }
+
+    // A `while` with an increment can only be written as a `for`
+    fn visit_while(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> String {
+        let forever: bool = matches!(
+            condition,
+            Expr::Literal {
+                value: Literal::Boolean(true),
+                ..
+            }
+        );
+
+        match keyword.token_type {
+            TokenType::For => self.for_loop("", condition, body, increment),
+            _ if increment.is_some() => self.for_loop("", condition, body, increment),
+            TokenType::Loop if forever => format!("loop {}", body.accept(self)),
+            _ => format!(
+                "while ({}) {}",
+                self.operand(condition, ASSIGNMENT),
+                body.accept(self)
+            ),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+use crate::{time::Instant, token::Token};
+
+// Call counts and times of every Lox function, for `--profile`. Natives
+// aren't profiled, their time counts towards the function calling them.
+#[derive(Default)]
+pub struct Profiler {
+    // Keyed by the offset of the function's name, so that two functions with
+    // the same name are told apart
+    functions: HashMap<usize, Entry>,
+    // Calls in progress, innermost last
+    stack: Vec<Frame>,
+}
+
+struct Entry {
+    name: Token,
+    calls: u64,
+    // Including the functions it calls. Recursive calls are only counted
+    // once, by the outermost one.
+    total: Duration,
+    // Excluding the functions it calls
+    own: Duration,
+    // Calls in progress, to tell the outermost recursive call apart
+    active: usize,
+}
+
+struct Frame {
+    key: usize,
+    start: Instant,
+    // Time spent in the functions it called
+    callees: Duration,
+}
+
+impl Profiler {
+    pub fn enter(&mut self, name: &Token) {
+        let entry: &mut Entry = self.functions.entry(name.offset).or_insert_with(|| Entry {
+            name: name.clone(),
+            calls: 0,
+            total: Duration::ZERO,
+            own: Duration::ZERO,
+            active: 0,
+        });
+        entry.calls += 1;
+        entry.active += 1;
+
+        self.stack.push(Frame {
+            key: name.offset,
+            start: Instant::now(),
+            callees: Duration::ZERO,
+        });
+    }
+
+    // Also called when the function fails, so the stack stays balanced
+    pub fn exit(&mut self) {
+        let frame: Frame = self.stack.pop().unwrap();
+        let elapsed: Duration = frame.start.elapsed();
+
+        let entry: &mut Entry = self.functions.get_mut(&frame.key).unwrap();
+        entry.own += elapsed.saturating_sub(frame.callees);
+        entry.active -= 1;
+        if entry.active == 0 {
+            entry.total += elapsed;
+        }
+
+        if let Some(caller) = self.stack.last_mut() {
+            caller.callees += elapsed;
+        }
+    }
+
+    // A table of every function called, the slowest (in total) first
+    pub fn report(&self) -> String {
+        let mut entries: Vec<&Entry> = self.functions.values().collect();
+        entries.sort_by(|a, b| b.total.cmp(&a.total).then(b.calls.cmp(&a.calls)));
+
+        let mut res: String = format!(
+            "{:>10} {:>12} {:>12}  function\n",
+            "calls", "total (ms)", "self (ms)"
+        );
+        for entry in entries {
+            let _ = writeln!(
+                res,
+                "{:>10} {:>12.3} {:>12.3}  {} (line {})",
+                entry.calls,
+                entry.total.as_secs_f64() * 1000.0,
+                entry.own.as_secs_f64() * 1000.0,
+                entry.name.lexeme,
+                entry.name.line
+            );
+        }
+
+        res
+    }
+}
@@ -0,0 +1,98 @@
+// The `rustlox` Python module, with the `python` feature. Built and
+// installed into the current virtualenv with `maturin develop`:
+//
+//     from rustlox import Lox, LoxError
+//
+//     lox = Lox()
+//     lox.run('fn greet(name) { return "Hi " + name; }')
+//     assert lox.eval('greet("Lox")') == "Hi Lox"
+//
+// Results come back as Python values: nil is `None`, numbers are floats,
+// lists are lists and maps are dicts. Other values (functions, instances)
+// raise `TypeError`, and diagnostics raise `LoxError`. `print` writes to
+// the process' stdout, pytest's `capfd` captures it.
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTypeError},
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyList, PyString},
+};
+
+use crate::{
+    diagnostic::Diagnostic, interpreter::stringify, lox::Lox, native::from_key, object::Object,
+};
+
+create_exception!(rustlox, LoxError, PyException);
+
+// A `Lox` can't leave the thread that made it, unless built with `sync`
+#[pyclass(name = "Lox", unsendable)]
+pub struct PyLox {
+    lox: Lox,
+}
+
+#[pymethods]
+impl PyLox {
+    #[new]
+    fn new() -> Self {
+        PyLox { lox: Lox::new() }
+    }
+
+    // Runs a program, returns the value of its top-level `return`
+    fn run(&mut self, py: Python<'_>, source: &str) -> PyResult<Py<PyAny>> {
+        match self.lox.run_source(source) {
+            Ok(value) => to_python(py, &value),
+            Err(diagnostics) => Err(self.error(&diagnostics)),
+        }
+    }
+
+    // Evaluates one expression, seeing the globals of the programs run so far
+    fn eval(&mut self, py: Python<'_>, expr: &str) -> PyResult<Py<PyAny>> {
+        self.run(py, &format!("return {};", expr.trim_end_matches(';')))
+    }
+}
+
+impl PyLox {
+    fn error(&self, diagnostics: &[Diagnostic]) -> PyErr {
+        let rendered: Vec<String> = diagnostics.iter().map(|d| self.lox.render(d)).collect();
+        LoxError::new_err(rendered.join("\n"))
+    }
+}
+
+fn to_python(py: Python<'_>, value: &Object) -> PyResult<Py<PyAny>> {
+    let value: Bound<'_, PyAny> = match value {
+        Object::None => return Ok(py.None()),
+        Object::Boolean(val) => PyBool::new(py, *val).to_owned().into_any(),
+        Object::Number(val) => PyFloat::new(py, *val).into_any(),
+        Object::String(val) => PyString::new(py, val).into_any(),
+        Object::List(list) => {
+            let items: Vec<Py<PyAny>> = list
+                .borrow()
+                .iter()
+                .map(|item| to_python(py, item))
+                .collect::<PyResult<_>>()?;
+            PyList::new(py, items)?.into_any()
+        }
+        Object::Map(map) => {
+            let dict = PyDict::new(py);
+            for (key, val) in map.borrow().iter() {
+                dict.set_item(to_python(py, &from_key(key))?, to_python(py, val)?)?;
+            }
+            dict.into_any()
+        }
+        _ => {
+            return Err(PyTypeError::new_err(format!(
+                "Can't convert '{}' to a Python value.",
+                stringify(value)
+            )))
+        }
+    };
+    Ok(value.unbind())
+}
+
+#[pymodule]
+fn rustlox(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyLox>()?;
+    module.add("LoxError", module.py().get_type::<LoxError>())?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+// What the REPL adds to rustyline's line editor: the line being typed is
+// colored as it's scanned (keywords, strings, numbers), and the bracket
+// matching the one at the cursor lights up along with it.
+
+use std::{borrow::Cow, cell::Cell};
+
+use rustyline::{completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator};
+
+use crate::{
+    scanner::Scanner,
+    token::{Token, TokenType},
+};
+
+const KEYWORD: &str = "1;35";
+const STRING: &str = "32";
+const NUMBER: &str = "36";
+const BRACKET: &str = "1;34";
+
+#[derive(Default)]
+pub struct Helper {
+    // Off with `NO_COLOR`, the line is left as typed
+    pub color: bool,
+    // Whether the cursor's bracket is matched, see `highlight_char`
+    matching: Cell<bool>,
+}
+
+impl Helper {
+    pub fn new(color: bool) -> Self {
+        Helper {
+            color,
+            matching: Cell::new(false),
+        }
+    }
+}
+
+impl rustyline::Helper for Helper {}
+
+impl Completer for Helper {
+    type Candidate = String;
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Validator for Helper {}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.color || line.is_empty() {
+            return Cow::Borrowed(line);
+        }
+
+        // The offsets the colors apply to, in order
+        let mut spans: Vec<(usize, usize, &str)> = vec![];
+        let mut brackets: Vec<Token> = vec![];
+        for result in Scanner::new(line.to_owned(), 0) {
+            match result {
+                Ok(token) => {
+                    if let Some(color) = color_of(&token.token_type) {
+                        spans.push((token.offset, token.offset + token.lexeme.len(), color));
+                    }
+                    if is_bracket(&token.token_type) {
+                        brackets.push(token);
+                    }
+                }
+                // A string still being typed
+                Err(error) if error.message == "Unterminated" => {
+                    spans.push((error.span.start, line.len(), STRING));
+                }
+                Err(_) => (),
+            }
+        }
+
+        if self.matching.get() {
+            for offset in matching_brackets(&brackets, pos) {
+                spans.push((offset, offset + 1, BRACKET));
+            }
+            spans.sort_by_key(|(start, _, _)| *start);
+        }
+
+        let mut res = String::with_capacity(line.len());
+        let mut last: usize = 0;
+        for (start, end, color) in spans {
+            let end: usize = end.min(line.len());
+            if start < last || start >= end {
+                continue;
+            }
+            res.push_str(&line[last..start]);
+            res.push_str(&format!("\x1b[{}m{}\x1b[0m", color, &line[start..end]));
+            last = end;
+        }
+        res.push_str(&line[last..]);
+
+        Cow::Owned(res)
+    }
+
+    // Called when the cursor moves, `forced` once the line is accepted, so
+    // the brackets aren't left lit up
+    fn highlight_char(&self, _line: &str, _pos: usize, forced: bool) -> bool {
+        self.matching.set(!forced);
+        self.color
+    }
+}
+
+fn color_of(token_type: &TokenType) -> Option<&'static str> {
+    let color: &str = match token_type {
+        TokenType::String | TokenType::Bytes => STRING,
+        TokenType::Number => NUMBER,
+        TokenType::And
+        | TokenType::Break
+        | TokenType::Class
+        | TokenType::Continue
+        | TokenType::Else
+        | TokenType::Eprint
+        | TokenType::False
+        | TokenType::Fn
+        | TokenType::For
+        | TokenType::If
+        | TokenType::Loop
+        | TokenType::Nil
+        | TokenType::Or
+        | TokenType::Print
+        | TokenType::Return
+        | TokenType::Super
+        | TokenType::This
+        | TokenType::True
+        | TokenType::Var
+        | TokenType::While => KEYWORD,
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+fn is_bracket(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::LeftParen
+            | TokenType::RightParen
+            | TokenType::LeftBrace
+            | TokenType::RightBrace
+            | TokenType::LeftBracket
+            | TokenType::RightBracket
+    )
+}
+
+// The offsets of the bracket under (or else right before) the cursor and of
+// the one it matches, if any. Brackets in strings and comments don't count,
+// they aren't tokens.
+fn matching_brackets(brackets: &[Token], pos: usize) -> Vec<usize> {
+    let at: Option<usize> = brackets
+        .iter()
+        .position(|token| token.offset == pos)
+        .or_else(|| brackets.iter().position(|token| token.offset + 1 == pos));
+
+    // Pairs every closing bracket with the last opening one still open
+    let mut open: Vec<usize> = vec![];
+    let mut pairs: Vec<(usize, usize)> = vec![];
+    for (i, token) in brackets.iter().enumerate() {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => open.push(i),
+            _ => {
+                if let Some(j) = open.pop() {
+                    pairs.push((j, i));
+                }
+            }
+        }
+    }
+
+    let Some(at) = at else {
+        return vec![];
+    };
+    pairs
+        .into_iter()
+        .find(|(j, i)| *j == at || *i == at)
+        .map(|(j, i)| vec![brackets[j].offset, brackets[i].offset])
+        .unwrap_or_default()
+}
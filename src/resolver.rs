@@ -1,9 +1,20 @@
-use crate::{expr::Expr, interpreter::Interpreter, lox::Lox, stmt::Stmt, token::Token};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use crate::{
+    diagnostic::Diagnostic,
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    symbol::Symbol,
+    sync::Rc,
+    token::{Literal, Span, Token},
+    util,
+    visitor::{ExprVisitor, StmtVisitor},
+};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 enum FunctionType {
     None,
+    // Top-level code of a script file, which may `return` an exit code
+    Script,
     Function,
     Initializer,
     Method,
@@ -16,218 +27,130 @@ enum ClassType {
     Subclass,
 }
 
+#[derive(Debug, Clone)]
+enum LoopType {
+    None,
+    Loop,
+}
+
+pub const UNREACHABLE: &str = "Code after this is unreachable.";
+
+// A variable declared in a scope
+struct Local {
+    // Index into the scope's environment at runtime
+    slot: usize,
+    // Whether we have finished resolving the variable
+    defined: bool,
+    // Whether it's read anywhere, warned about otherwise. Parameters, `this`
+    // and `super` start as used.
+    used: bool,
+    // Where it's declared, `None` for `this` and `super`
+    token: Option<Token>,
+}
+
 // #[derive(Debug, Default, Clone)]
 pub struct Resolver {
-    interpreter: Rc<RefCell<Interpreter>>,
-    // The value of scopes (bool) indicates whether we have finished resolving the key
-    scopes: Vec<HashMap<String, bool>>,
+    // The (depth, slot) of every local variable resolved, see
+    // `Interpreter::locals`
+    pub locals: HashMap<ExprId, (usize, usize)>,
+    // Where each local variable read or assigned is declared, keyed by the
+    // offset of the name's token (see `Token::offset`), for go-to-definition
+    pub definitions: HashMap<usize, Token>,
+    // The name of every local declared, in order, for `rustlox minify`
+    pub declarations: Vec<Token>,
+    // With `--strict`, every global name there is: those already defined
+    // (natives, the prelude, earlier REPL lines) and the script's own. Using
+    // any other name that isn't a local is an error.
+    globals: Option<HashSet<Symbol>>,
+    scopes: Vec<HashMap<Symbol, Local>>,
     current_function: FunctionType,
     current_class: ClassType,
+    current_loop: LoopType,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl Resolver {
-    pub fn new(interpreter: Rc<RefCell<Interpreter>>) -> Self {
+    pub fn new(is_script: bool) -> Self {
         Resolver {
-            interpreter,
+            locals: HashMap::new(),
+            definitions: HashMap::new(),
+            declarations: vec![],
+            globals: None,
             scopes: vec![],
-            current_function: FunctionType::None,
+            current_function: if is_script {
+                FunctionType::Script
+            } else {
+                FunctionType::None
+            },
             current_class: ClassType::None,
+            current_loop: LoopType::None,
+            diagnostics: vec![],
         }
     }
 
-    pub fn resolve_stmt_list(&mut self, statements: &Vec<Option<Box<Stmt>>>) {
-        for stmt in statements.into_iter().flatten() {
-            self.resolve_stmt(stmt);
-        }
+    // Turns on strict mode, given the globals defined before the code runs
+    pub fn strict(&mut self, globals: impl IntoIterator<Item = Symbol>) {
+        self.globals = Some(globals.into_iter().collect());
     }
 
-    fn resolve_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Block { statements } => {
-                // Nesting behaves like stack
-                // When a block is found, add the scope to the stack
-                self.begin_scope();
-                // Then resolve statements inside the scope
-                self.resolve_stmt_list(statements);
-                // Exiting the scope => popping the stack
-                // The immediate outer scope is now the head
-                self.end_scope();
-            }
-            Stmt::Class {
-                name,
-                superclass,
-                methods,
-            } => {
-                let enclosing_class: ClassType = self.current_class.clone();
-                self.current_class = ClassType::Class;
-
-                self.declare(name.clone());
-                self.define(name.clone());
-
-                if let Some(Expr::Variable {
-                    name: superclass_name,
-                }) = superclass
+    pub fn resolve_stmt_list(&mut self, statements: &[Option<Box<Stmt>>]) {
+        let statements: Vec<&Stmt> = statements.iter().flatten().map(|stmt| &**stmt).collect();
+
+        // Top-level declarations are global, and a function may use one
+        // declared further down
+        if let (true, Some(globals)) = (self.scopes.is_empty(), self.globals.as_mut()) {
+            for stmt in &statements {
+                if let Stmt::Var { name, .. }
+                | Stmt::Function { name, .. }
+                | Stmt::Class { name, .. } = stmt
                 {
-                    if name.lexeme.eq(&superclass_name.lexeme) {
-                        Lox::parse_error(superclass_name, "A class cannot inherit from itself.");
-                    }
+                    globals.insert(name.symbol);
                 }
+            }
+        }
 
-                if !superclass.is_none() {
-                    self.current_class = ClassType::Subclass;
-                    self.resolve_expr(&superclass.clone().unwrap());
-
-                    self.begin_scope();
-                    self.scopes
-                        .last_mut()
-                        .unwrap()
-                        .insert("super".to_owned(), true);
-                }
+        // Dead code is still resolved, it's only a warning
+        if let Some(i) = statements.iter().position(|stmt| stmt.exit().is_some()) {
+            if i + 1 < statements.len() {
+                let keyword: &Token = statements[i].exit().unwrap();
+                self.diagnostics
+                    .push(Diagnostic::warning(keyword, UNREACHABLE));
+            }
+        }
 
-                self.begin_scope();
-                self.scopes
-                    .last_mut()
-                    .unwrap()
-                    .insert("this".to_owned(), true);
-
-                for method in methods {
-                    match *method.to_owned() {
-                        Stmt::Function { params, body, .. } => {
-                            let declaration: FunctionType;
-                            if name.lexeme.eq("init") {
-                                declaration = FunctionType::Initializer;
-                            } else {
-                                declaration = FunctionType::Method
-                            }
-
-                            self.resolve_function(&params, &body, declaration)
-                        }
-                        _ => unreachable!(),
-                    }
-                }
+        for stmt in statements {
+            self.resolve_stmt(stmt);
+        }
+    }
 
-                if !superclass.is_none() {
-                    self.end_scope();
-                }
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        stmt.accept(self)
+    }
 
-                self.end_scope();
+    pub fn resolve_expr(&mut self, expr: &Expr) {
+        expr.accept(self)
+    }
 
-                self.current_class = enclosing_class;
-            }
-            Stmt::Var { name, initializer } => {
-                self.declare(name.clone());
-                if let Some(init) = initializer {
-                    self.resolve_expr(&init);
-                }
-                self.define(name.clone());
-            }
-            Stmt::Function { name, params, body } => {
-                self.declare(name.clone());
-                self.define(name.clone());
-                self.resolve_function(params, body, FunctionType::Function);
-            }
-            Stmt::Expression { expression } => self.resolve_expr(expression),
-            Stmt::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => {
-                self.resolve_expr(condition);
-                self.resolve_stmt(then_branch);
-
-                if let Some(else_stmt) = else_branch.as_ref() {
-                    self.resolve_stmt(else_stmt);
-                }
-            }
-            Stmt::Print { expression } => self.resolve_expr(expression),
-            Stmt::Return { value, keyword } => {
-                match self.current_function {
-                    FunctionType::None => {
-                        Lox::parse_error(keyword, "Can't return from top-level code.")
-                    }
-                    _ => (),
-                };
-
-                if let Some(expr) = value {
-                    match self.current_function {
-                        FunctionType::Initializer => {
-                            Lox::parse_error(keyword, "Can't return a value from an initializer")
-                        }
-                        _ => self.resolve_expr(expr),
-                    }
-                }
-            }
-            Stmt::While { condition, body } => {
-                self.resolve_expr(condition);
-                self.resolve_stmt(body);
-            }
-        };
+    fn error(&mut self, token: &Token, message: &str) {
+        self.diagnostics.push(Diagnostic::error_at(token, message));
     }
 
-    fn resolve_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Variable { name } => {
-                if !self.scopes.is_empty() {
-                    if let Some(resolved) = self.scopes.last().unwrap().get(&name.lexeme) {
-                        if !resolved {
-                            Lox::parse_error(
-                                name,
-                                "Can't read local variable in its own initializer.",
-                            );
-                        }
-                    }
-                }
-                self.resolve_local(expr, name.clone());
-            }
-            Expr::Assign { name, value } => {
-                // Recursively resolve the value of this assignment since it can
-                // contain references to other variables (e.g. `var x = (a == b)`)
-                self.resolve_expr(value);
-                self.resolve_local(expr, name.clone());
-            }
-            Expr::Binary { left, right, .. } => {
-                self.resolve_expr(left);
-                self.resolve_expr(right);
-            }
-            Expr::Call {
-                callee, arguments, ..
-            } => {
-                self.resolve_expr(callee);
+    fn warning(&mut self, token: &Token, message: &str) {
+        self.diagnostics.push(Diagnostic::warning(token, message));
+    }
 
-                for arg in arguments.iter() {
-                    self.resolve_expr(arg);
-                }
+    // Resolves code run in the middle of others, e.g. by the debugger, where
+    // `scopes` are the names defined in each local environment, outermost first
+    pub fn with_scopes(scopes: Vec<Vec<Symbol>>) -> Self {
+        let mut resolver = Resolver::new(false);
+        for names in scopes {
+            resolver.begin_scope();
+            for name in names {
+                resolver.declare_builtin(name);
             }
-            Expr::Get { object, .. } => self.resolve_expr(object),
-            Expr::Set { object, value, .. } => {
-                self.resolve_expr(value);
-                self.resolve_expr(object);
-            }
-            Expr::Super { keyword, .. } => {
-                if matches!(self.current_class, ClassType::None) {
-                    Lox::parse_error(keyword, "Can't use 'super' outside of a class.");
-                } else if !matches!(self.current_class, ClassType::Subclass) {
-                    Lox::parse_error(keyword, "Can't use 'super' in a class with no superclass.");
-                }
+        }
 
-                self.resolve_local(&expr, keyword.clone())
-            }
-            Expr::This { keyword } => match self.current_class {
-                ClassType::None => {
-                    Lox::parse_error(keyword, "Can't use 'this' outside of a class.")
-                }
-                _ => self.resolve_local(expr, keyword.clone()),
-            },
-            Expr::Grouping { expression } => self.resolve_expr(expression),
-            Expr::Literal { .. } => (),
-            Expr::Logical { left, right, .. } => {
-                self.resolve_expr(left);
-                self.resolve_expr(right);
-            }
-            Expr::Unary { right, .. } => {
-                self.resolve_expr(right);
-            }
-        };
+        resolver
     }
 
     fn begin_scope(&mut self) {
@@ -235,58 +158,168 @@ impl Resolver {
     }
 
     fn end_scope(&mut self) {
-        self.scopes.pop();
+        let scope: HashMap<Symbol, Local> = self.scopes.pop().unwrap();
+
+        // In declaration order, so the warnings come out in a stable order.
+        // Names starting with `_` are meant to be unused.
+        let mut unused: Vec<(usize, Token)> = scope
+            .into_values()
+            .filter(|local| !local.used)
+            .filter_map(|local| local.token.map(|token| (local.slot, token)))
+            .filter(|(_, token)| !token.lexeme.starts_with('_'))
+            .collect();
+        unused.sort_by_key(|(slot, _)| *slot);
+
+        for (_, token) in unused {
+            self.warning(&token, "Local variable is never used.");
+        }
     }
 
     fn declare(&mut self, name: Token) {
         // Put the variable name into the current scope (top of the stack)
-        if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(&name.lexeme) {
-                Lox::parse_error(&name, "Already a variable with this name in this scope.");
-            }
+        if self.scopes.is_empty() {
+            return;
+        }
+
+        if self.scopes.last().unwrap().contains_key(&name.symbol) {
+            self.error(&name, "Already a variable with this name in this scope.");
+            return;
+        }
+
+        let outer: Option<usize> = self.scopes[..self.scopes.len() - 1]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.symbol))
+            .and_then(|local| local.token.as_ref())
+            .map(|token| token.line);
+        if let Some(line) = outer {
+            let message: String = format!("Shadows the variable declared at line {}.", line);
+            self.warning(&name, &message);
+        }
+
+        // This is just a declaration, so it's not `defined`
+        // since we haven't finished resolving `name`.
+        // The environment defines its values in this same order.
+        self.declarations.push(name.clone());
+        let scope = self.scopes.last_mut().unwrap();
+        let slot: usize = scope.len();
+        scope.insert(
+            name.symbol,
+            Local {
+                slot,
+                defined: false,
+                used: false,
+                token: Some(name),
+            },
+        );
+    }
 
-            // This is just a declaration, so the value is `false`
-            // since we haven't finished resolving `name`
-            scope.insert(name.lexeme, false);
+    // Marks the innermost variable called `name` as read
+    fn mark_used(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name.symbol))
+        {
+            local.used = true;
         }
     }
 
     fn define(&mut self, name: Token) {
         // Mark the declared varible as resolved
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme, true);
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.symbol))
+        {
+            local.defined = true;
         }
     }
 
-    fn resolve_local(&self, expr: &Expr, name: Token) {
+    // `this` and `super`, which the interpreter defines in scopes of their own
+    fn declare_builtin(&mut self, name: Symbol) {
+        let scope = self.scopes.last_mut().unwrap();
+        let slot: usize = scope.len();
+        scope.insert(
+            name,
+            Local {
+                slot,
+                defined: true,
+                used: true,
+                token: None,
+            },
+        );
+    }
+
+    fn resolve_local(&mut self, id: ExprId, name: &Token) {
         // Starting from the innermost scope (top of the stack), we check for `name`.
         // Then resolve it under the correct scope.
         // If we don't find it in `self.scopes`, we assume that it's global or undefined.
         for i in (0..self.scopes.len()).rev() {
-            if self.scopes.get(i).unwrap().contains_key(&name.lexeme) {
-                self.interpreter
-                    .borrow_mut()
-                    .resolve(expr.clone(), self.scopes.len() - 1 - i);
+            if let Some(local) = self.scopes.get(i).unwrap().get(&name.symbol) {
+                self.locals
+                    .insert(id, (self.scopes.len() - 1 - i, local.slot));
+                if let Some(token) = &local.token {
+                    self.definitions.insert(name.offset, token.clone());
+                }
+                return;
             }
         }
     }
 
+    // In strict mode, fails if `name` is neither a local nor a known global
+    fn check_defined(&mut self, name: &Token) {
+        let Some(globals) = &self.globals else {
+            return;
+        };
+        if globals.contains(&name.symbol)
+            || self
+                .scopes
+                .iter()
+                .any(|scope| scope.contains_key(&name.symbol))
+        {
+            return;
+        }
+
+        let names: Vec<Rc<str>> = globals
+            .iter()
+            .chain(self.scopes.iter().flat_map(|scope| scope.keys()))
+            .map(Symbol::as_str)
+            .collect();
+        let message: String = match util::closest(&name.lexeme, names.iter().map(|name| &**name)) {
+            Some(closest) => format!(
+                "Undefined variable '{}'. Did you mean '{}'?",
+                name.lexeme, closest
+            ),
+            None => format!("Undefined variable '{}'.", name.lexeme),
+        };
+        self.error(name, &message);
+    }
+
     fn resolve_function(
         &mut self,
-        params: &Vec<Token>,
-        body: &Vec<Option<Box<Stmt>>>,
+        params: &[Token],
+        body: &[Option<Box<Stmt>>],
         func_type: FunctionType,
     ) {
         let enclosing_func: FunctionType = self.current_function.clone();
         self.current_function = func_type;
 
+        // A function body starts outside of any loop, even if the function
+        // itself is declared inside one
+        let enclosing_loop: LoopType = self.current_loop.clone();
+        self.current_loop = LoopType::None;
+
         // Activate the function's scope
         self.begin_scope();
 
         // Resolve all arguments
+        // Parameters don't have to be used, e.g. in callbacks
         for param in params {
             self.declare(param.clone());
             self.define(param.clone());
+            self.mark_used(param);
         }
 
         // Resolve the body block
@@ -296,5 +329,266 @@ impl Resolver {
         self.end_scope();
 
         self.current_function = enclosing_func;
+        self.current_loop = enclosing_loop;
+    }
+}
+
+impl StmtVisitor<()> for Resolver {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) {
+        // Nesting behaves like stack
+        // When a block is found, add the scope to the stack
+        self.begin_scope();
+        // Then resolve statements inside the scope
+        self.resolve_stmt_list(statements);
+        // Exiting the scope => popping the stack
+        // The immediate outer scope is now the head
+        self.end_scope();
+    }
+
+    fn visit_break(&mut self, keyword: &Token) {
+        if let LoopType::None = self.current_loop {
+            self.error(keyword, "Can't use 'break' outside of a loop.")
+        }
+    }
+
+    fn visit_class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Box<Stmt>]) {
+        let enclosing_class: ClassType = self.current_class.clone();
+        self.current_class = ClassType::Class;
+
+        self.declare(name.clone());
+        self.define(name.clone());
+
+        if let Some(Expr::Variable {
+            name: superclass_name,
+            ..
+        }) = superclass
+        {
+            if name.lexeme.eq(&superclass_name.lexeme) {
+                self.error(superclass_name, "A class cannot inherit from itself.");
+            }
+        }
+
+        if let Some(superclass) = superclass {
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.declare_builtin(Symbol::SUPER);
+        }
+
+        self.begin_scope();
+        self.declare_builtin(Symbol::THIS);
+
+        for method in methods {
+            match &**method {
+                Stmt::Function { params, body, .. } => {
+                    let declaration: FunctionType = if name.lexeme.eq("init") {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+
+                    self.resolve_function(params, body, declaration)
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.end_scope();
+
+        self.current_class = enclosing_class;
+    }
+
+    fn visit_continue(&mut self, keyword: &Token) {
+        if let LoopType::None = self.current_loop {
+            self.error(keyword, "Can't use 'continue' outside of a loop.")
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) {
+        self.resolve_expr(expression);
+    }
+
+    fn visit_function(&mut self, name: &Token, params: &[Token], body: &[Option<Box<Stmt>>]) {
+        self.declare(name.clone());
+        self.define(name.clone());
+        self.resolve_function(params, body, FunctionType::Function);
+    }
+
+    fn visit_if(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) {
+        self.resolve_expr(condition);
+        self.resolve_stmt(then_branch);
+
+        if let Some(else_stmt) = else_branch {
+            self.resolve_stmt(else_stmt);
+        }
+    }
+
+    fn visit_print(&mut self, _keyword: &Token, expression: &Expr, _stderr: bool) {
+        self.resolve_expr(expression);
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expr>) {
+        if let FunctionType::None = self.current_function {
+            self.error(keyword, "Can't return from top-level code.")
+        }
+
+        if let Some(expr) = value {
+            match self.current_function {
+                FunctionType::Initializer => self.diagnostics.push(Diagnostic::error_in(
+                    expr.span(),
+                    "Can't return a value from an initializer",
+                )),
+                _ => self.resolve_expr(expr),
+            }
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) {
+        self.declare(name.clone());
+        if let Some(init) = initializer {
+            self.resolve_expr(init);
+        }
+        self.define(name.clone());
+    }
+
+    fn visit_while(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) {
+        let enclosing_loop: LoopType = self.current_loop.clone();
+        self.current_loop = LoopType::Loop;
+
+        self.resolve_expr(condition);
+        self.resolve_stmt(body);
+
+        if let Some(increment) = increment {
+            self.resolve_expr(increment);
+        }
+
+        self.current_loop = enclosing_loop;
+    }
+}
+
+impl ExprVisitor<()> for Resolver {
+    fn visit_assign(&mut self, id: ExprId, name: &Token, value: &Expr) {
+        if let Expr::Variable { name: source, .. } = value {
+            if source.symbol == name.symbol {
+                self.diagnostics.push(Diagnostic::warning_in(
+                    Span::of(name).to(value.span()),
+                    "Assigning a variable to itself has no effect.",
+                ));
+            }
+        }
+
+        // Recursively resolve the value of this assignment since it can
+        // contain references to other variables (e.g. `var x = (a == b)`)
+        self.resolve_expr(value);
+        self.check_defined(name);
+        self.resolve_local(id, name);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.resolve_expr(left);
+        self.resolve_expr(right);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Box<Expr>]) {
+        self.resolve_expr(callee);
+
+        for arg in arguments.iter() {
+            self.resolve_expr(arg);
+        }
+    }
+
+    fn visit_get(&mut self, _id: ExprId, object: &Expr, _name: &Token) {
+        self.resolve_expr(object);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        self.resolve_expr(expression);
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) {
+        self.resolve_expr(object);
+        self.resolve_expr(index);
+    }
+
+    fn visit_index_set(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) {
+        self.resolve_expr(value);
+        self.resolve_expr(object);
+        self.resolve_expr(index);
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Box<Expr>]) {
+        for element in elements.iter() {
+            self.resolve_expr(element);
+        }
+    }
+
+    fn visit_literal(&mut self, _value: &Literal) {}
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Box<Expr>, Box<Expr>)]) {
+        for (key, value) in entries.iter() {
+            self.resolve_expr(key);
+            self.resolve_expr(value);
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.resolve_expr(left);
+        self.resolve_expr(right);
+    }
+
+    fn visit_set(&mut self, object: &Expr, _name: &Token, value: &Expr) {
+        self.resolve_expr(value);
+        self.resolve_expr(object);
+    }
+
+    fn visit_super(&mut self, id: ExprId, keyword: &Token, _method: &Token) {
+        if matches!(self.current_class, ClassType::None) {
+            self.error(keyword, "Can't use 'super' outside of a class.");
+        } else if !matches!(self.current_class, ClassType::Subclass) {
+            self.error(keyword, "Can't use 'super' in a class with no superclass.");
+        }
+
+        self.resolve_local(id, keyword)
+    }
+
+    fn visit_this(&mut self, id: ExprId, keyword: &Token) {
+        match self.current_class {
+            ClassType::None => self.error(keyword, "Can't use 'this' outside of a class."),
+            _ => self.resolve_local(id, keyword),
+        }
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Expr) {
+        self.resolve_expr(right);
+    }
+
+    fn visit_variable(&mut self, id: ExprId, name: &Token) {
+        if !self.scopes.is_empty() {
+            if let Some(local) = self.scopes.last().unwrap().get(&name.symbol) {
+                if !local.defined {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+            }
+        }
+        self.check_defined(name);
+        self.resolve_local(id, name);
+        self.mark_used(name);
     }
 }
@@ -1,69 +1,198 @@
+use std::{collections::VecDeque, ops::Range};
+
 use crate::{
-    lox::Lox,
+    diagnostic::Diagnostic,
+    sync::Rc,
     token::{Literal, Token, TokenType},
 };
 
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    // See `Token::offset`
+    pub span: Range<usize>,
+}
+
+impl From<ScanError> for Diagnostic {
+    fn from(error: ScanError) -> Self {
+        Diagnostic::error(error.line, error.column, error.span, &error.message)
+    }
+}
+
+// Produces tokens lazily, as an iterator, so that the whole source never has
+// to be held as tokens at once. Ends with an `Eof` token, even after errors.
 pub struct Scanner {
     source: String,
-    tokens: Vec<Token>,
+    // Scanned but not yet taken. One step can produce a token along with
+    // errors, e.g. a byte literal with a bad escape.
+    pending: VecDeque<Result<Token, ScanError>>,
     start: usize,
     current: usize,
     line: usize,
+    // Where the current line starts, to compute columns
+    line_start: usize,
+    // Line and column of the token (or error) starting at `start`
+    start_line: usize,
+    start_column: usize,
+    // Offset of this source's first character, see `Token::offset`
+    base: usize,
     in_comment_block: bool,
+    // The lines of the `///` comments since the last token, given to the next
+    // one
+    doc: Option<String>,
+    // Whether the `Eof` token was produced
+    finished: bool,
+}
+
+impl Iterator for Scanner {
+    type Item = Result<Token, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.finished {
+            self.scan_step();
+        }
+
+        self.pending.pop_front()
+    }
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Self {
+    pub fn new(source: String, base: usize) -> Self {
         Self {
             source,
-            tokens: vec![],
+            pending: VecDeque::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            base,
             in_comment_block: false,
+            doc: None,
+            finished: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Option<&Vec<Token>> {
-        while !self.is_at_end() {
-            self.start = self.current;
+    // Scans the whole source at once, for callers that want every token up
+    // front rather than iterating
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<ScanError>) {
+        let mut tokens: Vec<Token> = vec![];
+        let mut errors: Vec<ScanError> = vec![];
 
-            if self.in_comment_block {
-                // Consume block (possibly multi-line) comment
-                while !self.is_at_end() {
-                    let c = self.advance();
-
-                    if c == '\n' {
-                        self.line += 1;
-                    } else if c == '*' && self.peek() == '/' {
-                        self.in_comment_block = false;
-                        break;
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        (tokens, errors)
+    }
+
+    // Whether `source` stops inside a string, a block comment or unclosed
+    // brackets, so the REPL reads more lines before running it. Unbalanced
+    // closing brackets are left to the parser to report.
+    pub fn is_incomplete(source: &str) -> bool {
+        let mut depth: isize = 0;
+
+        for result in Scanner::new(source.to_owned(), 0) {
+            match result {
+                Ok(token) => match token.token_type {
+                    TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => {
+                        depth += 1
                     }
-                }
+                    TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                        depth -= 1
+                    }
+                    _ => (),
+                },
+                Err(error) if error.message == "Unterminated" => return true,
+                Err(error) if error.message.starts_with("Block comment") => return true,
+                Err(_) => (),
+            }
+        }
 
-                if self.in_comment_block {
-                    // If after consuming everything above, we haven't found the closing "*/"
-                    // Then we throw an error.
-                    Lox::error(self.line, "Block comment never closed.");
-                    return None;
-                } else {
-                    // The above iter stopped at the closing '*'.
-                    // So, we consume the closing '\'.
-                    self.advance();
+        depth > 0
+    }
+
+    // Scans a token, a comment or some whitespace, or ends the source with
+    // `Eof`
+    fn scan_step(&mut self) {
+        if self.is_at_end() {
+            self.add_eof();
+            return;
+        }
+
+        // A block comment starts at its '/', scanned in the last step
+        if !self.in_comment_block {
+            self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column();
+        }
+
+        if self.in_comment_block {
+            // Consume block (possibly multi-line) comment
+            while !self.is_at_end() {
+                let c = self.advance();
+
+                if c == '\n' {
+                    self.newline();
+                } else if c == '*' && self.peek() == '/' {
+                    self.in_comment_block = false;
+                    break;
                 }
             }
 
-            self.scan_single_token();
+            if self.in_comment_block {
+                // If after consuming everything above, we haven't found the closing "*/"
+                // Then we throw an error. The tokens before it are still parsed.
+                self.error("Block comment never closed.");
+            } else {
+                // The above iter stopped at the closing '*'.
+                // So, we consume the closing '\'.
+                self.advance();
+            }
+            return;
         }
 
-        self.tokens.push(Token::new(
+        self.scan_single_token();
+    }
+
+    fn add_eof(&mut self) {
+        self.pending.push_back(Ok(Token::new(
             TokenType::Eof,
             "".to_string(),
             Literal::None,
             self.line,
-        ));
+            self.column(),
+            self.base + self.current,
+        )));
+        self.finished = true;
+    }
+
+    // Points at the token being scanned, from its start to the current character
+    fn error(&mut self, message: &str) {
+        self.pending.push_back(Err(ScanError {
+            message: message.to_owned(),
+            line: self.start_line,
+            column: self.start_column,
+            span: self.base + self.start..self.base + self.current,
+        }));
+    }
+
+    // Column of the current character, starting at 1. Counted in characters,
+    // not bytes, so it matches what editors show.
+    fn column(&self) -> usize {
+        self.source[self.line_start..self.current].chars().count() + 1
+    }
 
-        Some(&self.tokens)
+    // Called after consuming a '\n'
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     fn is_at_end(&self) -> bool {
@@ -76,12 +205,16 @@ impl Scanner {
 
     fn add_token(&mut self, token_type: TokenType, literal: Literal) {
         let lexeme: &str = &self.source[self.start..self.current];
-        self.tokens.push(Token::new(
+        let mut token: Token = Token::new(
             token_type,
             lexeme.to_string(),
             literal,
-            self.line,
-        ))
+            self.start_line,
+            self.start_column,
+            self.base + self.start,
+        );
+        token.doc = self.doc.take().map(Rc::from);
+        self.pending.push_back(Ok(token))
     }
 
     fn scan_single_token(&mut self) {
@@ -92,6 +225,9 @@ impl Scanner {
             ')' => self.add_token_no_lit(TokenType::RightParen),
             '{' => self.add_token_no_lit(TokenType::LeftBrace),
             '}' => self.add_token_no_lit(TokenType::RightBrace),
+            '[' => self.add_token_no_lit(TokenType::LeftBracket),
+            ']' => self.add_token_no_lit(TokenType::RightBracket),
+            ':' => self.add_token_no_lit(TokenType::Colon),
             ',' => self.add_token_no_lit(TokenType::Comma),
             '.' => self.add_token_no_lit(TokenType::Dot),
             '-' => self.add_token_no_lit(TokenType::Minus),
@@ -130,19 +266,17 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_doc();
                 } else {
                     self.add_token_no_lit(TokenType::Slash);
                 }
             }
             ' ' | '\r' | '\t' => (), // Do nothing
-            '\n' => {
-                self.line += 1;
-            }
+            '\n' => self.newline(),
             '"' => self.add_string(),
-            'o' => {
-                if self.matches('r') {
-                    self.add_token_no_lit(TokenType::Or)
-                }
+            'b' if self.peek() == '"' => {
+                self.advance();
+                self.add_bytes();
             }
             _ => {
                 if next_char.is_ascii_digit() {
@@ -150,58 +284,70 @@ impl Scanner {
                 } else if Scanner::is_alpha(next_char) {
                     self.add_identifier();
                 } else {
-                    Lox::error(self.line, "Unexpected character.");
+                    self.error("Unexpected character.");
                 }
             }
         };
     }
 
+    // `start` and `current` are byte indices, always on a character boundary
     fn advance(&mut self) -> char {
-        let next_char: char = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let next_char: char = self.source[self.current..].chars().next().unwrap();
+        self.current += next_char.len_utf8();
         next_char
     }
 
     fn matches(&mut self, expected: char) -> bool {
-        if self.current >= self.source.len() {
-            return false;
-        }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.current += expected.len_utf8();
         true
     }
 
     fn peek(&self) -> char {
-        match !self.is_at_end() {
-            true => self.source.chars().nth(self.current).unwrap(),
-            false => '\0',
-        }
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        match self.current + 1 < self.source.len() {
-            true => self.source.chars().nth(self.current + 1).unwrap(),
-            false => '\0',
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn peek_prev(&self) -> char {
-        self.source.chars().nth(self.current - 1).unwrap()
+        self.source[..self.current].chars().next_back().unwrap()
+    }
+
+    // Keeps the comment just scanned if it's a `///` doc comment (but not
+    // `////...`, a separator), without the slashes and the space after them
+    fn add_doc(&mut self) {
+        let comment: &str = &self.source[self.start..self.current];
+        let Some(line) = comment.strip_prefix("///") else {
+            return;
+        };
+        if line.starts_with('/') {
+            return;
+        }
+
+        let line: &str = line.strip_prefix(' ').unwrap_or(line).trim_end();
+        match &mut self.doc {
+            Some(doc) => {
+                doc.push('\n');
+                doc.push_str(line);
+            }
+            None => self.doc = Some(line.to_owned()),
+        }
     }
 
     fn add_string(&mut self) {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == '\n' {
+                self.newline();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            Lox::error(self.line, "Unterminated");
+            self.error("Unterminated");
             return;
         }
 
@@ -209,7 +355,60 @@ impl Scanner {
 
         // Trim the quotes, get the string itself
         let lit_val: &str = &self.source[(self.start + 1)..(self.current - 1)];
-        self.add_token(TokenType::String, Literal::String(lit_val.to_string()));
+        self.add_token(TokenType::String, Literal::String(lit_val.into()));
+    }
+
+    // `b"..."`, ASCII only. Supports the escapes \xNN, \n, \r, \t, \0, \\ and \".
+    fn add_bytes(&mut self) {
+        let mut bytes: Vec<u8> = vec![];
+
+        // Errors are reported without stopping, so the rest of the literal
+        // isn't scanned as code
+        while self.peek() != '"' && !self.is_at_end() {
+            let c: char = self.advance();
+
+            if c == '\n' {
+                self.newline();
+            } else if !c.is_ascii() {
+                self.error("Byte literals can only contain ASCII characters.");
+            }
+
+            if c != '\\' {
+                bytes.push(c as u8);
+                continue;
+            }
+
+            let byte: Option<u8> = match self.peek() {
+                'n' => Some(b'\n'),
+                'r' => Some(b'\r'),
+                't' => Some(b'\t'),
+                '0' => Some(0),
+                '\\' => Some(b'\\'),
+                '"' => Some(b'"'),
+                'x' if self.peek_next().is_ascii_hexdigit() => {
+                    self.advance();
+                    let digits: String = [self.advance(), self.peek()].iter().collect();
+                    u8::from_str_radix(&digits, 16).ok()
+                }
+                _ => None,
+            };
+
+            match byte {
+                Some(byte) => {
+                    self.advance();
+                    bytes.push(byte);
+                }
+                None => self.error("Invalid escape sequence in byte literal."),
+            }
+        }
+
+        if self.is_at_end() {
+            self.error("Unterminated");
+            return;
+        }
+
+        self.advance(); // Move cursor to the closing "
+        self.add_token(TokenType::Bytes, Literal::Bytes(bytes));
     }
 
     fn add_number(&mut self) {
@@ -250,15 +449,19 @@ impl Scanner {
         c.is_ascii_digit() || Scanner::is_alpha(c)
     }
 
-    fn text2token(text: &str) -> TokenType {
+    pub fn text2token(text: &str) -> TokenType {
         match text {
             "and" => TokenType::And,
+            "break" => TokenType::Break,
             "class" => TokenType::Class,
+            "continue" => TokenType::Continue,
             "else" => TokenType::Else,
+            "eprint" => TokenType::Eprint,
             "false" => TokenType::False,
             "for" => TokenType::For,
             "fn" => TokenType::Fn,
             "if" => TokenType::If,
+            "loop" => TokenType::Loop,
             "nil" => TokenType::Nil,
             "or" => TokenType::Or,
             "print" => TokenType::Print,
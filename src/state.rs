@@ -0,0 +1,214 @@
+// `saveState(path)` and `loadState(path)` (or `--save-state` and
+// `--load-state`): the globals holding plain data, written to a JSON file
+// and read back into another session.
+//
+// What's saved: nil, booleans, finite numbers, strings, bytes, and lists,
+// maps and instances made of those. Functions, classes and natives are code,
+// they're left out (the script or `~/.loxrc` defines them again). A global
+// whose value holds code anywhere inside, a non-finite number, a host's
+// Rust value (see `foreign`), or itself (a list containing itself, ...)
+// isn't saved either, `saveState` returns its name. Values shared by two globals come back as two copies.
+//
+// An instance is restored with the class of the same name, which has to be
+// defined by then.
+
+use std::{collections::BTreeMap, fs};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    class::LoxInstance,
+    interpreter::Interpreter,
+    native::{self, from_key, to_key},
+    object::Object,
+    symbol::Symbol,
+    sync::Rc,
+    token::Literal,
+};
+
+// Bumped whenever the format changes
+const VERSION: u32 = 1;
+
+// Defined anew by every interpreter (`Math`, see `Interpreter::new`) or
+// every script (see `Lox::define_script_globals`)
+const SKIPPED: [&str; 3] = ["Math", "scriptPath", "args"];
+
+#[derive(Serialize, Deserialize)]
+struct State {
+    version: u32,
+    globals: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Instance {
+        class: String,
+        fields: BTreeMap<String, Value>,
+    },
+}
+
+// Saves the globals to `path`. Returns the names of those that couldn't
+// be, in order.
+pub fn save(interpreter: &Interpreter, path: &str) -> Result<Vec<String>, String> {
+    let mut globals: BTreeMap<String, Value> = BTreeMap::new();
+    let mut skipped: Vec<String> = vec![];
+
+    for (name, value) in interpreter.globals.borrow().bindings() {
+        let name: String = name.as_str().to_string();
+        if SKIPPED.contains(&name.as_str())
+            || matches!(value, Object::Callable(_) | Object::Class(_))
+        {
+            continue;
+        }
+        match to_value(&value, &mut vec![]) {
+            Some(value) => {
+                globals.insert(name, value);
+            }
+            None => skipped.push(name),
+        }
+    }
+
+    let state = State {
+        version: VERSION,
+        globals,
+    };
+    let json: String = serde_json::to_string_pretty(&state).map_err(|error| error.to_string())?;
+    fs::write(path, json).map_err(|error| format!("Can't write '{}': {}.", path, error))?;
+
+    Ok(skipped)
+}
+
+// Defines the globals saved in `path`, replacing those with the same names
+pub fn load(interpreter: &mut Interpreter, path: &str) -> Result<(), String> {
+    let json: String =
+        fs::read_to_string(path).map_err(|error| format!("Can't read '{}': {}.", path, error))?;
+    let state: State = serde_json::from_str(&json)
+        .map_err(|error| format!("'{}' isn't a saved state: {}.", path, error))?;
+    if state.version != VERSION {
+        return Err(format!(
+            "'{}' was saved in version {} of the format, expect {}.",
+            path, state.version, VERSION
+        ));
+    }
+
+    // Converted first, so a missing class doesn't leave half of them loaded
+    let mut globals: Vec<(Symbol, Object)> = vec![];
+    for (name, value) in state.globals {
+        let value: Object = from_value(interpreter, value)
+            .map_err(|class| format!("Can't load '{}': no class '{}'.", name, class))?;
+        globals.push((Symbol::intern(&name), value));
+    }
+    for (name, value) in globals {
+        interpreter.globals.borrow_mut().define(name, value);
+    }
+
+    Ok(())
+}
+
+// `None` if `object` (or anything in it) can't be saved. `containers` are
+// the lists, maps and instances it's inside of, to catch cycles.
+fn to_value(object: &Object, containers: &mut Vec<usize>) -> Option<Value> {
+    let address: Option<usize> = match object {
+        Object::List(list) => Some(Rc::as_ptr(list) as usize),
+        Object::Map(map) => Some(Rc::as_ptr(map) as usize),
+        Object::Instance(instance) => Some(Rc::as_ptr(instance) as usize),
+        _ => None,
+    };
+    if let Some(address) = address {
+        if containers.contains(&address) {
+            return None;
+        }
+        containers.push(address);
+    }
+
+    let value: Option<Value> = match object {
+        Object::None => Some(Value::Nil),
+        Object::Boolean(value) => Some(Value::Boolean(*value)),
+        Object::Number(value) if value.is_finite() => Some(Value::Number(*value)),
+        Object::String(value) => Some(Value::String(value.to_string())),
+        Object::Bytes(value) => Some(Value::Bytes(value.to_vec())),
+        Object::List(list) => list
+            .borrow()
+            .iter()
+            .map(|element| to_value(element, containers))
+            .collect::<Option<_>>()
+            .map(Value::List),
+        Object::Map(map) => map
+            .borrow()
+            .iter()
+            .map(|(key, value)| {
+                let key: Value = to_value(&from_key(key), containers)?;
+                Some((key, to_value(value, containers)?))
+            })
+            .collect::<Option<_>>()
+            .map(Value::Map),
+        Object::Instance(instance) => {
+            let instance = instance.borrow();
+            let fields: Option<BTreeMap<String, Value>> = instance
+                .fields()
+                .iter()
+                .map(|(name, value)| {
+                    Some((name.as_str().to_string(), to_value(value, containers)?))
+                })
+                .collect();
+            fields.map(|fields| Value::Instance {
+                class: instance.class().borrow().name.clone(),
+                fields,
+            })
+        }
+        Object::Number(_) | Object::Callable(_) | Object::Class(_) | Object::Foreign(_) => None,
+    };
+
+    if address.is_some() {
+        containers.pop();
+    }
+    value
+}
+
+// Fails with the name of an instance's class that isn't defined
+fn from_value(interpreter: &Interpreter, value: Value) -> Result<Object, String> {
+    Ok(match value {
+        Value::Nil => Object::None,
+        Value::Boolean(value) => Object::Boolean(value),
+        Value::Number(value) => Object::Number(value),
+        Value::String(value) => Object::String(value.into()),
+        Value::Bytes(value) => Object::Bytes(value.into()),
+        Value::List(elements) => native::new_list(
+            elements
+                .into_iter()
+                .map(|element| from_value(interpreter, element))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Map(entries) => {
+            let mut map: IndexMap<Literal, Object> = IndexMap::new();
+            for (key, value) in entries {
+                // Keys were saved from keys, they're valid ones
+                let key: Literal = to_key(&from_value(interpreter, key)?).unwrap();
+                map.insert(key, from_value(interpreter, value)?);
+            }
+            native::new_map(map)
+        }
+        Value::Instance { class, fields } => {
+            let Some(Object::Class(class_obj)) =
+                Symbol::find(&class).and_then(|class| interpreter.globals.borrow().lookup(class))
+            else {
+                return Err(class);
+            };
+            let instance = LoxInstance::new(class_obj);
+            for (name, value) in fields {
+                let value: Object = from_value(interpreter, value)?;
+                instance.borrow_mut().define(Symbol::intern(&name), value);
+            }
+            Object::Instance(instance)
+        }
+    })
+}
@@ -1,42 +1,146 @@
-use crate::{expr::Expr, token::Token};
+use crate::{
+    expr::Expr,
+    token::{Span, Token},
+};
 
-#[derive(Debug, Clone)]
+// `While` carries three expressions, well above the other variants. There
+// are few statements next to expressions, not worth boxing them for.
+#[allow(clippy::large_enum_variant)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
 pub enum Stmt {
     Block {
         statements: Vec<Option<Box<Stmt>>>,
+        #[serde(default)]
+        span: Span,
+    },
+    Break {
+        keyword: Token,
+        #[serde(default)]
+        span: Span,
     },
     Class {
         name: Token,
         superclass: Option<Expr>,
         methods: Vec<Box<Stmt>>,
+        #[serde(default)]
+        span: Span,
+    },
+    Continue {
+        keyword: Token,
+        #[serde(default)]
+        span: Span,
     },
     Expression {
         expression: Expr,
+        #[serde(default)]
+        span: Span,
     },
     Function {
         name: Token,
         params: Vec<Token>,
         body: Vec<Option<Box<Stmt>>>,
+        #[serde(default)]
+        span: Span,
     },
     If {
+        keyword: Token,
         condition: Expr,
         then_branch: Box<Stmt>,
         // TODO: Convert to Option<Box<Stmt>>
         else_branch: Box<Option<Stmt>>,
+        #[serde(default)]
+        span: Span,
     },
     Print {
+        // `print` or `eprint`
+        keyword: Token,
         expression: Expr,
+        // `eprint`, diagnostics that stay out of the script's output
+        stderr: bool,
+        #[serde(default)]
+        span: Span,
     },
     Return {
         keyword: Token,
         value: Option<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     Var {
         name: Token,
         initializer: Option<Expr>,
+        #[serde(default)]
+        span: Span,
     },
     While {
+        // `while`, or the `for` or `loop` desugared into it
+        keyword: Token,
         condition: Expr,
         body: Box<Stmt>,
+        // Only set by desugared `for` loops. Evaluated after every iteration,
+        // including the ones cut short by `continue`.
+        increment: Option<Expr>,
+        #[serde(default)]
+        span: Span,
     },
 }
+
+impl Stmt {
+    // Where the statement is in the source, see `Span`
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Block { span, .. }
+            | Stmt::Break { span, .. }
+            | Stmt::Class { span, .. }
+            | Stmt::Continue { span, .. }
+            | Stmt::Expression { span, .. }
+            | Stmt::Function { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Var { span, .. }
+            | Stmt::While { span, .. } => *span,
+        }
+    }
+
+    // The token a statement is located at, `None` for blocks and for
+    // expression statements that have none (e.g. `1;`)
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Block { .. } => None,
+            Stmt::Expression { expression, .. } => expression.token(),
+            Stmt::Break { keyword, .. }
+            | Stmt::Continue { keyword, .. }
+            | Stmt::If { keyword, .. }
+            | Stmt::Print { keyword, .. }
+            | Stmt::Return { keyword, .. }
+            | Stmt::While { keyword, .. } => Some(keyword),
+            Stmt::Class { name, .. } | Stmt::Function { name, .. } | Stmt::Var { name, .. } => {
+                Some(name)
+            }
+        }
+    }
+
+    // The `return`, `break` or `continue` this statement always ends with, if
+    // any. Whatever follows it in the same block can never run.
+    pub fn exit(&self) -> Option<&Token> {
+        match self {
+            Stmt::Return { keyword, .. }
+            | Stmt::Break { keyword, .. }
+            | Stmt::Continue { keyword, .. } => Some(keyword),
+            Stmt::Block { statements, .. } => {
+                statements.iter().flatten().find_map(|stmt| stmt.exit())
+            }
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => match &**else_branch {
+                Some(else_stmt) => then_branch.exit().and(else_stmt.exit()),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
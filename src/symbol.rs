@@ -0,0 +1,110 @@
+use std::{collections::HashMap, fmt};
+
+use crate::sync::Rc;
+
+// An interned identifier. Environments, fields and methods are keyed by
+// symbols, so lookups hash and compare a `u32` instead of a whole string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Names the interpreter itself looks up, interned up front in this order
+const PREDEFINED: [&str; 4] = ["", "this", "super", "init"];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+    pub const INIT: Symbol = Symbol(3);
+
+    pub fn intern(name: &str) -> Symbol {
+        with_interner(|interner| interner.intern(name))
+    }
+
+    // The symbol of `name` if it was interned, without interning it
+    pub fn find(name: &str) -> Option<Symbol> {
+        with_interner(|interner| interner.symbols.get(name).copied())
+    }
+
+    pub fn as_str(&self) -> Rc<str> {
+        with_interner(|interner| interner.names[self.0 as usize].clone())
+    }
+}
+
+// Serialized as its name, which is interned again when read back
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name: String = serde::Deserialize::deserialize(deserializer)?;
+        Ok(Symbol::intern(&name))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Symbols are never freed, the set of identifiers in a program is small.
+// Only names are interned (identifiers, and the globals and fields a saved
+// state defines), never string values.
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Interner {
+            symbols: HashMap::new(),
+            names: vec![],
+        };
+
+        for name in PREDEFINED {
+            interner.intern(name);
+        }
+
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+}
+
+#[cfg(not(feature = "sync"))]
+thread_local! {
+    static INTERNER: std::cell::RefCell<Interner> = std::cell::RefCell::new(Interner::new());
+}
+
+#[cfg(not(feature = "sync"))]
+fn with_interner<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
+    INTERNER.with(|interner| f(&mut interner.borrow_mut()))
+}
+
+// Symbols have to mean the same on every thread an interpreter moves to.
+// One lock for the whole process: interpreters running on several threads at
+// once wait on each other whenever they intern a name or look one up, e.g.
+// while scanning a script, when the host calls a function by name, or when
+// an error suggests a name.
+#[cfg(feature = "sync")]
+static INTERNER: std::sync::LazyLock<std::sync::Mutex<Interner>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(Interner::new()));
+
+#[cfg(feature = "sync")]
+fn with_interner<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
+    f(&mut INTERNER.lock().unwrap())
+}
@@ -0,0 +1,64 @@
+// The shared, mutable handles the interpreter is built from. They're the
+// single-threaded `Rc` and `RefCell` by default. The `sync` feature swaps
+// in `Arc` and an `RwLock` with `RefCell`'s API instead, which makes `Lox`
+// `Send` so it can be moved to, and driven from, another thread.
+
+#[cfg(not(feature = "sync"))]
+pub use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+#[cfg(feature = "sync")]
+pub use std::sync::{Arc as Rc, Weak};
+
+#[cfg(feature = "sync")]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+// `Send + Sync` with the `sync` feature, nothing without. A bound for the
+// closures the interpreter holds on to, e.g. `NativeFn`.
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> MaybeSend for T {}
+
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+// Like `RefCell`, a conflicting borrow is a bug in the interpreter, and
+// panics with `RefCell`'s messages rather than deadlocking on the lock this
+// thread already holds. So does a value locked by another thread at the
+// same time, e.g. a list shared by interpreters running on two threads:
+// borrows never wait.
+#[cfg(feature = "sync")]
+#[derive(Debug, Default)]
+pub struct RefCell<T: ?Sized>(RwLock<T>);
+
+#[cfg(feature = "sync")]
+impl<T> RefCell<T> {
+    pub fn new(value: T) -> Self {
+        RefCell(RwLock::new(value))
+    }
+}
+
+// Unsized for `foreign`'s values
+#[cfg(feature = "sync")]
+impl<T: ?Sized> RefCell<T> {
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        match self.0.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => panic!("already mutably borrowed: BorrowError"),
+            Err(TryLockError::Poisoned(error)) => panic!("{}", error),
+        }
+    }
+
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+        match self.0.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => panic!("already borrowed: BorrowMutError"),
+            Err(TryLockError::Poisoned(error)) => panic!("{}", error),
+        }
+    }
+}
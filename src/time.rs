@@ -0,0 +1,9 @@
+// The clock behind budgets, timings and `clock()`. The standard library's
+// panics on `wasm32-unknown-unknown`, where `web-time` reads the browser's
+// (`performance.now()` and `Date.now()`) instead.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
@@ -0,0 +1,45 @@
+use std::{fmt::Write, time::Duration};
+
+// How long each phase took, summed over every script or REPL line compiled
+// and run since `--time` turned it on, so the prelude isn't counted
+#[derive(Default)]
+pub struct Timings {
+    pub scan: Duration,
+    pub parse: Duration,
+    // Zero unless `--opt` is on
+    pub optimize: Duration,
+    pub resolve: Duration,
+    pub interpret: Duration,
+    // Most statements a single script or REPL line ran, see `Budget`
+    pub peak_statements: u64,
+}
+
+impl Timings {
+    pub fn report(&self) -> String {
+        let phases: [(&str, Duration); 5] = [
+            ("scan", self.scan),
+            ("parse", self.parse),
+            ("optimize", self.optimize),
+            ("resolve", self.resolve),
+            ("interpret", self.interpret),
+        ];
+        let total: Duration = phases.iter().map(|(_, time)| *time).sum();
+
+        let mut res: String = format!("{:>10} {:>12}\n", "phase", "time (ms)");
+        for (phase, time) in phases {
+            if phase == "optimize" && time.is_zero() {
+                continue;
+            }
+            let _ = writeln!(res, "{:>10} {:>12.3}", phase, time.as_secs_f64() * 1000.0);
+        }
+        let _ = writeln!(
+            res,
+            "{:>10} {:>12.3}",
+            "total",
+            total.as_secs_f64() * 1000.0
+        );
+        let _ = writeln!(res, "peak statements: {}", self.peak_statements);
+
+        res
+    }
+}
@@ -1,12 +1,19 @@
-use std::{fmt, hash::Hash};
+use std::{fmt, hash::Hash, ops::Range};
 
-#[derive(strum_macros::Display, Eq, PartialEq, Clone, Debug, Hash)]
+use crate::{symbol::Symbol, sync::Rc};
+
+#[derive(
+    strum_macros::Display, serde::Serialize, serde::Deserialize, Eq, PartialEq, Clone, Debug, Hash,
+)]
 pub enum TokenType {
     // Single-character tokens
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -26,15 +33,20 @@ pub enum TokenType {
     // Literals
     Identifier,
     String,
+    Bytes,
     Number,
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
+    Eprint,
     False,
     Fn,
     For,
     If,
+    Loop,
     Nil,
     Or,
     Print,
@@ -48,9 +60,11 @@ pub enum TokenType {
     Eof,
 }
 
-#[derive(strum_macros::Display, Clone, Debug, PartialEq)]
+#[derive(strum_macros::Display, serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "value")]
 pub enum Literal {
-    String(String),
+    String(Rc<str>),
+    Bytes(Vec<u8>),
     Number(f64),
     Boolean(bool),
     None,
@@ -59,6 +73,7 @@ impl Hash for Literal {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
             Literal::String(val) => val.hash(state),
+            Literal::Bytes(val) => val.hash(state),
             Literal::Number(val) => val.to_bits().hash(state),
             Literal::Boolean(val) => val.hash(state),
             Literal::None => 0u64.hash(state),
@@ -67,31 +82,102 @@ impl Hash for Literal {
 }
 impl Eq for Literal {}
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    // The interned lexeme of identifiers (and `this`, `super`), empty for
+    // any other token
+    pub symbol: Symbol,
     pub literal: Literal,
     pub line: usize,
+    // Starting at 1, counted in characters
+    pub column: usize,
+    // Where the token starts, in bytes, counted across all sources run by the same
+    // `Lox`, so no two tokens share an offset
+    pub offset: usize,
+    // The `///` comment right before the token, see `Scanner::doc`. Kept on
+    // the names of functions and classes by the parser.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<Rc<str>>,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, literal: Literal, line: usize) -> Self {
+    pub fn new(
+        token_type: TokenType,
+        lexeme: String,
+        literal: Literal,
+        line: usize,
+        column: usize,
+        offset: usize,
+    ) -> Self {
+        let symbol: Symbol = match token_type {
+            TokenType::Identifier | TokenType::This | TokenType::Super => Symbol::intern(&lexeme),
+            _ => Symbol::EMPTY,
+        };
+
         Self {
             token_type,
             lexeme,
+            symbol,
             literal,
             line,
+            column,
+            offset,
+            doc: None,
+        }
+    }
+
+    // The offsets the token covers, e.g. to highlight it
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.lexeme.len()
+    }
+}
+
+// The source an `Expr` or `Stmt` was parsed from: the bytes from its first
+// token to its last, in the same offsets as `Token::offset`, and where its
+// first token is. Nodes the parser makes up (e.g. the `true` of `loop`)
+// get the span of the code they stand for.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq, Hash,
+)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    // Starting at 1, counted in characters
+    pub column: usize,
+}
+
+impl Span {
+    pub fn of(token: &Token) -> Self {
+        Span {
+            start: token.offset,
+            end: token.offset + token.lexeme.len(),
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    // From the start of `self` to the end of `last`
+    pub fn to(self, last: Span) -> Self {
+        Span {
+            end: last.end.max(self.start),
+            ..self
         }
     }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Token( type: {}, lexeme: \"{}\", literal: \"{}\", line: {} )",
-            self.token_type, self.lexeme, self.literal, self.line
+            "Token( type: {}, lexeme: \"{}\", literal: \"{}\", line: {}, column: {} )",
+            self.token_type, self.lexeme, self.literal, self.line, self.column
         )
     }
 }
@@ -1,6 +1,8 @@
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
+use crate::time::{SystemTime, UNIX_EPOCH};
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Number {
     value: f64,
@@ -25,3 +27,182 @@ impl fmt::Display for Number {
         write!(f, "{}", self.value)
     }
 }
+
+// Small deterministic PRNG (SplitMix64) behind the `random*` natives.
+// Seeding it with `randomSeed(n)` makes script runs reproducible.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1), using the top 53 bits
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [0, n)
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        let nanos: u128 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        Rng::new(nanos as u64)
+    }
+}
+
+// FNV-1a, used by the `hash()` native. Unlike std's DefaultHasher its output
+// is fixed, so hashes can be persisted (e.g. for content-addressed caches).
+pub struct Fnv {
+    state: u64,
+}
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv {
+            state: 0xcbf29ce484222325,
+        }
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+// How numbers are printed: the fewest digits that read back as the same
+// double, so integers have no fraction. Past 1e21 or below 1e-6 they're in
+// scientific notation, where JavaScript switches too, e.g. `1e21`, `1.5e-7`.
+pub fn format_number(val: f64) -> String {
+    match val {
+        _ if val.is_nan() => "nan".to_owned(),
+        f64::INFINITY => "inf".to_owned(),
+        f64::NEG_INFINITY => "-inf".to_owned(),
+        _ if val != 0.0 && !(1e-6..1e21).contains(&val.abs()) => format!("{:e}", val),
+        _ => val.to_string(),
+    }
+}
+
+// `digits` decimals, without ever switching to scientific notation. Rounded
+// like JavaScript's `toFixed`: by the exact value of the double, so 2.675
+// (really 2.67499...) gives "2.67", with exact halves away from zero, so
+// 2.5 gives "3" where Rust's formatting would round to even.
+pub fn format_fixed(val: f64, digits: usize) -> String {
+    if !val.is_finite() {
+        return format_number(val);
+    }
+
+    let half: bool =
+        format!("{:.*}", digits + 1, val).ends_with('5') && has_decimals(val, digits + 1);
+    match half {
+        // Any bigger magnitude rounds away from zero
+        true => format!("{:.*}", digits, val.abs().next_up().copysign(val)),
+        false => format!("{:.*}", digits, val),
+    }
+}
+
+// Whether `val` is exactly written with `decimals` decimals or fewer, i.e.
+// its mantissa times 2^exponent times 10^decimals is an integer
+fn has_decimals(val: f64, decimals: usize) -> bool {
+    let bits: u64 = val.to_bits();
+    let (mantissa, exponent): (u64, i64) = match (bits >> 52) & 0x7ff {
+        0 => (bits & ((1 << 52) - 1), -1074),
+        biased => ((bits & ((1 << 52) - 1)) | (1 << 52), biased as i64 - 1075),
+    };
+
+    // 10^decimals brings as many factors of 2, the 5s can't cancel any
+    mantissa == 0 || mantissa.trailing_zeros() as i64 + exponent + decimals as i64 >= 0
+}
+
+// How bytes are printed: a `b"..."` literal that would scan back to them
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut res = String::from("b\"");
+
+    for byte in bytes {
+        match byte {
+            b'\n' => res.push_str("\\n"),
+            b'\r' => res.push_str("\\r"),
+            b'\t' => res.push_str("\\t"),
+            b'\\' => res.push_str("\\\\"),
+            b'"' => res.push_str("\\\""),
+            b' '..=b'~' => res.push(*byte as char),
+            _ => res.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+
+    res.push('"');
+    res
+}
+
+// The candidate closest to `name` by edit distance, if it's close enough to
+// be a typo of it: within a third of the name's length, and at least 1
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max: usize = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance in characters, where swapping two neighbours (`widht`)
+// also counts as a single edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance from the first i characters of `a` to the first
+    // j of `b`
+    let mut d: Vec<Vec<usize>> = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost: usize = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
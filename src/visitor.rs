@@ -0,0 +1,180 @@
+use crate::{
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    token::{Literal, Token},
+};
+
+// A pass over expressions, e.g. the interpreter (`T` is the value) or the
+// resolver (`T` is `()`). `Expr::accept` calls the method of the expression's
+// variant with its fields, the pass decides whether and how to recurse into
+// the subexpressions (usually with `accept` again).
+//
+// A new pass implements both traits instead of adding an arm to every
+// `match` over the syntax tree, see `ast::AstPrinter` for a small one.
+pub trait ExprVisitor<T> {
+    fn visit_assign(&mut self, id: ExprId, name: &Token, value: &Expr) -> T;
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> T;
+    fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Box<Expr>]) -> T;
+    fn visit_get(&mut self, id: ExprId, object: &Expr, name: &Token) -> T;
+    fn visit_grouping(&mut self, expression: &Expr) -> T;
+    fn visit_index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> T;
+    fn visit_index_set(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> T;
+    fn visit_list(&mut self, bracket: &Token, elements: &[Box<Expr>]) -> T;
+    fn visit_literal(&mut self, value: &Literal) -> T;
+    fn visit_map(&mut self, brace: &Token, entries: &[(Box<Expr>, Box<Expr>)]) -> T;
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> T;
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> T;
+    fn visit_super(&mut self, id: ExprId, keyword: &Token, method: &Token) -> T;
+    fn visit_this(&mut self, id: ExprId, keyword: &Token) -> T;
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> T;
+    fn visit_variable(&mut self, id: ExprId, name: &Token) -> T;
+}
+
+// The same for statements. Optional parts (e.g. an `else` branch) are `None`
+// when they're missing.
+pub trait StmtVisitor<T> {
+    fn visit_block(&mut self, statements: &[Option<Box<Stmt>>]) -> T;
+    fn visit_break(&mut self, keyword: &Token) -> T;
+    // `methods` are all `Stmt::Function`s
+    fn visit_class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Box<Stmt>]) -> T;
+    fn visit_continue(&mut self, keyword: &Token) -> T;
+    fn visit_expression(&mut self, expression: &Expr) -> T;
+    fn visit_function(&mut self, name: &Token, params: &[Token], body: &[Option<Box<Stmt>>]) -> T;
+    fn visit_if(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> T;
+    fn visit_print(&mut self, keyword: &Token, expression: &Expr, stderr: bool) -> T;
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expr>) -> T;
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> T;
+    fn visit_while(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> T;
+}
+
+impl Expr {
+    // Inlined, so evaluating an expression costs one call like it did before
+    // the passes were split into methods
+    #[inline(always)]
+    pub fn accept<T, V: ExprVisitor<T> + ?Sized>(&self, visitor: &mut V) -> T {
+        match self {
+            Expr::Assign {
+                id, name, value, ..
+            } => visitor.visit_assign(*id, name, value),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => visitor.visit_binary(left, operator, right),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                ..
+            } => visitor.visit_call(callee, paren, arguments),
+            Expr::Get {
+                id, object, name, ..
+            } => visitor.visit_get(*id, object, name),
+            Expr::Grouping { expression, .. } => visitor.visit_grouping(expression),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+                ..
+            } => visitor.visit_index(object, bracket, index),
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                value,
+                ..
+            } => visitor.visit_index_set(object, bracket, index, value),
+            Expr::List {
+                bracket, elements, ..
+            } => visitor.visit_list(bracket, elements),
+            Expr::Literal { value, .. } => visitor.visit_literal(value),
+            Expr::Map { brace, entries, .. } => visitor.visit_map(brace, entries),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => visitor.visit_logical(left, operator, right),
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => visitor.visit_set(object, name, value),
+            Expr::Super {
+                id,
+                keyword,
+                method,
+                ..
+            } => visitor.visit_super(*id, keyword, method),
+            Expr::This { id, keyword, .. } => visitor.visit_this(*id, keyword),
+            Expr::Unary {
+                operator, right, ..
+            } => visitor.visit_unary(operator, right),
+            Expr::Variable { id, name, .. } => visitor.visit_variable(*id, name),
+        }
+    }
+}
+
+impl Stmt {
+    #[inline(always)]
+    pub fn accept<T, V: StmtVisitor<T> + ?Sized>(&self, visitor: &mut V) -> T {
+        match self {
+            Stmt::Block { statements, .. } => visitor.visit_block(statements),
+            Stmt::Break { keyword, .. } => visitor.visit_break(keyword),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => visitor.visit_class(name, superclass.as_ref(), methods),
+            Stmt::Continue { keyword, .. } => visitor.visit_continue(keyword),
+            Stmt::Expression { expression, .. } => visitor.visit_expression(expression),
+            Stmt::Function {
+                name, params, body, ..
+            } => visitor.visit_function(name, params, body),
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => visitor.visit_if(
+                keyword,
+                condition,
+                then_branch,
+                else_branch.as_ref().as_ref(),
+            ),
+            Stmt::Print {
+                keyword,
+                expression,
+                stderr,
+                ..
+            } => visitor.visit_print(keyword, expression, *stderr),
+            Stmt::Return { keyword, value, .. } => visitor.visit_return(keyword, value.as_ref()),
+            Stmt::Var {
+                name, initializer, ..
+            } => visitor.visit_var(name, initializer.as_ref()),
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+                increment,
+                ..
+            } => visitor.visit_while(keyword, condition, body, increment.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// The bindings behind the browser playground, built with
+// `wasm-pack build --target web`. `run` executes a program in a fresh
+// interpreter, with the prelude, and hands back what it printed along with
+// its errors:
+//
+//     import init, { run } from "./pkg/rustlox.js";
+//     await init();
+//     const { output, diagnostics } = run('print "Hi";');
+//
+// There's no terminal, filesystem or process in a browser: `readLine()`
+// gives nil, and the natives that need one fail like any runtime error.
+
+use std::io::{self, Write};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    lox::Lox,
+    sync::{Rc, RefCell},
+};
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct RunResult {
+    // What `print` and `eprint` wrote, in order
+    pub output: String,
+    // The compile errors, or the runtime error, as the terminal shows them
+    pub diagnostics: Vec<String>,
+}
+
+#[wasm_bindgen]
+pub fn run(source: &str) -> RunResult {
+    let output = Buffer::default();
+    let mut lox: Lox = Lox::new();
+    lox.set_stdout(output.clone());
+    lox.set_stderr(output.clone());
+    lox.set_stdin(io::empty());
+    lox.run_prelude();
+
+    let diagnostics: Vec<String> = match lox.run_source(source) {
+        Ok(_) => vec![],
+        Err(diagnostics) => diagnostics.iter().map(|d| lox.render(d)).collect(),
+    };
+    let output: String = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+
+    RunResult {
+        output,
+        diagnostics,
+    }
+}
+
+// Collects the output, the interpreter holds one handle and `run` another
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+// `--opt` mustn't change what a program does: every playground script
+// prints the same, fails the same way and exits with the same code with
+// the optimizer as without it.
+
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn run(options: &[&str], script: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lox"))
+        // In case a script never ends
+        .args(["--timeout", "10000"])
+        .args(options)
+        .arg(script)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn playground_runs_the_same_optimized() {
+    let mut scripts: Vec<PathBuf> = fs::read_dir("playground")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty());
+
+    for script in &scripts {
+        let plain: Output = run(&[], script);
+        let optimized: Output = run(&["--opt"], script);
+
+        let name = script.display();
+        assert_eq!(
+            String::from_utf8_lossy(&plain.stdout),
+            String::from_utf8_lossy(&optimized.stdout),
+            "stdout of {}",
+            name
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&plain.stderr),
+            String::from_utf8_lossy(&optimized.stderr),
+            "stderr of {}",
+            name
+        );
+        assert_eq!(
+            plain.status.code(),
+            optimized.status.code(),
+            "exit code of {}",
+            name
+        );
+    }
+}
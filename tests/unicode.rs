@@ -0,0 +1,48 @@
+// Sources with multi-byte UTF-8: strings keep every character, and errors
+// point at the column of the character (not the byte) they come from, with
+// and without `--opt`.
+
+use std::process::{Command, Output, Stdio};
+
+fn run(options: &[&str], arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(options)
+        .args(arguments)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+// What `arguments` prints to stdout and its exit code, the same optimized
+fn check(arguments: &[&str]) -> (String, Option<i32>) {
+    let plain: Output = run(&[], arguments);
+    let optimized: Output = run(&["--opt"], arguments);
+    assert_eq!(plain.stdout, optimized.stdout);
+    assert_eq!(plain.stderr, optimized.stderr);
+    assert_eq!(plain.status.code(), optimized.status.code());
+
+    (
+        String::from_utf8(plain.stdout).unwrap(),
+        plain.status.code(),
+    )
+}
+
+#[test]
+fn strings_and_runtime_error_columns() {
+    let (stdout, code) = check(&["playground/unicode.lox"]);
+
+    assert!(stdout.starts_with("héllo, wörld 👋\n14\n[26085, 26412, 35486]\n"));
+    assert!(stdout.contains("[line 8, col 23]"));
+    assert!(stdout.contains("8 | print \"→ \" + greeting - 1;\n  |                       ^\n"));
+    assert_eq!(code, Some(70));
+}
+
+#[test]
+fn non_ascii_identifier_columns() {
+    let (stdout, code) = check(&["-e", "var café = \"naïve\"; print \"日本\" + café;"]);
+
+    // Identifiers are ASCII, each `é` is reported where it is
+    assert!(stdout.contains("[line 1, col 8] Error: Unexpected character."));
+    assert!(stdout.contains("[line 1, col 37] Error: Unexpected character."));
+    assert_eq!(code, Some(65));
+}